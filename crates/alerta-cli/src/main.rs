@@ -1,6 +1,6 @@
-use std::process;
+use std::{fs, path::PathBuf, process};
 
-use alerta::{Answer, ButtonPreset, Error, Icon, Theme};
+use alerta::{Answer, ButtonPreset, Error, Highlight, Icon, Palette, Theme};
 use larpa::{
     Command,
     types::{Color, PrintVersion},
@@ -17,6 +17,9 @@ use larpa::{
 ///   Alt+F4 or ESC).
 /// - 100: An error occurred while displaying the dialog.
 /// - 101: A panic occurred (this is a bug in Alerta, please file an issue).
+///
+/// With `--render-png`, the dialog is written to an image instead of being displayed, and the exit
+/// status is 0 on success.
 #[derive(Command)]
 struct Args {
     /// The message to display in the dialog.
@@ -26,6 +29,14 @@ struct Args {
     #[larpa(name = "--title")]
     title: Option<String>,
 
+    /// Additional details (like a backtrace), shown when expanding the "Show details" section.
+    #[larpa(name = "--details")]
+    details: Option<String>,
+
+    /// A file to read the details from (alternative to `--details`).
+    #[larpa(name = "--details-file")]
+    details_file: Option<PathBuf>,
+
     /// The icon to display next to the message. [choices: info, warning, error, question]
     #[larpa(name = "--icon", default = "info")]
     icon: Icon,
@@ -34,10 +45,82 @@ struct Args {
     #[larpa(name = "--buttons", default = "close")]
     buttons: ButtonPreset,
 
-    /// The theme to use. [choices: light, dark]
+    /// The theme to use. Follows the desktop's preference by default. [choices: system, light, dark]
     #[larpa(name = "--theme")]
     theme: Option<Theme>,
 
+    /// Custom colors, as comma-separated `name=#rrggbb` entries (see `alerta::Palette`).
+    #[larpa(name = "--colors")]
+    colors: Option<String>,
+
+    /// A file to read custom colors from (alternative to `--colors`).
+    #[larpa(name = "--theme-file")]
+    theme_file: Option<PathBuf>,
+
+    /// The language of the buttons and default title, like `de` or `pt_BR.UTF-8`. Follows the
+    /// environment (`LC_ALL`, `LC_MESSAGES` and `LANG`) by default.
+    #[larpa(name = "--locale")]
+    locale: Option<String>,
+
+    /// Allow resizing the dialog window.
+    #[larpa(flag, name = "--resizable")]
+    resizable: bool,
+
+    /// Make the window manager draw attention to the dialog (the default with `--icon=error`).
+    #[larpa(flag, name = "--urgent")]
+    urgent: bool,
+
+    /// Don't make the window manager draw attention to the dialog.
+    #[larpa(flag, name = "--no-urgent")]
+    no_urgent: bool,
+
+    /// Ring the bell when the dialog opens (the default for urgent dialogs).
+    #[larpa(flag, name = "--bell")]
+    bell: bool,
+
+    /// Don't ring the bell when the dialog opens.
+    #[larpa(flag, name = "--no-bell")]
+    no_bell: bool,
+
+    /// The `WM_CLASS` of the dialog window, for matching window manager rules. Defaults to `alerta`.
+    ///
+    /// This is the instance name; the class name is the same with its first letter capitalized
+    /// (`--class myapp` sets `myapp`, `Myapp`). X resources are looked up under both.
+    #[larpa(name = "--class")]
+    class: Option<String>,
+
+    /// Keep the dialog above other windows.
+    #[larpa(flag, name = "--always-on-top")]
+    always_on_top: bool,
+
+    /// Show the dialog on every workspace.
+    #[larpa(flag, name = "--sticky")]
+    sticky: bool,
+
+    /// Hide the dialog from taskbars.
+    #[larpa(flag, name = "--skip-taskbar")]
+    skip_taskbar: bool,
+
+    /// Hide the dialog from pagers and workspace switchers.
+    #[larpa(flag, name = "--skip-pager")]
+    skip_pager: bool,
+
+    /// Round the dialog's corners and give it a shadow, if a compositor is running.
+    #[larpa(flag, name = "--rounded")]
+    rounded: bool,
+
+    /// Write the dialog to a PNG file instead of displaying it. Works without an X server.
+    #[larpa(name = "--render-png")]
+    render_png: Option<PathBuf>,
+
+    /// With `--render-png`, draw the button with this 0-based index as if the mouse was on it.
+    #[larpa(name = "--render-hover")]
+    render_hover: Option<usize>,
+
+    /// With `--render-png`, draw the button with this 0-based index as if it was being pressed.
+    #[larpa(name = "--render-pressed")]
+    render_pressed: Option<usize>,
+
     /// Whether to use ANSI colors in console output. [choices: always, auto, never]
     #[larpa(name = "--color", default)]
     _color: Color,
@@ -63,12 +146,108 @@ fn run() -> Result<i32, Error> {
     let mut b = alerta::alerta()
         .message(args.message)
         .icon(args.icon)
-        .button_preset(args.buttons);
+        .button_preset(args.buttons)
+        .resizable(args.resizable)
+        .always_on_top(args.always_on_top)
+        .sticky(args.sticky)
+        .skip_taskbar(args.skip_taskbar)
+        .skip_pager(args.skip_pager)
+        .rounded(args.rounded);
     if let Some(title) = args.title {
         b = b.title(title);
     }
-    if let Some(theme) = args.theme {
-        b = b.theme(theme);
+    if let Some(class) = args.class {
+        b = b.app_id(class);
+    }
+    if let Some(locale) = args.locale {
+        b = b.locale(&locale);
+    }
+    match (args.urgent, args.no_urgent) {
+        (true, true) => {
+            eprintln!("error: `--urgent` and `--no-urgent` cannot be used together");
+            return Ok(64);
+        }
+        (true, false) => b = b.urgent(true),
+        (false, true) => b = b.urgent(false),
+        (false, false) => {}
+    }
+    match (args.bell, args.no_bell) {
+        (true, true) => {
+            eprintln!("error: `--bell` and `--no-bell` cannot be used together");
+            return Ok(64);
+        }
+        (true, false) => b = b.bell(true),
+        (false, true) => b = b.bell(false),
+        (false, false) => {}
+    }
+    let colors = match (args.colors, args.theme_file) {
+        (Some(_), Some(_)) => {
+            eprintln!("error: `--colors` and `--theme-file` cannot be used together");
+            return Ok(64);
+        }
+        (Some(colors), None) => Some(colors),
+        (None, Some(path)) => match fs::read_to_string(&path) {
+            Ok(colors) => Some(colors),
+            Err(e) => {
+                eprintln!("error: failed to read '{}': {e}", path.display());
+                return Ok(64);
+            }
+        },
+        (None, None) => None,
+    };
+    match (args.theme, colors) {
+        (Some(_), Some(_)) => {
+            eprintln!(
+                "error: `--theme` cannot be combined with custom colors (use `base=dark` instead)"
+            );
+            return Ok(64);
+        }
+        (Some(theme), None) => b = b.theme(theme),
+        (None, Some(colors)) => match colors.parse::<Palette>() {
+            Ok(palette) => b = b.theme(Theme::Custom(palette)),
+            Err(e) => {
+                eprintln!("error: failed to parse colors: {e}");
+                return Ok(64);
+            }
+        },
+        (None, None) => {}
+    }
+    match (args.details, args.details_file) {
+        (Some(_), Some(_)) => {
+            eprintln!("error: `--details` and `--details-file` cannot be used together");
+            return Ok(64);
+        }
+        (Some(details), None) => b = b.details(details),
+        (None, Some(path)) => match fs::read_to_string(&path) {
+            Ok(details) => b = b.details(details),
+            Err(e) => {
+                eprintln!("error: failed to read '{}': {e}", path.display());
+                return Ok(64);
+            }
+        },
+        (None, None) => {}
+    }
+
+    let highlight = match (args.render_hover, args.render_pressed) {
+        (Some(_), Some(_)) => {
+            eprintln!("error: `--render-hover` and `--render-pressed` cannot be used together");
+            return Ok(64);
+        }
+        (Some(index), None) => Some(Highlight::Hovered(index)),
+        (None, Some(index)) => Some(Highlight::Pressed(index)),
+        (None, None) => None,
+    };
+    if let Some(path) = args.render_png {
+        let png = b.render_highlighted(highlight).to_png();
+        if let Err(e) = fs::write(&path, png) {
+            eprintln!("error: failed to write '{}': {e}", path.display());
+            return Ok(100);
+        }
+        return Ok(0);
+    }
+    if highlight.is_some() {
+        eprintln!("error: `--render-hover` and `--render-pressed` require `--render-png`");
+        return Ok(64);
     }
 
     let ans = b.show()?;
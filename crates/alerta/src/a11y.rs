@@ -0,0 +1,475 @@
+//! Screen reader support via AT-SPI.
+//!
+//! AT-SPI is the accessibility framework of Linux desktops: applications export a tree of
+//! accessible objects on a dedicated D-Bus bus (the accessibility bus), and assistive technologies
+//! like the Orca screen reader query that tree and listen for events about it.
+//!
+//! The tree alerta exports is small and fixed:
+//!
+//! - the application (the root, which gets embedded into the registry's desktop object)
+//!   - the dialog (role `ALERT`), named after the window title
+//!     - the message (role `LABEL`)
+//!     - one `PUSH_BUTTON` per button, with a `click` action
+//!
+//! The interfaces are specified at <https://gitlab.gnome.org/GNOME/at-spi2-core/-/tree/main/xml>.
+
+use std::{
+    env,
+    os::fd::{AsFd, BorrowedFd},
+    path::Path,
+};
+
+use crate::{
+    Error,
+    dbus::{self, Message, MessageType, Value},
+};
+
+const ROOT_PATH: &str = "/org/a11y/atspi/accessible/root";
+/// Path of the "null" object reference, returned when there is no such object.
+const NULL_PATH: &str = "/org/a11y/atspi/null";
+
+const ACCESSIBLE: &str = "org.a11y.atspi.Accessible";
+const APPLICATION: &str = "org.a11y.atspi.Application";
+const ACTION: &str = "org.a11y.atspi.Action";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+
+const UNKNOWN_METHOD: &str = "org.freedesktop.DBus.Error.UnknownMethod";
+const UNKNOWN_OBJECT: &str = "org.freedesktop.DBus.Error.UnknownObject";
+const UNKNOWN_PROPERTY: &str = "org.freedesktop.DBus.Error.UnknownProperty";
+const INVALID_ARGS: &str = "org.freedesktop.DBus.Error.InvalidArgs";
+
+// Values of `AtspiRole`.
+const ROLE_ALERT: u32 = 2;
+const ROLE_LABEL: u32 = 29;
+const ROLE_PUSH_BUTTON: u32 = 43;
+const ROLE_APPLICATION: u32 = 75;
+
+// Values of `AtspiStateType`, which are bit indices into the state set.
+const STATE_ACTIVE: u32 = 1;
+const STATE_ENABLED: u32 = 8;
+const STATE_FOCUSABLE: u32 = 11;
+const STATE_FOCUSED: u32 = 12;
+const STATE_MULTI_LINE: u32 = 17;
+const STATE_SENSITIVE: u32 = 24;
+const STATE_SHOWING: u32 = 25;
+const STATE_VISIBLE: u32 = 30;
+
+/// The accessible objects alerta exports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Object {
+    Application,
+    Dialog,
+    Message,
+    Button(usize),
+}
+
+impl Object {
+    fn path(self) -> String {
+        match self {
+            Object::Application => ROOT_PATH.into(),
+            Object::Dialog => "/org/a11y/atspi/accessible/dialog".into(),
+            Object::Message => "/org/a11y/atspi/accessible/message".into(),
+            Object::Button(i) => format!("/org/a11y/atspi/accessible/button{i}"),
+        }
+    }
+}
+
+/// The result of handling a method call: the body of the reply and the button to press, or the
+/// name and message of an error.
+type Handled = Result<(Vec<Value>, Option<usize>), (&'static str, String)>;
+
+/// Exports the dialog on the accessibility bus.
+pub(crate) struct Accessibility {
+    bus: dbus::Connection,
+    /// Reference to the registry's desktop object, which is the parent of our application object.
+    desktop: Value,
+    app_name: String,
+    title: String,
+    message: String,
+    buttons: Vec<String>,
+    /// The button that has keyboard focus, as last announced.
+    focus: Option<usize>,
+    /// The ID the registry assigned to the application.
+    id: i32,
+}
+
+impl Accessibility {
+    /// Connects to the accessibility bus and registers the dialog with the AT-SPI registry.
+    ///
+    /// Fails if the desktop doesn't run the AT-SPI services.
+    pub(crate) fn new(title: &str, message: &str, buttons: &[&str]) -> Result<Self, Error> {
+        // The accessibility bus is separate from the session bus, which knows its address.
+        let reply = dbus::Connection::session()?.call(Message::method_call(
+            "org.a11y.Bus",
+            "/org/a11y/bus",
+            "org.a11y.Bus",
+            "GetAddress",
+            Vec::new(),
+        ))?;
+        let address = reply
+            .body
+            .first()
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::new("invalid accessibility bus address"))?;
+        Self::register(dbus::Connection::connect(address)?, title, message, buttons)
+    }
+
+    pub(crate) fn register(
+        mut bus: dbus::Connection,
+        title: &str,
+        message: &str,
+        buttons: &[&str],
+    ) -> Result<Self, Error> {
+        let root = reference(bus.unique_name(), ROOT_PATH);
+        let reply = bus.call(Message::method_call(
+            "org.a11y.atspi.Registry",
+            ROOT_PATH,
+            "org.a11y.atspi.Socket",
+            "Embed",
+            vec![root],
+        ))?;
+        let desktop = reply
+            .body
+            .into_iter()
+            .next()
+            .filter(|desktop| desktop.signature() == "(so)")
+            .ok_or_else(|| Error::new("invalid reply to `Embed`"))?;
+
+        let app_name = env::args_os()
+            .next()
+            .and_then(|arg0| Some(Path::new(&arg0).file_name()?.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| "alerta".into());
+        Ok(Self {
+            bus,
+            desktop,
+            app_name,
+            title: title.into(),
+            message: message.into(),
+            buttons: buttons.iter().map(|label| label.to_string()).collect(),
+            focus: None,
+            id: 0,
+        })
+    }
+
+    /// Returns the file descriptor to wait on before calling [`Accessibility::process`].
+    pub(crate) fn fd(&self) -> BorrowedFd<'_> {
+        self.bus.as_fd()
+    }
+
+    /// Returns whether messages are waiting to be processed without `fd` becoming readable.
+    pub(crate) fn has_queued(&self) -> bool {
+        self.bus.has_queued()
+    }
+
+    /// Announces that the dialog window has been activated, with the button `focus` focused.
+    ///
+    /// This is what makes screen readers read out the dialog.
+    pub(crate) fn activate(&mut self, focus: usize) -> Result<(), Error> {
+        self.emit(
+            Object::Dialog,
+            "org.a11y.atspi.Event.Window",
+            "Activate",
+            ("", 0),
+            Value::String(self.title.clone()),
+        )?;
+        self.set_focus(focus)
+    }
+
+    /// Announces that keyboard focus moved to the button `focus`.
+    pub(crate) fn set_focus(&mut self, focus: usize) -> Result<(), Error> {
+        if self.focus == Some(focus) {
+            return Ok(());
+        }
+        if let Some(old) = self.focus.replace(focus) {
+            self.emit_state_change(Object::Button(old), "focused", false)?;
+        }
+        self.emit_state_change(Object::Button(focus), "focused", true)
+    }
+
+    fn emit_state_change(&mut self, object: Object, state: &str, value: bool) -> Result<(), Error> {
+        self.emit(
+            object,
+            "org.a11y.atspi.Event.Object",
+            "StateChanged",
+            (state, value.into()),
+            Value::I32(0),
+        )
+    }
+
+    fn emit(
+        &mut self,
+        object: Object,
+        interface: &str,
+        member: &str,
+        (detail, detail1): (&str, i32),
+        data: Value,
+    ) -> Result<(), Error> {
+        let body = vec![
+            Value::String(detail.into()),
+            Value::I32(detail1),
+            Value::I32(0),
+            Value::Variant(Box::new(data)),
+            Value::Array("{sv}".into(), Vec::new()),
+        ];
+        self.bus
+            .send(Message::signal(&object.path(), interface, member, body))?;
+        Ok(())
+    }
+
+    /// Reads an incoming message and answers it.
+    ///
+    /// Returns the index of a button if the message asked to press it.
+    pub(crate) fn process(&mut self) -> Result<Option<usize>, Error> {
+        let call = self.bus.receive()?;
+        if call.kind != MessageType::MethodCall {
+            return Ok(None);
+        }
+        let (reply, pressed) = match self.handle(&call) {
+            Ok((body, pressed)) => (Message::method_return(&call, body), pressed),
+            Err((name, text)) => (Message::error(&call, name, &text), None),
+        };
+        if call.flags & dbus::NO_REPLY_EXPECTED == 0 {
+            self.bus.send(reply)?;
+        }
+        Ok(pressed)
+    }
+
+    /// Handles a method call.
+    fn handle(&mut self, call: &Message) -> Handled {
+        let path = call.path.as_deref().unwrap_or_default();
+        let object = self
+            .objects()
+            .find(|object| object.path() == path)
+            .ok_or_else(|| (UNKNOWN_OBJECT, format!("no object at '{path}'")))?;
+        let member = call.member.as_deref().unwrap_or_default();
+        let interface = call.interface.as_deref().unwrap_or_default();
+        let index = || match call.body.first() {
+            Some(Value::I32(i)) => Ok(*i),
+            _ => Err((INVALID_ARGS, format!("`{member}` expects an index"))),
+        };
+        let unknown = || {
+            Err((
+                UNKNOWN_METHOD,
+                format!("unknown method `{interface}.{member}`"),
+            ))
+        };
+
+        let value = match (interface, member) {
+            (PROPERTIES, "Get") => {
+                let (Some(interface), Some(name)) = (
+                    call.body.first().and_then(Value::as_str),
+                    call.body.get(1).and_then(Value::as_str),
+                ) else {
+                    return Err((INVALID_ARGS, "expected interface and property".into()));
+                };
+                let value = self
+                    .property(object, interface, name)
+                    .ok_or_else(|| (UNKNOWN_PROPERTY, format!("unknown property `{name}`")))?;
+                Value::Variant(Box::new(value))
+            }
+            (PROPERTIES, "GetAll") => {
+                let interface = call
+                    .body
+                    .first()
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                let names: &[&str] = match interface {
+                    ACCESSIBLE => &[
+                        "Name",
+                        "Description",
+                        "Parent",
+                        "ChildCount",
+                        "Locale",
+                        "AccessibleId",
+                    ],
+                    APPLICATION => &["ToolkitName", "Version", "AtspiVersion", "Id"],
+                    ACTION => &["NActions"],
+                    _ => &[],
+                };
+                let entries = names
+                    .iter()
+                    .filter_map(|name| {
+                        let value = self.property(object, interface, name)?;
+                        Some(Value::DictEntry(
+                            Box::new(Value::String(name.to_string())),
+                            Box::new(Value::Variant(Box::new(value))),
+                        ))
+                    })
+                    .collect();
+                Value::Array("{sv}".into(), entries)
+            }
+            (PROPERTIES, "Set") => match &call.body[..] {
+                // The registry tells the application its ID this way.
+                [interface, name, value]
+                    if object == Object::Application
+                        && interface.as_str() == Some(APPLICATION)
+                        && name.as_str() == Some("Id") =>
+                {
+                    match value.unwrap_variant() {
+                        Value::I32(id) => self.id = *id,
+                        _ => return Err((INVALID_ARGS, "expected an integer".into())),
+                    }
+                    return Ok((Vec::new(), None));
+                }
+                _ => return Err((INVALID_ARGS, "property is read-only".into())),
+            },
+            (ACCESSIBLE, "GetChildAtIndex") => {
+                let child = usize::try_from(index()?)
+                    .ok()
+                    .and_then(|i| self.children(object).get(i).copied());
+                match child {
+                    Some(child) => self.reference(child),
+                    None => reference(self.bus.unique_name(), NULL_PATH),
+                }
+            }
+            (ACCESSIBLE, "GetChildren") => {
+                let children = self.children(object);
+                let children = children.iter().map(|child| self.reference(*child));
+                Value::Array("(so)".into(), children.collect())
+            }
+            (ACCESSIBLE, "GetIndexInParent") => Value::I32(match object {
+                Object::Application => -1,
+                Object::Dialog | Object::Message => 0,
+                Object::Button(i) => i as i32 + 1,
+            }),
+            (ACCESSIBLE, "GetRelationSet") => Value::Array("(ua(so))".into(), Vec::new()),
+            (ACCESSIBLE, "GetRole") => Value::U32(role(object).0),
+            (ACCESSIBLE, "GetRoleName" | "GetLocalizedRoleName") => {
+                Value::String(role(object).1.into())
+            }
+            (ACCESSIBLE, "GetState") => {
+                let states = self.states(object);
+                Value::Array(
+                    "u".into(),
+                    vec![Value::U32(states as u32), Value::U32((states >> 32) as u32)],
+                )
+            }
+            (ACCESSIBLE, "GetAttributes") => Value::Array("{ss}".into(), Vec::new()),
+            (ACCESSIBLE, "GetApplication") => self.reference(Object::Application),
+            (ACCESSIBLE, "GetInterfaces") => {
+                let interfaces = interfaces(object).iter();
+                let interfaces = interfaces.map(|iface| Value::String(iface.to_string()));
+                Value::Array("s".into(), interfaces.collect())
+            }
+            (APPLICATION, "GetLocale") if object == Object::Application => {
+                Value::String(String::new())
+            }
+            (ACTION, _) if !matches!(object, Object::Button(_)) => return unknown(),
+            (ACTION, "GetActions") => Value::Array(
+                "(sss)".into(),
+                vec![Value::Struct(vec![
+                    Value::String("click".into()),
+                    Value::String(String::new()),
+                    Value::String(String::new()),
+                ])],
+            ),
+            (ACTION, "GetName" | "GetLocalizedName" | "GetDescription" | "GetKeyBinding") => {
+                if index()? != 0 {
+                    return Err((INVALID_ARGS, "buttons only have one action".into()));
+                }
+                Value::String(
+                    if member.ends_with("Name") {
+                        "click"
+                    } else {
+                        ""
+                    }
+                    .into(),
+                )
+            }
+            (ACTION, "DoAction") => {
+                let Object::Button(button) = object else {
+                    unreachable!()
+                };
+                let valid = index()? == 0;
+                return Ok((vec![Value::Bool(valid)], valid.then_some(button)));
+            }
+            ("org.freedesktop.DBus.Peer", "Ping") => return Ok((Vec::new(), None)),
+            _ => return unknown(),
+        };
+        Ok((vec![value], None))
+    }
+
+    fn objects(&self) -> impl Iterator<Item = Object> {
+        [Object::Application, Object::Dialog, Object::Message]
+            .into_iter()
+            .chain((0..self.buttons.len()).map(Object::Button))
+    }
+
+    fn children(&self, object: Object) -> Vec<Object> {
+        match object {
+            Object::Application => vec![Object::Dialog],
+            Object::Dialog => self.objects().skip(2).collect(),
+            Object::Message | Object::Button(_) => Vec::new(),
+        }
+    }
+
+    fn reference(&self, object: Object) -> Value {
+        reference(self.bus.unique_name(), &object.path())
+    }
+
+    fn property(&self, object: Object, interface: &str, name: &str) -> Option<Value> {
+        Some(match (interface, name) {
+            (ACCESSIBLE, "Name") => Value::String(match object {
+                Object::Application => self.app_name.clone(),
+                Object::Dialog => self.title.clone(),
+                Object::Message => self.message.clone(),
+                Object::Button(i) => self.buttons[i].clone(),
+            }),
+            (ACCESSIBLE, "Description" | "Locale" | "AccessibleId") => Value::String(String::new()),
+            (ACCESSIBLE, "Parent") => match object {
+                Object::Application => self.desktop.clone(),
+                Object::Dialog => self.reference(Object::Application),
+                Object::Message | Object::Button(_) => self.reference(Object::Dialog),
+            },
+            (ACCESSIBLE, "ChildCount") => Value::I32(self.children(object).len() as i32),
+            (APPLICATION, _) if object != Object::Application => return None,
+            (APPLICATION, "ToolkitName") => Value::String("alerta".into()),
+            (APPLICATION, "Version") => Value::String(env!("CARGO_PKG_VERSION").into()),
+            (APPLICATION, "AtspiVersion") => Value::String("2.1".into()),
+            (APPLICATION, "Id") => Value::I32(self.id),
+            (ACTION, "NActions") if matches!(object, Object::Button(_)) => Value::I32(1),
+            _ => return None,
+        })
+    }
+
+    /// Returns the state set of `object`, as a bit set of `AtspiStateType` values.
+    fn states(&self, object: Object) -> u64 {
+        let states: &[u32] = match object {
+            Object::Application => return 0,
+            Object::Dialog => &[STATE_ACTIVE],
+            Object::Message => &[STATE_MULTI_LINE],
+            Object::Button(i) if self.focus == Some(i) => &[STATE_FOCUSABLE, STATE_FOCUSED],
+            Object::Button(_) => &[STATE_FOCUSABLE],
+        };
+        [STATE_ENABLED, STATE_SENSITIVE, STATE_SHOWING, STATE_VISIBLE]
+            .iter()
+            .chain(states)
+            .fold(0, |set, state| set | 1 << state)
+    }
+}
+
+/// Creates an AT-SPI object reference: the bus name and object path of an accessible object.
+fn reference(bus_name: &str, path: &str) -> Value {
+    Value::Struct(vec![
+        Value::String(bus_name.into()),
+        Value::ObjectPath(path.into()),
+    ])
+}
+
+/// Returns the role of `object`, and its name.
+fn role(object: Object) -> (u32, &'static str) {
+    match object {
+        Object::Application => (ROLE_APPLICATION, "application"),
+        Object::Dialog => (ROLE_ALERT, "alert"),
+        Object::Message => (ROLE_LABEL, "label"),
+        Object::Button(_) => (ROLE_PUSH_BUTTON, "push button"),
+    }
+}
+
+fn interfaces(object: Object) -> &'static [&'static str] {
+    match object {
+        Object::Application => &[ACCESSIBLE, APPLICATION],
+        Object::Dialog | Object::Message => &[ACCESSIBLE],
+        Object::Button(_) => &[ACCESSIBLE, ACTION],
+    }
+}
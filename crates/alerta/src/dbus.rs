@@ -0,0 +1,771 @@
+//! A minimal D-Bus client, just capable enough to call methods on the session bus and to answer
+//! method calls from the accessibility bus.
+//!
+//! Only the parts of the [specification] that alerta needs are implemented: connecting to a Unix
+//! socket, `EXTERNAL` authentication, and (un)marshalling of messages.
+//!
+//! [specification]: https://dbus.freedesktop.org/doc/dbus-specification.html
+
+use std::{
+    collections::VecDeque,
+    env,
+    ffi::OsStr,
+    io::{Read, Write},
+    os::{
+        fd::{AsFd, BorrowedFd},
+        unix::{ffi::OsStrExt, net::UnixStream},
+    },
+    time::Duration,
+};
+
+use crate::{Error, error::err};
+
+/// How long to wait for the bus (or the other peer) before giving up.
+const TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Messages larger than this are rejected (the specification's limit is 128 MiB, but we never
+/// expect to receive anything close to that).
+const MAX_MESSAGE_SIZE: usize = 1 << 24;
+
+/// Maximum nesting depth of variants, to avoid overflowing the stack on malicious input.
+const MAX_VARIANT_DEPTH: u32 = 32;
+
+/// A D-Bus value.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Byte(u8),
+    Bool(bool),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    Double(f64),
+    String(String),
+    ObjectPath(String),
+    Signature(String),
+    Variant(Box<Value>),
+    /// An array whose elements have the given signature.
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+}
+
+impl Value {
+    pub(crate) fn signature(&self) -> String {
+        match self {
+            Value::Byte(_) => "y".into(),
+            Value::Bool(_) => "b".into(),
+            Value::I16(_) => "n".into(),
+            Value::U16(_) => "q".into(),
+            Value::I32(_) => "i".into(),
+            Value::U32(_) => "u".into(),
+            Value::I64(_) => "x".into(),
+            Value::U64(_) => "t".into(),
+            Value::Double(_) => "d".into(),
+            Value::String(_) => "s".into(),
+            Value::ObjectPath(_) => "o".into(),
+            Value::Signature(_) => "g".into(),
+            Value::Variant(_) => "v".into(),
+            Value::Array(elem, _) => format!("a{elem}"),
+            Value::Struct(fields) => {
+                format!(
+                    "({})",
+                    fields.iter().map(Value::signature).collect::<String>()
+                )
+            }
+            Value::DictEntry(k, v) => format!("{{{}{}}}", k.signature(), v.signature()),
+        }
+    }
+
+    /// Strips any number of variant wrappers from the value.
+    pub(crate) fn unwrap_variant(&self) -> &Value {
+        match self {
+            Value::Variant(inner) => inner.unwrap_variant(),
+            value => value,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self.unwrap_variant() {
+            Value::String(s) | Value::ObjectPath(s) | Value::Signature(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_u32(&self) -> Option<u32> {
+        match self.unwrap_variant() {
+            Value::U32(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the alignment of the type whose signature starts with `code`.
+fn alignment(code: u8) -> usize {
+    match code {
+        b'y' | b'g' | b'v' => 1,
+        b'n' | b'q' => 2,
+        b'x' | b't' | b'd' | b'(' | b'{' => 8,
+        _ => 4,
+    }
+}
+
+/// Splits the first complete type off of `sig`.
+fn split_type(sig: &str) -> Result<(&str, &str), Error> {
+    let invalid = || Error::new(format!("invalid D-Bus signature '{sig}'"));
+    let len = match sig.as_bytes().first().ok_or_else(invalid)? {
+        b'a' => 1 + split_type(&sig[1..])?.0.len(),
+        open @ (b'(' | b'{') => {
+            let close = if *open == b'(' { b')' } else { b'}' };
+            let mut depth = 0;
+            let end = sig.bytes().position(|b| {
+                if b == *open {
+                    depth += 1;
+                } else if b == close {
+                    depth -= 1;
+                }
+                depth == 0
+            });
+            match end.ok_or_else(invalid)? + 1 {
+                // Empty structs and dict entries are not allowed.
+                2 => return Err(invalid()),
+                len => len,
+            }
+        }
+        b')' | b'}' => return Err(invalid()),
+        _ => 1,
+    };
+    Ok(sig.split_at(len))
+}
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn align(&mut self, n: usize) {
+        self.buf.resize(self.buf.len().next_multiple_of(n), 0);
+    }
+
+    fn fixed<const N: usize>(&mut self, bytes: [u8; N]) {
+        self.align(N);
+        self.buf.extend(bytes);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.fixed(v.to_le_bytes());
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Byte(v) => self.buf.push(*v),
+            Value::Bool(v) => self.u32(u32::from(*v)),
+            Value::I16(v) => self.fixed(v.to_le_bytes()),
+            Value::U16(v) => self.fixed(v.to_le_bytes()),
+            Value::I32(v) => self.fixed(v.to_le_bytes()),
+            Value::U32(v) => self.u32(*v),
+            Value::I64(v) => self.fixed(v.to_le_bytes()),
+            Value::U64(v) => self.fixed(v.to_le_bytes()),
+            Value::Double(v) => self.fixed(v.to_le_bytes()),
+            Value::String(s) | Value::ObjectPath(s) => {
+                self.u32(s.len() as u32);
+                self.buf.extend(s.as_bytes());
+                self.buf.push(0);
+            }
+            Value::Signature(s) => {
+                self.buf.push(s.len() as u8);
+                self.buf.extend(s.as_bytes());
+                self.buf.push(0);
+            }
+            Value::Variant(inner) => {
+                self.value(&Value::Signature(inner.signature()));
+                self.value(inner);
+            }
+            Value::Array(elem, values) => {
+                self.u32(0);
+                let len_pos = self.buf.len() - 4;
+                // The padding before the first element doesn't count towards the length.
+                self.align(alignment(elem.as_bytes()[0]));
+                let start = self.buf.len();
+                for value in values {
+                    self.value(value);
+                }
+                let len = (self.buf.len() - start) as u32;
+                self.buf[len_pos..][..4].copy_from_slice(&len.to_le_bytes());
+            }
+            Value::Struct(fields) => {
+                self.align(8);
+                for field in fields {
+                    self.value(field);
+                }
+            }
+            Value::DictEntry(k, v) => {
+                self.align(8);
+                self.value(k);
+                self.value(v);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+    variant_depth: u32,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos.saturating_add(n))
+            .ok_or_else(|| Error::new("truncated D-Bus message"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn align(&mut self, n: usize) -> Result<(), Error> {
+        let padding = self.pos.next_multiple_of(n) - self.pos;
+        self.bytes(padding)?;
+        Ok(())
+    }
+
+    fn fixed<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        self.align(N)?;
+        let mut bytes: [u8; N] = self.bytes(N)?.try_into().unwrap();
+        if self.big_endian {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        self.fixed().map(u32::from_le_bytes)
+    }
+
+    fn string(&mut self, len: usize) -> Result<String, Error> {
+        let bytes = self.bytes(len)?;
+        self.bytes(1)?; // nul terminator
+        String::from_utf8(bytes.to_vec()).map_err(err)
+    }
+
+    /// Reads a value of the single complete type `sig`.
+    fn value(&mut self, sig: &str) -> Result<Value, Error> {
+        Ok(match sig.as_bytes()[0] {
+            b'y' => Value::Byte(self.bytes(1)?[0]),
+            b'b' => Value::Bool(self.u32()? != 0),
+            b'n' => Value::I16(i16::from_le_bytes(self.fixed()?)),
+            b'q' => Value::U16(u16::from_le_bytes(self.fixed()?)),
+            b'i' => Value::I32(i32::from_le_bytes(self.fixed()?)),
+            b'u' => Value::U32(self.u32()?),
+            b'x' => Value::I64(i64::from_le_bytes(self.fixed()?)),
+            b't' => Value::U64(u64::from_le_bytes(self.fixed()?)),
+            b'd' => Value::Double(f64::from_le_bytes(self.fixed()?)),
+            b's' => {
+                let len = self.u32()?;
+                Value::String(self.string(len as usize)?)
+            }
+            b'o' => {
+                let len = self.u32()?;
+                Value::ObjectPath(self.string(len as usize)?)
+            }
+            b'g' => {
+                let len = self.bytes(1)?[0];
+                Value::Signature(self.string(len.into())?)
+            }
+            b'v' => {
+                let sig = self.bytes(1)?[0];
+                let sig = self.string(sig.into())?;
+                if !split_type(&sig)?.1.is_empty() {
+                    return Err(Error::new(format!("invalid variant signature '{sig}'")));
+                }
+                if self.variant_depth == MAX_VARIANT_DEPTH {
+                    return Err(Error::new("D-Bus variants nested too deeply"));
+                }
+                self.variant_depth += 1;
+                let value = self.value(&sig)?;
+                self.variant_depth -= 1;
+                Value::Variant(Box::new(value))
+            }
+            b'a' => {
+                let len = self.u32()? as usize;
+                let elem = &sig[1..];
+                self.align(alignment(elem.as_bytes()[0]))?;
+                let end = self.pos.saturating_add(len);
+                let mut values = Vec::new();
+                while self.pos < end {
+                    let start = self.pos;
+                    values.push(self.value(elem)?);
+                    // Elements that take up no space would make the array endless.
+                    if self.pos == start {
+                        return Err(Error::new(format!("invalid D-Bus array type '{sig}'")));
+                    }
+                }
+                Value::Array(elem.to_string(), values)
+            }
+            b'(' => {
+                self.align(8)?;
+                let mut fields = Vec::new();
+                let mut rest = &sig[1..sig.len() - 1];
+                while !rest.is_empty() {
+                    let (ty, tail) = split_type(rest)?;
+                    fields.push(self.value(ty)?);
+                    rest = tail;
+                }
+                Value::Struct(fields)
+            }
+            b'{' => {
+                self.align(8)?;
+                let (key, rest) = split_type(&sig[1..])?;
+                let k = self.value(key)?;
+                let v = self.value(split_type(rest)?.0)?;
+                Value::DictEntry(Box::new(k), Box::new(v))
+            }
+            _ => return Err(Error::new(format!("unsupported D-Bus type '{sig}'"))),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MessageType {
+    MethodCall = 1,
+    MethodReturn = 2,
+    Error = 3,
+    Signal = 4,
+}
+
+/// Message flag indicating that the sender doesn't want a reply to its method call.
+pub(crate) const NO_REPLY_EXPECTED: u8 = 0x1;
+
+/// A D-Bus message, consisting of a header and a body.
+#[derive(Debug, Clone)]
+pub(crate) struct Message {
+    pub(crate) kind: MessageType,
+    pub(crate) flags: u8,
+    pub(crate) serial: u32,
+    pub(crate) path: Option<String>,
+    pub(crate) interface: Option<String>,
+    pub(crate) member: Option<String>,
+    pub(crate) error_name: Option<String>,
+    pub(crate) reply_serial: Option<u32>,
+    pub(crate) destination: Option<String>,
+    pub(crate) sender: Option<String>,
+    pub(crate) body: Vec<Value>,
+}
+
+impl Message {
+    fn new(kind: MessageType, body: Vec<Value>) -> Self {
+        Self {
+            kind,
+            flags: 0,
+            serial: 0,
+            path: None,
+            interface: None,
+            member: None,
+            error_name: None,
+            reply_serial: None,
+            destination: None,
+            sender: None,
+            body,
+        }
+    }
+
+    pub(crate) fn method_call(
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        body: Vec<Value>,
+    ) -> Self {
+        Self {
+            destination: Some(destination.into()),
+            path: Some(path.into()),
+            interface: Some(interface.into()),
+            member: Some(member.into()),
+            ..Self::new(MessageType::MethodCall, body)
+        }
+    }
+
+    /// Creates a successful reply to the method call `call`.
+    pub(crate) fn method_return(call: &Message, body: Vec<Value>) -> Self {
+        Self {
+            reply_serial: Some(call.serial),
+            destination: call.sender.clone(),
+            ..Self::new(MessageType::MethodReturn, body)
+        }
+    }
+
+    pub(crate) fn signal(path: &str, interface: &str, member: &str, body: Vec<Value>) -> Self {
+        Self {
+            path: Some(path.into()),
+            interface: Some(interface.into()),
+            member: Some(member.into()),
+            ..Self::new(MessageType::Signal, body)
+        }
+    }
+
+    /// Creates an error reply to the method call `call`.
+    pub(crate) fn error(call: &Message, name: &str, text: &str) -> Self {
+        Self {
+            reply_serial: Some(call.serial),
+            destination: call.sender.clone(),
+            error_name: Some(name.into()),
+            ..Self::new(MessageType::Error, vec![Value::String(text.into())])
+        }
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut fields = Vec::new();
+        let mut field = |code, value| {
+            fields.push(Value::Struct(vec![
+                Value::Byte(code),
+                Value::Variant(Box::new(value)),
+            ]))
+        };
+        let strings = [
+            (1, &self.path),
+            (2, &self.interface),
+            (3, &self.member),
+            (4, &self.error_name),
+            (6, &self.destination),
+            (7, &self.sender),
+        ];
+        for (code, value) in strings {
+            if let Some(s) = value {
+                field(
+                    code,
+                    if code == 1 {
+                        Value::ObjectPath(s.clone())
+                    } else {
+                        Value::String(s.clone())
+                    },
+                );
+            }
+        }
+        if let Some(serial) = self.reply_serial {
+            field(5, Value::U32(serial));
+        }
+        if !self.body.is_empty() {
+            field(
+                8,
+                Value::Signature(self.body.iter().map(Value::signature).collect()),
+            );
+        }
+
+        let mut w = Writer::default();
+        w.buf.extend([b'l', self.kind as u8, self.flags, 1]);
+        w.u32(0); // body length, filled in below
+        w.u32(self.serial);
+        w.value(&Value::Array("(yv)".into(), fields));
+        w.align(8);
+        let body_start = w.buf.len();
+        for value in &self.body {
+            w.value(value);
+        }
+        let body_len = (w.buf.len() - body_start) as u32;
+        w.buf[4..8].copy_from_slice(&body_len.to_le_bytes());
+        w.buf
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self, Error> {
+        let big_endian = match buf.first() {
+            Some(b'l') => false,
+            Some(b'B') => true,
+            _ => return Err(Error::new("invalid D-Bus message")),
+        };
+        let mut r = Reader {
+            buf,
+            pos: 0,
+            big_endian,
+            variant_depth: 0,
+        };
+        let &[_, kind, flags, _] = r.bytes(4)? else {
+            unreachable!()
+        };
+        let kind = match kind {
+            1 => MessageType::MethodCall,
+            2 => MessageType::MethodReturn,
+            3 => MessageType::Error,
+            4 => MessageType::Signal,
+            _ => return Err(Error::new(format!("unknown D-Bus message type {kind}"))),
+        };
+        let mut msg = Message::new(kind, Vec::new());
+        msg.flags = flags;
+        let _body_len = r.u32()?;
+        msg.serial = r.u32()?;
+
+        let Value::Array(_, fields) = r.value("a(yv)")? else {
+            unreachable!()
+        };
+        let mut signature = String::new();
+        for field in fields {
+            let Value::Struct(field) = field else {
+                unreachable!()
+            };
+            let (Value::Byte(code), value) = (&field[0], field[1].unwrap_variant()) else {
+                unreachable!()
+            };
+            let string = value.as_str().map(str::to_string);
+            match code {
+                1 => msg.path = string,
+                2 => msg.interface = string,
+                3 => msg.member = string,
+                4 => msg.error_name = string,
+                5 => msg.reply_serial = value.as_u32(),
+                6 => msg.destination = string,
+                7 => msg.sender = string,
+                8 => signature = string.unwrap_or_default(),
+                _ => {}
+            }
+        }
+        r.align(8)?;
+
+        let mut rest = &*signature;
+        while !rest.is_empty() {
+            let (ty, tail) = split_type(rest)?;
+            msg.body.push(r.value(ty)?);
+            rest = tail;
+        }
+        Ok(msg)
+    }
+}
+
+/// Reads a single message from `stream`.
+pub(crate) fn read_message(stream: &mut impl Read) -> Result<Message, Error> {
+    let mut buf = vec![0; 16];
+    stream.read_exact(&mut buf).map_err(err)?;
+    let u32_at = |i: usize| {
+        let bytes = buf[i..i + 4].try_into().unwrap();
+        (if buf[0] == b'B' {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }) as usize
+    };
+    let too_large = || Error::new("D-Bus message too large");
+    let len = u32_at(12)
+        .checked_add(16)
+        .and_then(|len| len.checked_next_multiple_of(8))
+        .and_then(|header_len| header_len.checked_add(u32_at(4)))
+        .ok_or_else(too_large)?;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(Error::new(format!("D-Bus message too large ({len} bytes)")));
+    }
+    buf.resize(len, 0);
+    stream.read_exact(&mut buf[16..]).map_err(err)?;
+    Message::decode(&buf)
+}
+
+/// Reads a line of the authentication protocol, without the trailing `\r\n`.
+pub(crate) fn read_line(stream: &mut impl Read) -> Result<String, Error> {
+    let mut line = Vec::new();
+    while !line.ends_with(b"\r\n") {
+        let mut byte = 0;
+        stream
+            .read_exact(std::slice::from_mut(&mut byte))
+            .map_err(err)?;
+        line.push(byte);
+        if line.len() > 1024 {
+            return Err(Error::new("D-Bus authentication line too long"));
+        }
+    }
+    line.truncate(line.len() - 2);
+    String::from_utf8(line).map_err(err)
+}
+
+/// A connection to a message bus.
+pub(crate) struct Connection {
+    stream: UnixStream,
+    next_serial: u32,
+    /// The name the bus assigned to this connection.
+    unique_name: String,
+    /// Messages that arrived while waiting for a method reply.
+    queue: VecDeque<Message>,
+}
+
+impl Connection {
+    /// Connects to the session bus.
+    pub(crate) fn session() -> Result<Self, Error> {
+        let address = match env::var("DBUS_SESSION_BUS_ADDRESS") {
+            Ok(address) => address,
+            // Most systems nowadays put the bus at a fixed location.
+            Err(_) => match env::var("XDG_RUNTIME_DIR") {
+                Ok(dir) => format!("unix:path={dir}/bus"),
+                Err(_) => return Err(Error::new("D-Bus session bus address is not set")),
+            },
+        };
+        Self::connect(&address)
+    }
+
+    /// Connects to the bus at `address`, which uses the D-Bus address format (for example,
+    /// `unix:path=/run/user/1000/bus`).
+    pub(crate) fn connect(address: &str) -> Result<Self, Error> {
+        // Multiple addresses can be given; they are tried in order.
+        let mut result = Err(Error::new("empty D-Bus address"));
+        for address in address.split(';').filter(|a| !a.is_empty()) {
+            result = Self::connect_one(address);
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+
+    fn connect_one(address: &str) -> Result<Self, Error> {
+        let stream = open(address)?;
+        stream.set_read_timeout(Some(TIMEOUT)).map_err(err)?;
+        stream.set_write_timeout(Some(TIMEOUT)).map_err(err)?;
+        let mut conn = Self {
+            stream,
+            next_serial: 1,
+            unique_name: String::new(),
+            queue: VecDeque::new(),
+        };
+        conn.authenticate()?;
+        let reply = conn.call(Message::method_call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "Hello",
+            Vec::new(),
+        ))?;
+        conn.unique_name = reply
+            .body
+            .first()
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::new("invalid reply to `Hello`"))?
+            .into();
+        Ok(conn)
+    }
+
+    /// Performs `EXTERNAL` authentication, letting the server use the credentials of the socket.
+    fn authenticate(&mut self) -> Result<(), Error> {
+        // The initial nul byte is where credentials would be passed on systems that need it.
+        self.stream.write_all(b"\0AUTH EXTERNAL\r\n").map_err(err)?;
+        let mut line = read_line(&mut self.stream)?;
+        if line == "DATA" {
+            // We didn't send an authorization identity, so the server asks for one. Sending an
+            // empty one makes it use the one it derived from the socket.
+            self.stream.write_all(b"DATA\r\n").map_err(err)?;
+            line = read_line(&mut self.stream)?;
+        }
+        if !line.starts_with("OK ") {
+            return Err(Error::new(format!("D-Bus authentication failed: {line}")));
+        }
+        self.stream.write_all(b"BEGIN\r\n").map_err(err)?;
+        Ok(())
+    }
+
+    /// Sends `msg`, returning the serial number assigned to it.
+    pub(crate) fn send(&mut self, mut msg: Message) -> Result<u32, Error> {
+        msg.serial = self.next_serial;
+        self.next_serial = self.next_serial.checked_add(1).unwrap_or(1);
+        self.stream.write_all(&msg.encode()).map_err(err)?;
+        Ok(msg.serial)
+    }
+
+    /// Asks the bus to deliver the signals matching `rule` to this connection.
+    pub(crate) fn add_match(&mut self, rule: &str) -> Result<(), Error> {
+        self.call(Message::method_call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "AddMatch",
+            vec![Value::String(rule.into())],
+        ))?;
+        Ok(())
+    }
+
+    /// Returns the unique name of this connection, like `:1.42`.
+    pub(crate) fn unique_name(&self) -> &str {
+        &self.unique_name
+    }
+
+    /// Reads the next incoming message.
+    pub(crate) fn receive(&mut self) -> Result<Message, Error> {
+        match self.queue.pop_front() {
+            Some(msg) => Ok(msg),
+            None => read_message(&mut self.stream),
+        }
+    }
+
+    /// Returns whether [`Connection::receive`] has messages to return without reading from the
+    /// socket.
+    ///
+    /// Waiting for the socket to become readable would miss those.
+    pub(crate) fn has_queued(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// Calls a method and waits for its reply.
+    ///
+    /// Unrelated messages received in the meantime are queued for [`Connection::receive`].
+    pub(crate) fn call(&mut self, msg: Message) -> Result<Message, Error> {
+        let serial = self.send(msg)?;
+        loop {
+            let reply = read_message(&mut self.stream)?;
+            if reply.reply_serial != Some(serial) {
+                self.queue.push_back(reply);
+                continue;
+            }
+            if reply.kind == MessageType::Error {
+                return Err(Error::new(format!(
+                    "{}: {}",
+                    reply.error_name.as_deref().unwrap_or("D-Bus error"),
+                    reply.body.first().and_then(Value::as_str).unwrap_or(""),
+                )));
+            }
+            return Ok(reply);
+        }
+    }
+}
+
+impl AsFd for Connection {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.stream.as_fd()
+    }
+}
+
+/// Opens the socket described by a single D-Bus server address.
+fn open(address: &str) -> Result<UnixStream, Error> {
+    let Some(("unix", params)) = address.split_once(':') else {
+        return Err(Error::new(format!("unsupported D-Bus address '{address}'")));
+    };
+    for param in params.split(',') {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+        let value = unescape(value)?;
+        match key {
+            "path" => return UnixStream::connect(OsStr::from_bytes(&value)).map_err(err),
+            #[cfg(target_os = "linux")]
+            "abstract" => {
+                use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+                let addr = SocketAddr::from_abstract_name(&value).map_err(err)?;
+                return UnixStream::connect_addr(&addr).map_err(err);
+            }
+            _ => {}
+        }
+    }
+    Err(Error::new(format!("unsupported D-Bus address '{address}'")))
+}
+
+/// Decodes the `%xx` escapes in an address value.
+fn unescape(value: &str) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    let mut bytes = value.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex = [bytes.next().unwrap_or(0), bytes.next().unwrap_or(0)];
+            let hex = str::from_utf8(&hex).map_err(err)?;
+            out.push(u8::from_str_radix(hex, 16).map_err(err)?);
+        } else {
+            out.push(b);
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,302 @@
+//! Images of dialogs rendered without an X server (see [`Builder::render`]).
+//!
+//! [`Builder::render`]: crate::Builder::render
+
+use raqote::DrawTarget;
+
+/// An image with 8-bit red, green, blue and alpha channels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl RgbaImage {
+    /// Copies the premultiplied ARGB pixels of `canvas`.
+    pub(crate) fn from_canvas(canvas: &DrawTarget) -> Self {
+        let data = canvas
+            .get_data()
+            .iter()
+            .flat_map(|&argb| {
+                let [a, r, g, b] = argb.to_be_bytes();
+                let unpremultiply = |c: u8| match a {
+                    0 => 0,
+                    _ => ((u32::from(c) * 255 + u32::from(a) / 2) / u32::from(a)) as u8,
+                };
+                [unpremultiply(r), unpremultiply(g), unpremultiply(b), a]
+            })
+            .collect();
+        Self {
+            width: canvas.width() as u32,
+            height: canvas.height() as u32,
+            data,
+        }
+    }
+
+    /// Returns the width of the image in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the image in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the pixels, row by row, with 4 bytes each (red, green, blue and alpha).
+    ///
+    /// The colors are not premultiplied with alpha.
+    pub fn as_raw(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the pixels in the layout described in [`RgbaImage::as_raw`].
+    pub fn into_raw(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Encodes the image as a PNG file.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&self.width.to_be_bytes());
+        header.extend_from_slice(&self.height.to_be_bytes());
+        // 8 bits per channel, RGBA, no interlacing.
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+        write_chunk(&mut png, b"IHDR", &header);
+        write_chunk(&mut png, b"IDAT", &zlib(&self.filtered_rows()));
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    /// Returns the rows with a filter byte in front of each, as stored in PNG files.
+    ///
+    /// Every row gets the filter that leaves the smallest differences, which compress best.
+    fn filtered_rows(&self) -> Vec<u8> {
+        let stride = self.width as usize * 4;
+        let mut out = Vec::with_capacity((stride + 1) * self.height as usize);
+        let zeros = vec![0; stride];
+        let mut prev = &zeros[..];
+        let mut candidates = [const { Vec::new() }; 3];
+        for row in self.data.chunks(stride.max(1)) {
+            for (filter, candidate) in candidates.iter_mut().enumerate() {
+                candidate.clear();
+                candidate.extend(row.iter().enumerate().map(|(i, &byte)| {
+                    let predicted = match filter {
+                        // Sub: the same channel of the pixel to the left.
+                        1 => i.checked_sub(4).map_or(0, |left| row[left]),
+                        // Up: the same channel of the pixel above.
+                        2 => prev[i],
+                        _ => 0,
+                    };
+                    byte.wrapping_sub(predicted)
+                }));
+            }
+            let cost = |bytes: &Vec<u8>| -> u32 {
+                bytes
+                    .iter()
+                    .map(|&b| u32::from((b as i8).unsigned_abs()))
+                    .sum()
+            };
+            let (filter, best) = candidates
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, bytes)| cost(bytes))
+                .expect("there are candidates");
+            out.push(filter as u8);
+            out.extend_from_slice(best);
+            prev = row;
+        }
+        out
+    }
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut table = [0; 256];
+    for (n, entry) in table.iter_mut().enumerate() {
+        *entry = (0..8).fold(n as u32, |c, _| {
+            if c & 1 == 1 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            }
+        });
+    }
+    !bytes.iter().fold(!0, |crc, &byte| {
+        table[usize::from(crc as u8 ^ byte)] ^ (crc >> 8)
+    })
+}
+
+pub(crate) fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        (a, b) = (a % 65521, b % 65521);
+    }
+    b << 16 | a
+}
+
+/// Compresses `data` into a zlib stream.
+///
+/// This uses a single deflate block with the fixed Huffman codes, and greedily picks the longest
+/// match found in a few candidates. That is far from optimal, but plenty for the large areas of
+/// flat color in dialogs, and much smaller than a PNG library.
+pub(crate) fn zlib(data: &[u8]) -> Vec<u8> {
+    const WINDOW: usize = 32768;
+    const MIN_MATCH: usize = 3;
+    const MAX_MATCH: usize = 258;
+    /// How many earlier positions with the same hash are tried.
+    const MAX_CHAIN: usize = 32;
+
+    let mut out = BitWriter {
+        out: vec![0x78, 0x01],
+        bits: 0,
+        len: 0,
+    };
+    // Final block, fixed Huffman codes.
+    out.write(0b011, 3);
+
+    let hash = |i: usize| {
+        let value = u32::from_le_bytes([data[i], data[i + 1], data[i + 2], 0]);
+        (value.wrapping_mul(0x9e3779b1) >> 17) as usize
+    };
+    // Most recent position + 1 with each hash, and for every position, the previous one.
+    let mut head = vec![0usize; 1 << 15];
+    let mut prev = vec![0; data.len()];
+    let insert = |head: &mut [usize], prev: &mut [usize], i: usize| {
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(i);
+            prev[i] = head[h];
+            head[h] = i + 1;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash(i)];
+            let max = (data.len() - i).min(MAX_MATCH);
+            for _ in 0..MAX_CHAIN {
+                let Some(start) = candidate.checked_sub(1) else {
+                    break;
+                };
+                if i - start > WINDOW {
+                    break;
+                }
+                let len = data[start..]
+                    .iter()
+                    .zip(&data[i..i + max])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best.0 {
+                    best = (len, i - start);
+                }
+                candidate = prev[start];
+            }
+        }
+
+        let (len, distance) = best;
+        if len >= MIN_MATCH {
+            out.length(len);
+            out.distance(distance);
+            for j in i..i + len {
+                insert(&mut head, &mut prev, j);
+            }
+            i += len;
+        } else {
+            out.literal(data[i]);
+            insert(&mut head, &mut prev, i);
+            i += 1;
+        }
+    }
+    out.literal_code(256);
+
+    let mut out = out.finish();
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// Writes the bits of a deflate stream, least significant first.
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    len: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, len: u32) {
+        self.bits |= u64::from(value) << self.len;
+        self.len += len;
+        while self.len >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which is stored starting from its most significant bit.
+    fn write_code(&mut self, code: u32, len: u32) {
+        self.write(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn literal(&mut self, byte: u8) {
+        self.literal_code(u32::from(byte));
+    }
+
+    /// Writes a symbol of the literal/length alphabet with its fixed code.
+    fn literal_code(&mut self, symbol: u32) {
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn length(&mut self, len: usize) {
+        const BASES: [u16; 29] = [
+            3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99,
+            115, 131, 163, 195, 227, 258,
+        ];
+        let code = BASES.partition_point(|&base| usize::from(base) <= len) - 1;
+        let extra = match code {
+            8..28 => code as u32 / 4 - 1,
+            _ => 0,
+        };
+        self.literal_code(257 + code as u32);
+        self.write((len - usize::from(BASES[code])) as u32, extra);
+    }
+
+    fn distance(&mut self, distance: usize) {
+        const BASES: [u16; 30] = [
+            1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025,
+            1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+        ];
+        let code = BASES.partition_point(|&base| usize::from(base) <= distance) - 1;
+        let extra = (code as u32 / 2).saturating_sub(1);
+        self.write_code(code as u32, 5);
+        self.write((distance - usize::from(BASES[code])) as u32, extra);
+    }
+
+    /// Pads the last byte with zeros and returns the bytes.
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
@@ -21,20 +21,35 @@
 //! # Ok::<_, alerta::Error>(())
 //! ```
 
+mod a11y;
+mod dbus;
 mod error;
+mod image;
+mod locale;
+mod script;
+mod theme;
 mod ui;
 mod x11;
 
 #[cfg(test)]
 mod tests;
 
-use std::{fmt, process::Command, str::FromStr};
+use std::{env, fmt, str::FromStr};
 
 pub use error::Error;
+pub use image::RgbaImage;
+pub use locale::Strings;
 use rapid_qoi::Qoi;
 use raqote::DrawTarget;
+pub use script::Input;
+pub use theme::{Palette, Rgb, Theme};
 
-use crate::{error::err, ui::Ui, x11::X11Window};
+use crate::{
+    a11y::Accessibility,
+    theme::ThemeWatcher,
+    ui::{Action, Ui},
+    x11::{Renderer, Wakeup, WindowState, X11Window},
+};
 
 /// Returns a [`Builder`] for creating dialogs.
 ///
@@ -43,9 +58,21 @@ pub fn alerta() -> Builder {
     Builder {
         title: None,
         message: None,
-        theme: None,
+        details: None,
+        theme: Theme::System,
         icon: Default::default(),
         button_preset: ButtonPreset::default(),
+        strings: None,
+        resizable: false,
+        urgent: None,
+        bell: None,
+        above: false,
+        sticky: false,
+        skip_taskbar: false,
+        skip_pager: false,
+        app_id: None,
+        rounded: false,
+        script: None,
     }
 }
 
@@ -53,9 +80,21 @@ pub fn alerta() -> Builder {
 pub struct Builder {
     title: Option<String>,
     message: Option<String>,
-    theme: Option<Theme>,
+    details: Option<String>,
+    theme: Theme,
     icon: Icon,
     button_preset: ButtonPreset,
+    strings: Option<Strings>,
+    resizable: bool,
+    urgent: Option<bool>,
+    bell: Option<bool>,
+    above: bool,
+    sticky: bool,
+    skip_taskbar: bool,
+    skip_pager: bool,
+    app_id: Option<String>,
+    rounded: bool,
+    script: Option<Vec<Input>>,
 }
 
 impl Builder {
@@ -75,6 +114,16 @@ impl Builder {
         self
     }
 
+    /// Sets additional details to show in the dialog.
+    ///
+    /// The details are hidden behind a "Show details" toggle. When expanded, they are displayed in
+    /// a monospace font below the message. This is useful for things like backtraces and logs
+    /// that would clutter the message itself.
+    pub fn details(mut self, details: impl Into<String>) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+
     /// Sets the icon to display in the dialog.
     ///
     /// By default, [`Icon::Info`] is used.
@@ -85,9 +134,11 @@ impl Builder {
 
     /// Sets the dialog's color theme.
     ///
-    /// By default, the OS theme is used.
+    /// By default, [`Theme::System`] is used, which follows the OS theme. With [`Theme::System`],
+    /// colors set as X resources (like `alerta*background: #ffffe0` in `~/.Xresources`) take
+    /// precedence over the OS theme.
     pub fn theme(mut self, theme: Theme) -> Self {
-        self.theme = Some(theme);
+        self.theme = theme;
         self
     }
 
@@ -99,6 +150,170 @@ impl Builder {
         self
     }
 
+    /// Sets the language of the button labels and default title, as a POSIX locale name like
+    /// `de_DE.UTF-8` (see [`Strings::for_locale`]).
+    ///
+    /// By default, the language is chosen based on the environment (see [`Strings::from_env`]).
+    pub fn locale(mut self, locale: &str) -> Self {
+        self.strings = Some(Strings::for_locale(locale));
+        self
+    }
+
+    /// Sets the texts of the button labels, default title and details toggle.
+    ///
+    /// This allows applications to use their own translations.
+    pub fn strings(mut self, strings: Strings) -> Self {
+        self.strings = Some(strings);
+        self
+    }
+
+    /// Sets whether the user can resize the dialog window.
+    ///
+    /// When resized, the dialog contents are laid out again to fit the new size. By default, the
+    /// window has a fixed size that fits its contents.
+    pub fn resizable(mut self, resizable: bool) -> Self {
+        self.resizable = resizable;
+        self
+    }
+
+    /// Sets whether the dialog should draw the user's attention when it opens.
+    ///
+    /// Urgent dialogs ask the window manager to highlight them (for example, by flashing their
+    /// taskbar entry), which helps when they open on another workspace or behind other windows.
+    /// By default, only dialogs with [`Icon::Error`] are urgent.
+    pub fn urgent(mut self, urgent: bool) -> Self {
+        self.urgent = Some(urgent);
+        self
+    }
+
+    /// Sets whether to ring the bell when the dialog opens.
+    ///
+    /// Desktops that play event sounds will play the sound matching the dialog's [`Icon`] (like
+    /// `dialog-error`) instead of beeping. By default, the bell rings for [`urgent`] dialogs.
+    ///
+    /// [`urgent`]: Builder::urgent
+    pub fn bell(mut self, bell: bool) -> Self {
+        self.bell = Some(bell);
+        self
+    }
+
+    /// Sets whether the dialog stays above other windows.
+    ///
+    /// This is meant for critical alerts that must not get lost behind other windows.
+    pub fn always_on_top(mut self, above: bool) -> Self {
+        self.above = above;
+        self
+    }
+
+    /// Sets whether the dialog is shown on every workspace.
+    pub fn sticky(mut self, sticky: bool) -> Self {
+        self.sticky = sticky;
+        self
+    }
+
+    /// Sets whether the dialog is hidden from taskbars.
+    pub fn skip_taskbar(mut self, skip: bool) -> Self {
+        self.skip_taskbar = skip;
+        self
+    }
+
+    /// Sets whether the dialog is hidden from pagers and workspace switchers.
+    pub fn skip_pager(mut self, skip: bool) -> Self {
+        self.skip_pager = skip;
+        self
+    }
+
+    /// Sets the application identifier, which is used as the window's `WM_CLASS`.
+    ///
+    /// `app_id` becomes the instance name, and the class name is `app_id` with its first letter
+    /// capitalized, so `myapp` results in the `WM_CLASS` `myapp`, `Myapp`. Window managers match
+    /// their window rules against `WM_CLASS`, so setting this to the name of the calling
+    /// application lets users configure its dialogs separately. X resources are then looked up
+    /// under these names too (like `myapp*background` or `Myapp*background`). By default, `alerta`
+    /// is used.
+    pub fn app_id(mut self, app_id: impl Into<String>) -> Self {
+        self.app_id = Some(app_id.into());
+        self
+    }
+
+    /// Sets whether the dialog gets rounded corners and a soft shadow.
+    ///
+    /// This needs a compositor that displays translucent windows. Without one, the dialog is shown
+    /// as a plain rectangle, as if this wasn't set.
+    pub fn rounded(mut self, rounded: bool) -> Self {
+        self.rounded = rounded;
+        self
+    }
+
+    /// Answers the dialog with scripted input instead of displaying it.
+    ///
+    /// This is meant for testing code that shows dialogs: [`Builder::show`] then doesn't need an X
+    /// server, and returns the [`Answer`] that the `inputs` lead to. If they don't close the
+    /// dialog, it returns an error.
+    pub fn script(mut self, inputs: impl IntoIterator<Item = Input>) -> Self {
+        self.script = Some(inputs.into_iter().collect());
+        self
+    }
+
+    /// Draws the dialog into an image, without displaying it.
+    ///
+    /// This doesn't need an X server, so it can be used for generating screenshots or reviewing
+    /// dialog texts in automated builds. Only the dialog contents are drawn: the title is left to
+    /// the window decorations.
+    ///
+    /// No desktop settings are consulted, so the output is the same on every machine: the default
+    /// fonts are used, and [`Theme::System`] is drawn as [`Theme::Light`].
+    pub fn render(self) -> RgbaImage {
+        self.render_highlighted(None)
+    }
+
+    /// Like [`Builder::render`], but draws one of the buttons as if the mouse was on it.
+    pub fn render_highlighted(self, highlight: Option<Highlight>) -> RgbaImage {
+        let strings = self.strings.clone().unwrap_or_else(Strings::from_env);
+        let theme = match self.theme {
+            Theme::System => Theme::Light,
+            theme => theme,
+        };
+        let mut ui = self.ui(theme, &strings);
+        if self.rounded {
+            ui = ui.with_frame();
+        }
+        match highlight {
+            Some(Highlight::Hovered(index)) => ui.highlight_button(index, false),
+            Some(Highlight::Pressed(index)) => ui.highlight_button(index, true),
+            None => {}
+        }
+        ui.redraw();
+        RgbaImage::from_canvas(ui.contents())
+    }
+
+    /// Returns the title of the dialog, which defaults to one matching the icon.
+    fn title_or_default<'a>(&'a self, strings: &'a Strings) -> &'a str {
+        let title = match &self.title {
+            Some(title) => title.as_str(),
+            None => match self.icon {
+                Icon::Error => &*strings.error,
+                Icon::Warning => &*strings.warning,
+                Icon::Info => &*strings.info,
+                Icon::Question => &*strings.question,
+            },
+        };
+        title.trim_end_matches('\0')
+    }
+
+    /// Creates the dialog contents.
+    fn ui(&self, theme: Theme, strings: &Strings) -> Ui {
+        let message = self.message.as_deref().unwrap_or_default();
+        let labels = self.button_preset.labels(strings);
+        let title = self.title_or_default(strings);
+        let mut ui = Ui::new(self.icon, theme, title, message, &labels);
+        if let Some(details) = &self.details {
+            ui = ui.with_details(details);
+            ui.set_details_labels(&strings.show_details, &strings.hide_details);
+        }
+        ui
+    }
+
     /// Displays the dialog and blocks until the dialog is closed.
     ///
     /// Returns an [`Answer`] indicating which dialog button was clicked.
@@ -107,67 +322,238 @@ impl Builder {
     ///
     /// An error may occur when communicating with the X server.
     pub fn show(self) -> Result<Answer, Error> {
-        let title = match self.title {
-            Some(title) => title,
-            None => match self.icon {
-                Icon::Error => "Error\0".into(),
-                Icon::Warning => "Warning\0".into(),
-                Icon::Info => "Info\0".into(),
-                Icon::Question => "Question\0".into(),
+        let strings = self.strings.clone().unwrap_or_else(Strings::from_env);
+        let title = self.title_or_default(&strings);
+        let message = self.message.as_deref().unwrap_or_default();
+        let labels = self.button_preset.labels(&strings);
+
+        if let Some(inputs) = &self.script {
+            // The colors don't matter here, so don't bother asking the desktop for them.
+            let theme = match self.theme {
+                Theme::System => Theme::Light,
+                theme => theme,
+            };
+            return script::run(&mut self.ui(theme, &strings), inputs);
+        }
+
+        let conn = x11::Connection::connect()?;
+        // Not every desktop runs an XSETTINGS manager, so treat failures like an empty set.
+        let xsettings = conn.xsettings().unwrap_or_default();
+        // Resources are looked up with the names in `WM_CLASS`, like in other X clients.
+        let app_id = self.app_id.as_deref().unwrap_or("alerta");
+        let resources = conn
+            .resources()
+            .unwrap_or_default()
+            .with_name(x11::instance_name(app_id), x11::class_name(app_id));
+
+        // When following the system theme, keep watching it for changes (if possible).
+        let (theme, mut theme_watcher) = match self.theme {
+            Theme::System => match ThemeWatcher::new() {
+                Ok((watcher, theme)) => (theme, Some(watcher)),
+                Err(_) => {
+                    let theme = xsettings.theme().map(Theme::with_desktop_colors);
+                    (theme.unwrap_or_else(Theme::detect), None)
+                }
             },
+            theme => (theme, None),
+        };
+        // X resources customize the system theme, but don't override an explicitly chosen one.
+        let theme = if self.theme == Theme::System {
+            resources.apply_colors(theme)
+        } else {
+            theme
         };
 
-        let mut ui = Ui::new(
-            self.icon,
-            self.theme.unwrap_or_else(Theme::detect),
-            &self.message.unwrap_or_default(),
-            self.button_preset.strings(),
-        );
-
-        let conn = x11::Connection::connect()?;
+        let mut ui = self
+            .ui(theme, &strings)
+            .with_font_prefs(&resources.apply_font_prefs(xsettings.font_prefs()));
+        // Without a window manager, nobody else adds a title bar.
+        let unmanaged = !conn.has_window_manager()?;
+        if unmanaged {
+            ui = ui.with_title_bar();
+        }
+        // The corners and the shadow are only translucent if a compositor is running.
+        let translucent = self.rounded && conn.supports_translucency()?;
+        if translucent {
+            ui = ui.with_frame();
+        }
 
-        let win = X11Window::create(
+        // Don't let the dialog grow taller than the screen (leaving some room for window
+        // decorations).
+        let work_area = conn.work_area()?;
+        ui.set_max_height(i32::from(work_area.height) - DECORATION_ALLOWANCE - 2 * ui.margin());
+
+        let urgent = self.urgent.unwrap_or(self.icon == Icon::Error);
+        let state = WindowState {
+            above: self.above,
+            sticky: self.sticky,
+            skip_taskbar: self.skip_taskbar,
+            skip_pager: self.skip_pager,
+            urgent,
+            unmanaged,
+            translucent,
+        };
+        // Set by launchers that implement the startup notification protocol.
+        let startup_id = env::var("DESKTOP_STARTUP_ID")
+            .ok()
+            .filter(|id| !id.is_empty());
+        let mut win = X11Window::create(
             conn.clone(),
-            ui.canvas.width() as u16,
-            ui.canvas.height() as u16,
+            ui.contents().width() as u16,
+            ui.contents().height() as u16,
+            state,
         )?
-        .with_title(title)?;
+        .with_class(app_id)?
+        .with_title(title.to_string())?;
+        if let Some(id) = &startup_id {
+            win = win.with_startup_id(id)?;
+        }
 
-        win.set_contents(&ui.canvas)?;
+        let size_limits = |ui: &Ui| {
+            let size = (ui.contents().width() as u16, ui.contents().height() as u16);
+            if self.resizable {
+                let min = ui.min_size();
+                ((min.width as u16, min.height as u16), None)
+            } else {
+                (size, Some(size))
+            }
+        };
+        let mut limits = size_limits(&ui);
+        let mut size = (ui.contents().width(), ui.contents().height());
+        win.set_size_limits(limits.0, limits.1)?;
+        if translucent {
+            win.set_frame_extents(ui.margin() as u32)?;
+        }
+        // The frame of translucent windows is only drawn locally.
+        let mut renderer = if translucent { None } else { win.renderer() };
+        present(&win, &mut ui, &mut renderer)?;
 
         win.show()?;
+        if let Some(id) = &startup_id {
+            win.complete_startup(id)?;
+        }
+        if self.bell.unwrap_or(urgent) {
+            // Not being heard is no reason to fail.
+            win.ring_bell(self.icon.sound_name()).ok();
+        }
 
-        let mut pressed = false;
-        loop {
-            let mut process_event = |event| {
-                match event {
-                    WindowEvent::CursorMove(..) if pressed => {
-                        win.start_drag().ok();
-                    }
-                    WindowEvent::ButtonPress(MouseButton::Left) => pressed = true,
-                    WindowEvent::ButtonRelease(MouseButton::Left) => pressed = false,
-                    _ => {}
+        // Screen readers are optional, so the dialog works without them.
+        let mut a11y = Accessibility::new(title, message, &labels).ok();
+        if let Some(a) = &mut a11y
+            && a.activate(0).is_err()
+        {
+            a11y = None;
+        }
+
+        let process_event = |ui: &mut Ui, event| -> Result<Option<Answer>, Error> {
+            match ui.process_event(event) {
+                Some(Action::Answer(answer)) => return Ok(Some(answer)),
+                Some(Action::StartDrag) => {
+                    win.start_drag().ok();
                 }
-                ui.process_event(event)
-            };
+                Some(Action::SetSelection(selection, text)) => {
+                    win.set_selection(selection, text)?;
+                }
+                None => {}
+            }
+            Ok(None)
+        };
 
-            let event = win.wait_for_event()?;
-            if let Some(answer) = process_event(event) {
-                return Ok(answer);
+        let answer = 'outer: loop {
+            // Messages that arrived while waiting for a method reply don't make the socket
+            // readable, so they have to be checked for first.
+            let wakeup = if theme_watcher.as_ref().is_some_and(|w| w.has_queued()) {
+                Wakeup::Readable(0)
+            } else if a11y.as_ref().is_some_and(|a| a.has_queued()) {
+                Wakeup::Readable(1)
+            } else {
+                win.wait_for_event_or(&[
+                    theme_watcher.as_ref().map(|w| w.fd()),
+                    a11y.as_ref().map(|a| a.fd()),
+                ])?
+            };
+            let event = match wakeup {
+                Wakeup::Event(event) => event,
+                Wakeup::Readable(0) => match theme_watcher.as_mut().map(|w| w.process()) {
+                    Some(Ok(Some(theme))) => {
+                        WindowEvent::ThemeChanged(resources.apply_colors(theme))
+                    }
+                    Some(Ok(None)) | None => continue,
+                    Some(Err(_)) => {
+                        // The bus connection is broken, so we can't get further updates.
+                        theme_watcher = None;
+                        continue;
+                    }
+                },
+                Wakeup::Readable(_) => match a11y.as_mut().map(|a| a.process()) {
+                    // A screen reader pressed a button.
+                    Some(Ok(Some(button))) => break Answer::Button(button),
+                    Some(Ok(None)) | None => continue,
+                    Some(Err(_)) => {
+                        a11y = None;
+                        continue;
+                    }
+                },
+            };
+            if let Some(answer) = process_event(&mut ui, event)? {
+                break answer;
             }
             // Batch all pending events together to limit the number of redraws.
             while let Some(event) = win.poll_for_event()? {
-                if let Some(answer) = process_event(event) {
-                    return Ok(answer);
+                if let Some(answer) = process_event(&mut ui, event)? {
+                    break 'outer answer;
+                }
+            }
+
+            // Expanding or collapsing the details changes the window size and size limits.
+            let new_limits = size_limits(&ui);
+            if new_limits != limits {
+                win.set_size_limits(new_limits.0, new_limits.1)?;
+                limits = new_limits;
+            }
+            // Only request a new window size if the dialog itself changed it. If the window
+            // manager disagrees with our limits, it will send back its own size, and insisting
+            // would never end.
+            let new_size = (ui.contents().width(), ui.contents().height());
+            if new_size != size {
+                size = new_size;
+                if (new_size.0 as u16, new_size.1 as u16) != win.size() {
+                    win.resize(new_size.0 as u16, new_size.1 as u16)?;
                 }
             }
+            // Only the parts of the dialog that changed are redrawn and sent to the X server.
+            present(&win, &mut ui, &mut renderer)?;
+        };
+
+        // The clipboard contents would vanish with our window, so hand them over to the clipboard
+        // manager (if there is one).
+        win.persist_clipboard()?;
+
+        Ok(answer)
+    }
+}
 
-            ui.redraw();
-            win.set_contents(&ui.canvas)?;
+/// Redraws the parts of the dialog that changed, and puts them into the window.
+///
+/// With a `renderer`, the dialog is drawn by the X server. If that fails, the renderer is dropped
+/// and the dialog is drawn locally from then on.
+fn present(win: &X11Window, ui: &mut Ui, renderer: &mut Option<Renderer>) -> Result<(), Error> {
+    if let Some(r) = renderer {
+        let mut frame = r.frame(win);
+        ui.update_with(&mut frame);
+        if frame.finish().is_ok() {
+            return Ok(());
         }
+        *renderer = None;
+        ui.invalidate();
     }
+    let damage = ui.update();
+    win.set_contents(ui.contents(), &damage)
 }
 
+/// Vertical screen space we leave for the window decorations added by the window manager.
+const DECORATION_ALLOWANCE: i32 = 64;
+
 /// A user response to a dialog.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Answer {
@@ -184,6 +570,16 @@ pub enum Answer {
     Button(usize),
 }
 
+/// A button state to draw in [`Builder::render_highlighted`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Highlight {
+    /// The mouse cursor is on the button with the given 0-based index.
+    Hovered(usize),
+    /// The button with the given 0-based index is being pressed.
+    Pressed(usize),
+}
+
 /// Presets of button groups.
 ///
 /// These presets define a couple of well-established button combinations, in the order that users
@@ -201,15 +597,16 @@ pub enum ButtonPreset {
 }
 
 impl ButtonPreset {
-    fn strings(&self) -> &[&str] {
-        match self {
-            ButtonPreset::Close => &["Close"],
-            ButtonPreset::Ok => &["OK"],
-            ButtonPreset::OkCancel => &["OK", "Cancel"],
-            ButtonPreset::RetryCancel => &["Retry", "Cancel"],
-            ButtonPreset::YesNo => &["Yes", "No"],
-            ButtonPreset::YesNoCancel => &["Yes", "No", "Cancel"],
-        }
+    fn labels<'a>(&self, strings: &'a Strings) -> Vec<&'a str> {
+        let labels = match self {
+            ButtonPreset::Close => &[&strings.close][..],
+            ButtonPreset::Ok => &[&strings.ok],
+            ButtonPreset::OkCancel => &[&strings.ok, &strings.cancel],
+            ButtonPreset::RetryCancel => &[&strings.retry, &strings.cancel],
+            ButtonPreset::YesNo => &[&strings.yes, &strings.no],
+            ButtonPreset::YesNoCancel => &[&strings.yes, &strings.no, &strings.cancel],
+        };
+        labels.iter().map(|label| &***label).collect()
     }
 }
 
@@ -224,7 +621,7 @@ impl FromStr for ButtonPreset {
             "retrycancel" => Self::RetryCancel,
             "yesno" => Self::YesNo,
             "yesnocancel" => Self::YesNoCancel,
-            _ => return Err(InvalidValue { _p: () }),
+            _ => return Err(InvalidValue::new()),
         })
     }
 }
@@ -249,12 +646,22 @@ impl FromStr for Icon {
             "warning" => Self::Warning,
             "info" => Self::Info,
             "question" => Self::Question,
-            _ => return Err(InvalidValue { _p: () }),
+            _ => return Err(InvalidValue::new()),
         })
     }
 }
 
 impl Icon {
+    /// Returns the name of the matching event sound in the freedesktop.org sound theme.
+    fn sound_name(self) -> &'static str {
+        match self {
+            Icon::Error => "dialog-error",
+            Icon::Warning => "dialog-warning",
+            Icon::Info => "dialog-information",
+            Icon::Question => "dialog-question",
+        }
+    }
+
     fn get(self) -> DrawTarget {
         let src: &[u8] = match self {
             Icon::Error => include_bytes!("../3rdparty/icons/dialog-error.qoi"),
@@ -285,112 +692,31 @@ impl Icon {
 /// Error returned by [`FromStr`] implementations.
 #[derive(Debug)]
 pub struct InvalidValue {
-    _p: (),
+    reason: Option<String>,
 }
 
-impl fmt::Display for InvalidValue {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str("invalid value")
+impl InvalidValue {
+    fn new() -> Self {
+        Self { reason: None }
     }
-}
-impl std::error::Error for InvalidValue {}
-
-/// Color theme selection.
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
-#[non_exhaustive]
-pub enum Theme {
-    #[default]
-    Light,
-    Dark,
-}
-
-impl FromStr for Theme {
-    type Err = InvalidValue;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s {
-            "light" => Self::Light,
-            "dark" => Self::Dark,
-            _ => return Err(InvalidValue { _p: () }),
-        })
-    }
-}
 
-impl Theme {
-    /// Attempts to detect the preferred color scheme of the desktop environment.
-    ///
-    /// X11 doesn't have a built-in mechanism to do this, so we run these external commands in order
-    /// (using the first one that works):
-    ///
-    /// - `dbus-send org.freedesktop.portal.Settings.Read org.freedesktop.appearance color-scheme`
-    /// - `gsettings get org.gnome.desktop.interface color-scheme`
-    ///
-    /// The latter doesn't properly update on KDE when changing themes, so the first option is
-    /// preferred (and is also generally newer and less vendor-specific).
-    fn detect() -> Theme {
-        Self::detect_dbus()
-            .or_else(|_| Self::detect_gsettings())
-            .unwrap_or(Theme::Light)
-    }
-
-    fn detect_dbus() -> Result<Theme, Error> {
-        // https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.Settings.html
-        // Invocation taken from `sctk-adwaita`.
-        let out = Command::new("dbus-send")
-            .args([
-                "--reply-timeout=100",
-                "--print-reply=literal",
-                "--dest=org.freedesktop.portal.Desktop",
-                "/org/freedesktop/portal/desktop",
-                "org.freedesktop.portal.Settings.Read",
-                "string:org.freedesktop.appearance",
-                "string:color-scheme",
-            ])
-            .output()
-            .map_err(err)?;
-        if !out.status.success() {
-            return Err(Error::new(format!(
-                "failed to query DBus: {}",
-                String::from_utf8_lossy(&out.stderr)
-            )));
-        }
-
-        let stdout = str::from_utf8(&out.stdout).map_err(err)?.trim();
-        if stdout.ends_with("uint32 1") {
-            Ok(Theme::Dark)
-        } else if stdout.ends_with("uint32 2") {
-            Ok(Theme::Light)
-        } else {
-            Err(Error::new(format!(
-                "unknown color scheme preference: {stdout}"
-            )))
+    fn with_reason(reason: impl Into<String>) -> Self {
+        Self {
+            reason: Some(reason.into()),
         }
     }
+}
 
-    fn detect_gsettings() -> Result<Theme, Error> {
-        let out = Command::new("gsettings")
-            .args(["get", "org.gnome.desktop.interface", "color-scheme"])
-            .output()
-            .map_err(err)?;
-        if !out.status.success() {
-            return Err(Error::new(format!(
-                "failed to query gsettings: {}",
-                String::from_utf8_lossy(&out.stderr)
-            )));
-        }
-        let stdout = str::from_utf8(&out.stdout)
-            .map_err(err)?
-            .trim()
-            .trim_matches('\'');
-        match stdout {
-            "prefer-dark" => Ok(Theme::Dark),
-            "prefer-light" => Ok(Theme::Light),
-            _ => Err(Error::new(format!(
-                "unknown color scheme preference: {stdout}"
-            ))),
+impl fmt::Display for InvalidValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid value")?;
+        if let Some(reason) = &self.reason {
+            write!(f, ": {reason}")?;
         }
+        Ok(())
     }
 }
+impl std::error::Error for InvalidValue {}
 
 #[derive(Debug)]
 enum WindowEvent {
@@ -401,6 +727,13 @@ enum WindowEvent {
     CursorLeave,
     ButtonPress(MouseButton),
     ButtonRelease(MouseButton),
+    /// Mouse wheel movement, in notches (positive values scroll down).
+    Scroll(i16),
+    KeyPress(Key, Modifiers),
+    /// The window was resized to the given width and height.
+    Resized(u16, u16),
+    /// The desktop's preferred color scheme has changed.
+    ThemeChanged(Theme),
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -415,3 +748,44 @@ enum MouseButton {
     Middle,
     Right,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Escape,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    /// A key that produces a character (ignoring modifiers like Shift).
+    Char(char),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Modifiers {
+    ctrl: bool,
+    shift: bool,
+    alt: bool,
+}
+
+impl Modifiers {
+    const NONE: Self = Self {
+        ctrl: false,
+        shift: false,
+        alt: false,
+    };
+    const CTRL: Self = Self {
+        ctrl: true,
+        ..Self::NONE
+    };
+}
+
+/// A selection buffer that text can be copied into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Selection {
+    /// The clipboard, filled by Ctrl+C.
+    Clipboard,
+    /// The primary selection, filled by selecting text with the mouse.
+    Primary,
+}
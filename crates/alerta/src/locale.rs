@@ -0,0 +1,219 @@
+//! Translations of the texts alerta displays on its own.
+
+use std::{borrow::Cow, env};
+
+/// The texts of the preset buttons, default window titles and the details toggle.
+///
+/// alerta includes translations for a number of common languages, and picks one based on the
+/// locale environment variables (see [`Strings::from_env`]). Individual strings can be replaced by
+/// the application:
+///
+/// ```
+/// # use alerta::Strings;
+/// let mut strings = Strings::for_locale("de_DE.UTF-8");
+/// assert_eq!(strings.cancel, "Abbrechen");
+/// strings.ok = "Verstanden".into();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Strings {
+    pub close: Cow<'static, str>,
+    pub ok: Cow<'static, str>,
+    pub cancel: Cow<'static, str>,
+    pub retry: Cow<'static, str>,
+    pub yes: Cow<'static, str>,
+    pub no: Cow<'static, str>,
+    /// Default title of dialogs with [`Icon::Error`][crate::Icon::Error].
+    pub error: Cow<'static, str>,
+    /// Default title of dialogs with [`Icon::Warning`][crate::Icon::Warning].
+    pub warning: Cow<'static, str>,
+    /// Default title of dialogs with [`Icon::Info`][crate::Icon::Info].
+    pub info: Cow<'static, str>,
+    /// Default title of dialogs with [`Icon::Question`][crate::Icon::Question].
+    pub question: Cow<'static, str>,
+    /// Label of the toggle that expands the details section.
+    pub show_details: Cow<'static, str>,
+    /// Label of the toggle that collapses the details section.
+    pub hide_details: Cow<'static, str>,
+}
+
+impl Default for Strings {
+    /// Returns the English strings.
+    fn default() -> Self {
+        Self::ENGLISH
+    }
+}
+
+macro_rules! strings {
+    (
+        $close:literal, $ok:literal, $cancel:literal, $retry:literal, $yes:literal, $no:literal,
+        $error:literal, $warning:literal, $info:literal, $question:literal,
+        $show_details:literal, $hide_details:literal $(,)?
+    ) => {
+        Strings {
+            close: Cow::Borrowed($close),
+            ok: Cow::Borrowed($ok),
+            cancel: Cow::Borrowed($cancel),
+            retry: Cow::Borrowed($retry),
+            yes: Cow::Borrowed($yes),
+            no: Cow::Borrowed($no),
+            error: Cow::Borrowed($error),
+            warning: Cow::Borrowed($warning),
+            info: Cow::Borrowed($info),
+            question: Cow::Borrowed($question),
+            show_details: Cow::Borrowed($show_details),
+            hide_details: Cow::Borrowed($hide_details),
+        }
+    };
+}
+
+/// Built-in translations, by language code (and optionally, territory).
+///
+/// Only languages that the bundled font can display are included.
+pub(crate) const TRANSLATIONS: &[(&str, Strings)] = &[
+    (
+        "cs",
+        strings! {
+            "Zavřít", "OK", "Zrušit", "Opakovat", "Ano", "Ne",
+            "Chyba", "Varování", "Informace", "Otázka",
+            "Zobrazit podrobnosti", "Skrýt podrobnosti",
+        },
+    ),
+    (
+        "da",
+        strings! {
+            "Luk", "OK", "Annuller", "Prøv igen", "Ja", "Nej",
+            "Fejl", "Advarsel", "Information", "Spørgsmål",
+            "Vis detaljer", "Skjul detaljer",
+        },
+    ),
+    (
+        "de",
+        strings! {
+            "Schließen", "OK", "Abbrechen", "Wiederholen", "Ja", "Nein",
+            "Fehler", "Warnung", "Information", "Frage",
+            "Details anzeigen", "Details ausblenden",
+        },
+    ),
+    ("en", Strings::ENGLISH),
+    (
+        "es",
+        strings! {
+            "Cerrar", "Aceptar", "Cancelar", "Reintentar", "Sí", "No",
+            "Error", "Advertencia", "Información", "Pregunta",
+            "Mostrar detalles", "Ocultar detalles",
+        },
+    ),
+    (
+        "fi",
+        strings! {
+            "Sulje", "OK", "Peruuta", "Yritä uudelleen", "Kyllä", "Ei",
+            "Virhe", "Varoitus", "Tiedote", "Kysymys",
+            "Näytä tiedot", "Piilota tiedot",
+        },
+    ),
+    (
+        "fr",
+        strings! {
+            "Fermer", "OK", "Annuler", "Réessayer", "Oui", "Non",
+            "Erreur", "Avertissement", "Information", "Question",
+            "Afficher les détails", "Masquer les détails",
+        },
+    ),
+    (
+        "it",
+        strings! {
+            "Chiudi", "OK", "Annulla", "Riprova", "Sì", "No",
+            "Errore", "Avviso", "Informazione", "Domanda",
+            "Mostra dettagli", "Nascondi dettagli",
+        },
+    ),
+    (
+        "nb",
+        strings! {
+            "Lukk", "OK", "Avbryt", "Prøv igjen", "Ja", "Nei",
+            "Feil", "Advarsel", "Informasjon", "Spørsmål",
+            "Vis detaljer", "Skjul detaljer",
+        },
+    ),
+    (
+        "nl",
+        strings! {
+            "Sluiten", "OK", "Annuleren", "Opnieuw proberen", "Ja", "Nee",
+            "Fout", "Waarschuwing", "Informatie", "Vraag",
+            "Details tonen", "Details verbergen",
+        },
+    ),
+    (
+        "pl",
+        strings! {
+            "Zamknij", "OK", "Anuluj", "Ponów", "Tak", "Nie",
+            "Błąd", "Ostrzeżenie", "Informacja", "Pytanie",
+            "Pokaż szczegóły", "Ukryj szczegóły",
+        },
+    ),
+    (
+        "pt",
+        strings! {
+            "Fechar", "OK", "Cancelar", "Tentar novamente", "Sim", "Não",
+            "Erro", "Aviso", "Informação", "Pergunta",
+            "Mostrar detalhes", "Ocultar detalhes",
+        },
+    ),
+    (
+        "sv",
+        strings! {
+            "Stäng", "OK", "Avbryt", "Försök igen", "Ja", "Nej",
+            "Fel", "Varning", "Information", "Fråga",
+            "Visa detaljer", "Dölj detaljer",
+        },
+    ),
+    (
+        "tr",
+        strings! {
+            "Kapat", "Tamam", "İptal", "Yeniden dene", "Evet", "Hayır",
+            "Hata", "Uyarı", "Bilgi", "Soru",
+            "Ayrıntıları göster", "Ayrıntıları gizle",
+        },
+    ),
+];
+
+impl Strings {
+    /// The English strings, which are used when no translation is available.
+    pub const ENGLISH: Self = strings! {
+        "Close", "OK", "Cancel", "Retry", "Yes", "No",
+        "Error", "Warning", "Info", "Question",
+        "Show details", "Hide details",
+    };
+
+    /// Returns the built-in translation for a POSIX locale name like `de_DE.UTF-8`.
+    ///
+    /// A plain language code like `de` works too. Falls back to English if there is no translation
+    /// for the language.
+    pub fn for_locale(locale: &str) -> Self {
+        // language[_territory][.codeset][@modifier]
+        let locale = locale.split(['.', '@']).next().unwrap_or_default();
+        let language = locale.split(['_', '-']).next().unwrap_or_default();
+        TRANSLATIONS
+            .iter()
+            .find(|(lang, _)| lang.eq_ignore_ascii_case(locale))
+            .or_else(|| {
+                TRANSLATIONS
+                    .iter()
+                    .find(|(lang, _)| lang.eq_ignore_ascii_case(language))
+            })
+            .map_or(Self::ENGLISH, |(_, strings)| strings.clone())
+    }
+
+    /// Returns the built-in translation for the user's locale.
+    ///
+    /// Like other programs, this uses the first one of the `LC_ALL`, `LC_MESSAGES` and `LANG`
+    /// environment variables that is set to a non-empty value.
+    pub fn from_env() -> Self {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|locale| !locale.is_empty());
+        locale.map_or(Self::ENGLISH, |locale| Self::for_locale(&locale))
+    }
+}
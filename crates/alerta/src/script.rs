@@ -0,0 +1,71 @@
+//! Scripted input for testing code that shows dialogs, without an X server.
+
+use crate::{
+    Answer, Key, Modifiers, MouseButton, WindowEvent,
+    error::Error,
+    ui::{Action, Ui},
+};
+
+/// A user interaction with a dialog, for [`Builder::script`](crate::Builder::script).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Input {
+    /// Clicks the button with the given 0-based index.
+    ClickButton(usize),
+    /// Types the given text, one key at a time.
+    ///
+    /// `'\x1b'` presses Escape.
+    Type(String),
+    /// Presses Escape, which closes the dialog.
+    Escape,
+    /// Closes the window, like the close button in the window frame does.
+    Close,
+}
+
+impl Input {
+    fn events(&self, ui: &Ui) -> Result<Vec<WindowEvent>, Error> {
+        let key = |key| WindowEvent::KeyPress(key, Modifiers::NONE);
+        Ok(match self {
+            Input::ClickButton(index) => {
+                let pos = ui.button_position(*index).ok_or_else(|| {
+                    Error::new(format!(
+                        "cannot click button {index}, the dialog has no such button"
+                    ))
+                })?;
+                vec![
+                    WindowEvent::CursorEnter(pos),
+                    WindowEvent::ButtonPress(MouseButton::Left),
+                    WindowEvent::ButtonRelease(MouseButton::Left),
+                    WindowEvent::CursorLeave,
+                ]
+            }
+            Input::Type(text) => text
+                .chars()
+                .map(|c| match c {
+                    '\x1b' => key(Key::Escape),
+                    c => key(Key::Char(c)),
+                })
+                .collect(),
+            Input::Escape => vec![key(Key::Escape)],
+            Input::Close => vec![WindowEvent::CloseRequested],
+        })
+    }
+}
+
+/// Feeds `inputs` into `ui` and returns the answer they lead to.
+///
+/// Between inputs, the dialog is updated like in the event loop of a displayed dialog.
+pub(crate) fn run(ui: &mut Ui, inputs: &[Input]) -> Result<Answer, Error> {
+    ui.update();
+    for input in inputs {
+        for event in input.events(ui)? {
+            if let Some(Action::Answer(answer)) = ui.process_event(event) {
+                return Ok(answer);
+            }
+        }
+        ui.update();
+    }
+    Err(Error::new(
+        "the scripted input ended without closing the dialog",
+    ))
+}
@@ -1,8 +1,36 @@
-use std::fs;
+use std::{
+    borrow::Cow,
+    env,
+    f32::consts::PI,
+    fs,
+    io::{Read, Write},
+    os::unix::net::UnixListener,
+    process,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
 
-use raqote::DrawTarget;
+use ab_glyph::{Font, FontArc};
+use euclid::default::Rect;
+use raqote::{DrawTarget, Image, IntPoint, IntRect, Path, PathBuilder};
+use x11rb::protocol::render::Triangle;
 
-use crate::{ButtonPreset, Icon, Theme, ui::Ui};
+use crate::{
+    Answer, ButtonPreset, CursorPos, Highlight, Icon, Input, Key, Modifiers, MouseButton, Palette,
+    Rgb, RgbaImage, Selection, Strings, Theme, WindowEvent,
+    a11y::Accessibility,
+    dbus::{self, Message, MessageType, Value},
+    image,
+    locale::TRANSLATIONS,
+    theme::{self, GtkColors, ThemeWatcher},
+    ui::{Action, FRAME_MARGIN, FontPrefs, Painter, Text, Ui},
+    x11::{
+        self, XResources, XSetting, XSettings,
+        pixels::{self, CUBE_LEVELS, PixelFormat},
+        render,
+    },
+};
 
 fn snap(name: &str, image: &DrawTarget) {
     let path = format!("src/snap/{name}.png");
@@ -25,13 +53,14 @@ const ZWSP: &str = "\u{200b}";
 fn textwrap() {
     snap(
         "textwrap",
-        &Ui::new(Icon::Info, Theme::Light, IPSUM, &["OK"]).canvas,
+        &Ui::new(Icon::Info, Theme::Light, "Title", IPSUM, &["OK"]).canvas,
     );
     snap(
         "nbsp",
         &Ui::new(
             Icon::Info,
             Theme::Light,
+            "Title",
             &IPSUM
                 .split_inclusive('.')
                 .next()
@@ -43,7 +72,14 @@ fn textwrap() {
     );
     snap(
         "zwsp",
-        &Ui::new(Icon::Info, Theme::Light, &IPSUM.replace(' ', ZWSP), &["OK"]).canvas,
+        &Ui::new(
+            Icon::Info,
+            Theme::Light,
+            "Title",
+            &IPSUM.replace(' ', ZWSP),
+            &["OK"],
+        )
+        .canvas,
     );
 }
 
@@ -54,8 +90,9 @@ fn buttons() {
         &Ui::new(
             Icon::Warning,
             Theme::Light,
+            "Title",
             "Buttons",
-            ButtonPreset::YesNoCancel.strings(),
+            &ButtonPreset::YesNoCancel.labels(&Strings::ENGLISH),
         )
         .canvas,
     );
@@ -64,8 +101,9 @@ fn buttons() {
         &Ui::new(
             Icon::Warning,
             Theme::Light,
+            "Title",
             "Buttons",
-            ButtonPreset::RetryCancel.strings(),
+            &ButtonPreset::RetryCancel.labels(&Strings::ENGLISH),
         )
         .canvas,
     );
@@ -75,7 +113,14 @@ fn buttons() {
 fn dark_theme() {
     snap(
         "dark-theme",
-        &Ui::new(Icon::Question, Theme::Dark, IPSUM, &["Yes", "No", "Cancel"]).canvas,
+        &Ui::new(
+            Icon::Question,
+            Theme::Dark,
+            "Title",
+            IPSUM,
+            &["Yes", "No", "Cancel"],
+        )
+        .canvas,
     );
 }
 
@@ -86,8 +131,9 @@ fn icons() {
         &Ui::new(
             Icon::Error,
             Theme::Light,
+            "Title",
             "Error",
-            ButtonPreset::RetryCancel.strings(),
+            &ButtonPreset::RetryCancel.labels(&Strings::ENGLISH),
         )
         .canvas,
     );
@@ -96,9 +142,1549 @@ fn icons() {
         &Ui::new(
             Icon::Question,
             Theme::Light,
+            "Title",
             "Huh? Wha?",
-            ButtonPreset::YesNo.strings(),
+            &ButtonPreset::YesNo.labels(&Strings::ENGLISH),
         )
         .canvas,
     );
 }
+
+#[test]
+fn selection() {
+    let mut ui = Ui::new(Icon::Info, Theme::Light, "Title", IPSUM, &["OK"]);
+    ui.process_event(WindowEvent::CursorEnter(CursorPos { x: 150, y: 40 }));
+    ui.process_event(WindowEvent::ButtonPress(MouseButton::Left));
+    ui.process_event(WindowEvent::CursorMove(CursorPos { x: 250, y: 90 }));
+    let action = ui.process_event(WindowEvent::ButtonRelease(MouseButton::Left));
+    let Some(Action::SetSelection(Selection::Primary, text)) = action else {
+        panic!("unexpected action {action:?}");
+    };
+    assert_eq!(
+        text,
+        "used in graphic design, publishing, and web development. Its purpose is to permit a page layout to be designed, independently of the copy that will subsequently popu"
+    );
+
+    ui.redraw();
+    snap("selection", &ui.canvas);
+}
+
+#[test]
+fn copy_dialog() {
+    let mut ui = Ui::new(
+        Icon::Info,
+        Theme::Light,
+        "Title",
+        "Message",
+        &ButtonPreset::OkCancel.labels(&Strings::ENGLISH),
+    );
+    let action = ui.process_event(WindowEvent::KeyPress(Key::Char('c'), Modifiers::CTRL));
+    let Some(Action::SetSelection(Selection::Clipboard, text)) = action else {
+        panic!("unexpected action {action:?}");
+    };
+    assert_eq!(
+        text,
+        "---------------------------\n\
+        Title\n\
+        ---------------------------\n\
+        Message\n\
+        ---------------------------\n\
+        OK   Cancel\n\
+        ---------------------------\n"
+    );
+}
+
+#[test]
+fn scroll() {
+    let text = [IPSUM; 4].join("\n\n");
+    let mut ui = Ui::new(Icon::Info, Theme::Light, "Title", &text, &["OK"]);
+    ui.set_max_height(300);
+    assert_eq!(ui.canvas.height(), 300);
+
+    ui.process_event(WindowEvent::Scroll(2));
+    ui.process_event(WindowEvent::KeyPress(Key::PageDown, Modifiers::NONE));
+    ui.redraw();
+    snap("scroll", &ui.canvas);
+}
+
+const BACKTRACE: &str = "thread 'main' panicked at src/main.rs:4:5:
+explicit panic
+stack backtrace:
+   0: __rustc::rust_begin_unwind
+             at /rustc/library/std/src/panicking.rs:689:5
+   1: core::panicking::panic_fmt
+             at /rustc/library/core/src/panicking.rs:80:14
+   2: core::panicking::panic_explicit
+             at /rustc/library/core/src/panicking.rs:260:5
+   3: app::main::panic_cold_explicit
+             at /rustc/library/core/src/panic.rs:88:13
+   4: app::main
+             at ./src/main.rs:4:5
+   5: core::ops::function::FnOnce::call_once
+             at /rustc/library/core/src/ops/function.rs:250:5
+note: Some details are omitted, run with `RUST_BACKTRACE=full` for a verbose backtrace.";
+
+#[test]
+fn details() {
+    let mut ui = Ui::new(
+        Icon::Error,
+        Theme::Light,
+        "Title",
+        "The application has crashed.",
+        &["Close"],
+    )
+    .with_details(BACKTRACE);
+    snap("details-collapsed", &ui.canvas);
+
+    ui.process_event(WindowEvent::CursorEnter(CursorPos { x: 30, y: 90 }));
+    ui.process_event(WindowEvent::ButtonPress(MouseButton::Left));
+    ui.process_event(WindowEvent::ButtonRelease(MouseButton::Left));
+    ui.redraw();
+    snap("details-expanded", &ui.canvas);
+}
+
+#[test]
+fn title_bar() {
+    let mut ui = Ui::new(
+        Icon::Warning,
+        Theme::Dark,
+        "Low disk space",
+        "Only 2% of the disk are left.",
+        &["OK"],
+    )
+    .with_title_bar();
+    ui.process_event(WindowEvent::CursorEnter(CursorPos {
+        x: ui.canvas.width() as i16 - 5,
+        y: 5,
+    }));
+    ui.redraw();
+    snap("title-bar", &ui.canvas);
+
+    ui.process_event(WindowEvent::ButtonPress(MouseButton::Left));
+    assert!(matches!(
+        ui.process_event(WindowEvent::ButtonRelease(MouseButton::Left)),
+        Some(Action::Answer(Answer::Closed))
+    ));
+}
+
+#[test]
+fn partial_redraw() {
+    let mut ui = Ui::new(Icon::Info, Theme::Light, "Title", IPSUM, &["OK", "Cancel"])
+        .with_details(BACKTRACE)
+        .with_title_bar();
+    ui.set_max_height(160);
+    let (width, height) = (ui.canvas.width(), ui.canvas.height());
+    assert_eq!(ui.update().len(), 1);
+    assert!(ui.update().is_empty());
+
+    let cursor = |x: i32, y: i32| CursorPos {
+        x: x as i16,
+        y: y as i16,
+    };
+    let ok = cursor(30, height - 25);
+    let cancel = cursor(width - 30, height - 25);
+    let events = [
+        (WindowEvent::CursorEnter(ok), 1),
+        (WindowEvent::ButtonPress(MouseButton::Left), 1),
+        (WindowEvent::CursorMove(cancel), 2),
+        (WindowEvent::CursorMove(cursor(width - 5, 5)), 2),
+        (WindowEvent::CursorMove(cursor(150, 60)), 1),
+        (WindowEvent::Scroll(1), 1),
+        (WindowEvent::RedrawRequested, 1),
+    ];
+    for (event, damaged) in events {
+        let name = format!("{event:?}");
+        ui.process_event(event);
+        let damage = ui.update();
+        assert_eq!(damage.len(), damaged, "{name}");
+        // Redrawing only the changed parts has to give the same result as redrawing everything.
+        let partial = ui.canvas.get_data().to_vec();
+        ui.redraw();
+        assert!(partial == ui.canvas.get_data(), "{name}");
+    }
+}
+
+#[test]
+fn rounded_frame() {
+    let mut ui = Ui::new(
+        Icon::Question,
+        Theme::Light,
+        "Title",
+        "Save changes?",
+        &["Yes", "No"],
+    )
+    .with_frame();
+    let margin = ui.margin();
+    let (width, height) = (ui.contents().width(), ui.contents().height());
+    assert_eq!(
+        (width - ui.canvas.width(), height - ui.canvas.height()),
+        (2 * margin, 2 * margin)
+    );
+    assert_eq!(ui.min_size().width, ui.contents().width());
+    assert_eq!(
+        ui.update(),
+        [IntRect::new(
+            IntPoint::new(margin, margin),
+            IntPoint::new(width - margin, height - margin),
+        )]
+    );
+    snap("rounded-frame", ui.contents());
+
+    // The corners and the space around the dialog are (partly) transparent.
+    let alpha = |x: i32, y: i32| ui.contents().get_data()[(y * width + x) as usize] >> 24;
+    assert_eq!(alpha(0, 0), 0);
+    assert!(alpha(margin, margin) < 32);
+    assert!(alpha(width / 2, height - margin / 2) > 0);
+    assert_eq!(alpha(width / 2, height / 2), 255);
+
+    // Cursor positions are relative to the window, so the margin has to be skipped.
+    ui.process_event(WindowEvent::CursorEnter(CursorPos {
+        x: (width - margin - 30) as i16,
+        y: (height - margin - 25) as i16,
+    }));
+    ui.process_event(WindowEvent::ButtonPress(MouseButton::Left));
+    let partial = ui.update();
+    assert!(
+        partial
+            .iter()
+            .all(|rect| rect.min.x >= margin && rect.min.y >= margin)
+    );
+    let contents = ui.contents().get_data().to_vec();
+    ui.redraw();
+    assert!(contents == ui.contents().get_data());
+    assert!(matches!(
+        ui.process_event(WindowEvent::ButtonRelease(MouseButton::Left)),
+        Some(Action::Answer(Answer::Button(1)))
+    ));
+
+    // Resizing works with window sizes, including the margin.
+    ui.resize(width + 40, height);
+    assert_eq!(ui.contents().width(), width + 40);
+    assert_eq!(ui.canvas.width(), width + 40 - 2 * margin);
+}
+
+#[test]
+fn render() {
+    let builder = || {
+        crate::alerta()
+            .title("Save")
+            .message("Save changes?")
+            .icon(Icon::Question)
+            .button_preset(ButtonPreset::YesNo)
+            .theme(Theme::Light)
+            .locale("en")
+    };
+    let mut ui = Ui::new(
+        Icon::Question,
+        Theme::Light,
+        "Save",
+        "Save changes?",
+        &["Yes", "No"],
+    );
+    ui.redraw();
+    let image = builder().render();
+    assert_eq!(
+        (image.width(), image.height()),
+        (ui.canvas.width() as u32, ui.canvas.height() as u32)
+    );
+    let argb = ui
+        .canvas
+        .get_data()
+        .iter()
+        .map(|p| p.rotate_left(8).to_be_bytes());
+    assert!(image.as_raw().chunks(4).eq(argb));
+
+    let hovered = builder().render_highlighted(Some(Highlight::Hovered(1)));
+    let pressed = builder().render_highlighted(Some(Highlight::Pressed(1)));
+    assert_ne!(hovered, image);
+    assert_ne!(pressed, hovered);
+    assert_eq!(
+        builder().render_highlighted(Some(Highlight::Pressed(2))),
+        image
+    );
+    // Highlighting only changes how the button is drawn, like in a displayed dialog.
+    let ui_image = |ui: &Ui| RgbaImage::from_canvas(ui.contents());
+    ui.highlight_button(1, true);
+    ui.redraw();
+    assert_eq!(ui_image(&ui), pressed);
+
+    // Rounded dialogs are drawn with their shadow and transparent corners.
+    let rounded = builder().rounded(true).render();
+    assert_eq!(rounded.width(), image.width() + 2 * FRAME_MARGIN as u32);
+    assert_eq!(rounded.as_raw()[3], 0);
+
+    let png = pressed.to_png();
+    assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+    assert!(png.ends_with(b"\0\0\0\0IEND\xae\x42\x60\x82"));
+    assert!(png.len() < pressed.as_raw().len() / 10);
+    let ihdr = &png[8..33];
+    assert_eq!(&ihdr[4..8], b"IHDR");
+    assert_eq!(
+        ihdr[8..16],
+        [
+            &image.width().to_be_bytes()[..],
+            &image.height().to_be_bytes()
+        ]
+        .concat()
+    );
+    assert_eq!(
+        u32::from_be_bytes(ihdr[21..].try_into().unwrap()),
+        image::crc32(&ihdr[4..21])
+    );
+}
+
+#[test]
+fn scripted_input() {
+    let answer = |inputs: Vec<Input>| {
+        crate::alerta()
+            .message(IPSUM)
+            .details(BACKTRACE)
+            .button_preset(ButtonPreset::YesNoCancel)
+            .locale("en")
+            .script(inputs)
+            .show()
+    };
+    let cases = [
+        (vec![Input::ClickButton(1)], Answer::Button(1)),
+        (vec![Input::ClickButton(2)], Answer::Button(2)),
+        (vec![Input::Escape], Answer::Closed),
+        (vec![Input::Type("no\x1b".into())], Answer::Closed),
+        (vec![Input::Close], Answer::Closed),
+    ];
+    for (inputs, expected) in cases {
+        let name = format!("{inputs:?}");
+        assert_eq!(answer(inputs).unwrap(), expected, "{name}");
+    }
+    answer(vec![]).unwrap_err();
+    answer(vec![Input::Type("no".into())]).unwrap_err();
+    answer(vec![Input::ClickButton(3)]).unwrap_err();
+}
+
+/// Decodes a zlib stream written by [`image::zlib`], which uses a single deflate block with the
+/// fixed Huffman codes.
+fn inflate_fixed(data: &[u8]) -> Vec<u8> {
+    const LENGTHS: [(usize, u32); 29] = [
+        (3, 0),
+        (4, 0),
+        (5, 0),
+        (6, 0),
+        (7, 0),
+        (8, 0),
+        (9, 0),
+        (10, 0),
+        (11, 1),
+        (13, 1),
+        (15, 1),
+        (17, 1),
+        (19, 2),
+        (23, 2),
+        (27, 2),
+        (31, 2),
+        (35, 3),
+        (43, 3),
+        (51, 3),
+        (59, 3),
+        (67, 4),
+        (83, 4),
+        (99, 4),
+        (115, 4),
+        (131, 5),
+        (163, 5),
+        (195, 5),
+        (227, 5),
+        (258, 0),
+    ];
+    assert_eq!(data[..2], [0x78, 0x01]);
+    let mut pos = 16;
+    let mut bits = |n: u32, msb_first: bool| {
+        let mut value = 0;
+        for i in 0..n {
+            let bit = u32::from(data[pos / 8] >> (pos % 8) & 1);
+            pos += 1;
+            value = if msb_first {
+                value << 1 | bit
+            } else {
+                value | bit << i
+            };
+        }
+        value
+    };
+    assert_eq!(bits(3, false), 0b011);
+    let mut out = Vec::new();
+    loop {
+        let code = bits(7, true);
+        let symbol = if code < 24 {
+            256 + code
+        } else {
+            let code = code << 1 | bits(1, true);
+            match code {
+                0x30..0xc0 => code - 0x30,
+                0xc0..0xc8 => 280 + code - 0xc0,
+                _ => 144 + (code << 1 | bits(1, true)) - 0x190,
+            }
+        };
+        match symbol {
+            0..256 => out.push(symbol as u8),
+            256 => break,
+            _ => {
+                let (base, extra) = LENGTHS[symbol as usize - 257];
+                let len = base + bits(extra, false) as usize;
+                let code = bits(5, true) as usize;
+                let (base, extra) = match code {
+                    0..4 => (code + 1, 0),
+                    _ => {
+                        let extra = code as u32 / 2 - 1;
+                        ((2 + code % 2) << extra | 1, extra)
+                    }
+                };
+                let start = out.len() - (base + bits(extra, false) as usize);
+                for i in start..start + len {
+                    out.push(out[i]);
+                }
+            }
+        }
+    }
+    let end = pos.div_ceil(8);
+    assert_eq!(data[end..], image::adler32(&out).to_be_bytes());
+    out
+}
+
+#[test]
+fn png_compression() {
+    assert_eq!(image::adler32(b"Wikipedia"), 0x11e60398);
+    assert_eq!(image::crc32(b"IEND"), 0xae426082);
+    assert_eq!(image::zlib(b""), b"\x78\x01\x03\x00\x00\x00\x00\x01");
+
+    let mut data = IPSUM.repeat(3).into_bytes();
+    data.extend((0..100_000u32).map(|i| (i / 300 % 7) as u8));
+    data.extend((0..70_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8));
+    let compressed = image::zlib(&data);
+    assert!(compressed.len() < data.len() / 2);
+    assert!(inflate_fixed(&compressed) == data);
+}
+
+/// Draws like the canvas, except that text is put together from its glyph masks, the way the X
+/// server does it with the XRender painter.
+struct GlyphPainter(DrawTarget);
+
+impl Painter for GlyphPainter {
+    fn fill_rect(&mut self, rect: Rect<f32>, color: Rgb) {
+        Painter::fill_rect(&mut self.0, rect, color);
+    }
+    fn fill_path(&mut self, path: &Path, color: Rgb) {
+        self.0.fill_path(path, color);
+    }
+    fn stroke_path(&mut self, path: &Path, color: Rgb, width: f32) {
+        self.0.stroke_path(path, color, width);
+    }
+    fn draw_image(&mut self, image: &DrawTarget, src: IntRect, pos: IntPoint) {
+        self.0.draw_image(image, src, pos);
+    }
+    fn draw_text(&mut self, text: &Text, pos: IntPoint) {
+        let Rgb(r, g, b) = text.color;
+        for glyph in &text.glyphs {
+            let data = glyph
+                .coverage
+                .iter()
+                .map(|&a| {
+                    let a = u32::from(a);
+                    let premul = |c: u8| u32::from(c) * a / 255;
+                    (a << 24) | (premul(r) << 16) | (premul(g) << 8) | premul(b)
+                })
+                .collect::<Vec<_>>();
+            self.0.draw_image_at(
+                (pos.x + glyph.pos.x) as f32,
+                (pos.y + glyph.pos.y) as f32,
+                &Image {
+                    width: glyph.width.into(),
+                    height: glyph.height.into(),
+                    data: &data,
+                },
+                &Default::default(),
+            );
+        }
+    }
+    fn push_clip(&mut self, rect: IntRect) {
+        Painter::push_clip(&mut self.0, rect);
+    }
+    fn pop_clip(&mut self) {
+        Painter::pop_clip(&mut self.0);
+    }
+}
+
+#[test]
+fn glyph_masks() {
+    let mut ui =
+        Ui::new(Icon::Info, Theme::Dark, "Title", IPSUM, &["OK", "Cancel"]).with_details(BACKTRACE);
+    ui.set_max_height(160);
+
+    let mut painter = GlyphPainter(DrawTarget::new(ui.canvas.width(), ui.canvas.height()));
+    ui.invalidate();
+    ui.update_with(&mut painter);
+    let differing = painter
+        .0
+        .get_data()
+        .iter()
+        .zip(ui.canvas.get_data())
+        .filter(|(a, b)| a != b)
+        .count();
+    // Where the boxes of adjacent glyphs overlap, the rendered text only keeps the later glyph,
+    // while the masks are blended together.
+    assert!(differing < 20, "{differing}");
+}
+
+#[test]
+fn pixel_formats() {
+    let pixels = [0xff_ff8000, 0xff_0000ff, 0xff_ffffff];
+
+    // The usual 24-bit visual, little-endian: sent as it is.
+    let native = PixelFormat::direct(32, 32, false, [0, 0xff0000, 0xff00, 0xff]).unwrap();
+    assert!(matches!(native.convert(&pixels, 3), Cow::Borrowed(_)));
+
+    // The same visual on a big-endian server.
+    let big = PixelFormat::direct(32, 32, true, [0, 0xff0000, 0xff00, 0xff]).unwrap();
+    assert_eq!(*big.convert(&pixels[..1], 1), [0x00, 0xff, 0x80, 0x00]);
+
+    // 16-bit (5-6-5) pixels, with rows padded to 32 bits.
+    let rgb565 = PixelFormat::direct(16, 32, false, [0, 0xf800, 0x07e0, 0x001f]).unwrap();
+    assert_eq!(rgb565.row_len(3), 8);
+    assert_eq!(
+        *rgb565.convert(&pixels, 3),
+        [0x00, 0xfc, 0x1f, 0x00, 0xff, 0xff, 0, 0]
+    );
+    assert_eq!(*rgb565.convert(&pixels[1..2], 1), [0x1f, 0x00, 0, 0]);
+
+    // 30-bit deep color.
+    let rgb30 = PixelFormat::direct(32, 32, false, [0, 0x3ff00000, 0xffc00, 0x3ff]).unwrap();
+    assert_eq!(
+        *rgb30.convert(&pixels[2..], 1),
+        0x3fffffff_u32.to_le_bytes()
+    );
+
+    // Indexed colors pick the closest entry of the color cube.
+    let cube = (0..CUBE_LEVELS.pow(3) as u32).map(|i| i + 16).collect();
+    let indexed = PixelFormat::indexed(8, 8, false, cube).unwrap();
+    assert_eq!(
+        *indexed.convert(&pixels, 3),
+        [5 * 36 + 3 * 6 + 16, 5 + 16, 215 + 16]
+    );
+    assert_eq!(pixels::cube_color(5 * 36 + 3 * 6), [0xffff, 0x9999, 0]);
+
+    assert!(PixelFormat::direct(4, 32, false, [0, 0, 0, 0]).is_none());
+}
+
+#[test]
+fn tessellation() {
+    let area = |triangles: &[Triangle]| {
+        triangles
+            .iter()
+            .map(|t| {
+                let [a, b, c] = [t.p1, t.p2, t.p3].map(|p| (p.x as f64, p.y as f64));
+                ((b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)).abs() / 2.0
+            })
+            .sum::<f64>()
+            / 65536.0
+            / 65536.0
+    };
+
+    let mut pb = PathBuilder::new();
+    pb.rect(10.0, 10.0, 100.0, 40.0);
+    assert_eq!(area(&render::fill_triangles(&pb.finish())), 4000.0);
+
+    // Curves are approximated closely enough.
+    let mut pb = PathBuilder::new();
+    pb.move_to(70.0, 50.0);
+    pb.arc(50.0, 50.0, 20.0, 0.0, 2.0 * PI);
+    let circle = area(&render::fill_triangles(&pb.finish()));
+    assert!(
+        (circle / (PI as f64 * 400.0) - 1.0).abs() < 0.01,
+        "{circle}"
+    );
+
+    // Lines are widened to both sides.
+    let mut pb = PathBuilder::new();
+    pb.move_to(0.0, 0.0);
+    pb.line_to(10.0, 0.0);
+    pb.move_to(20.0, 20.0);
+    pb.line_to(20.0, 30.0);
+    let triangles = render::stroke_triangles(&pb.finish(), 2.0);
+    assert_eq!(triangles.len(), 4);
+    assert_eq!(area(&triangles), 40.0);
+    assert_eq!(triangles[0].p1.y, 65536);
+}
+
+#[test]
+fn resize() {
+    let mut ui = Ui::new(Icon::Info, Theme::Light, "Title", IPSUM, &["OK", "Cancel"]);
+    ui.process_event(WindowEvent::Resized(700, 250));
+    assert_eq!((ui.canvas.width(), ui.canvas.height()), (700, 250));
+    snap("resize-wide", &ui.canvas);
+
+    // Too small to fit the whole message, so it becomes scrollable.
+    ui.process_event(WindowEvent::Resized(450, 150));
+    assert_eq!((ui.canvas.width(), ui.canvas.height()), (450, 150));
+    snap("resize-narrow", &ui.canvas);
+
+    // Sizes below the minimum are clamped.
+    ui.process_event(WindowEvent::Resized(10, 10));
+    let min = ui.min_size();
+    assert_eq!(
+        (ui.canvas.width(), ui.canvas.height()),
+        (min.width, min.height)
+    );
+}
+
+#[test]
+fn custom_theme() {
+    let palette = "
+        # Corporate colors
+        base = dark
+        background = #102040, button = #204070, button_outline = #4080c0
+        focus = #ffcc00
+    "
+    .parse::<Palette>()
+    .unwrap();
+    assert_eq!(palette.text, Palette::DARK.text);
+    assert_eq!(palette.focus, Rgb(0xff, 0xcc, 0x00));
+
+    let mut ui = Ui::new(
+        Icon::Question,
+        Theme::Custom(palette),
+        "Title",
+        "Do you want to continue?",
+        &["Yes", "No", "Cancel"],
+    );
+    ui.redraw();
+    snap("custom-theme", &ui.canvas);
+}
+
+#[test]
+fn palette_errors() {
+    assert_eq!("#abc".parse::<Rgb>().unwrap(), Rgb(0xaa, 0xbb, 0xcc));
+    assert_eq!(Rgb(1, 2, 255).to_string(), "#0102ff");
+    for invalid in [
+        "text",
+        "text = red",
+        "text = #12345",
+        "color = #123456",
+        "base = purple",
+    ] {
+        assert!(invalid.parse::<Palette>().is_err(), "{invalid}");
+    }
+}
+
+/// Runs a stand-in for a D-Bus daemon in a background thread, and returns its address.
+///
+/// The stand-in answers `Hello`, and replies to all other messages with the messages returned by
+/// `respond`.
+fn dbus_stand_in(
+    name: &str,
+    respond: impl Fn(&Message) -> Vec<Message> + Send + 'static,
+) -> String {
+    let path = env::temp_dir().join(format!("alerta-{}-{name}.sock", process::id()));
+    fs::remove_file(&path).ok();
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut nul = [0xff];
+        stream.read_exact(&mut nul).unwrap();
+        assert_eq!(nul, [0]);
+        assert_eq!(dbus::read_line(&mut stream).unwrap(), "AUTH EXTERNAL");
+        stream.write_all(b"DATA\r\n").unwrap();
+        assert_eq!(dbus::read_line(&mut stream).unwrap(), "DATA");
+        stream.write_all(b"OK 0123456789abcdef\r\n").unwrap();
+        assert_eq!(dbus::read_line(&mut stream).unwrap(), "BEGIN");
+
+        // Runs until the client disconnects.
+        let mut serial = 1;
+        while let Ok(call) = dbus::read_message(&mut stream) {
+            let replies = if call.member.as_deref() == Some("Hello") {
+                vec![Message::method_return(
+                    &call,
+                    vec![Value::String(":1.1".into())],
+                )]
+            } else {
+                respond(&call)
+            };
+            for mut reply in replies {
+                reply.serial = serial;
+                serial += 1;
+                stream.write_all(&reply.encode()).unwrap();
+            }
+        }
+    });
+    // Exercise escaping and address lists.
+    let escaped = path.to_str().unwrap().replace('-', "%2d");
+    format!("unix:path=/nonexistent/bus;unix:path={escaped}")
+}
+
+/// Returns a `respond` function for [`dbus_stand_in`] that implements the portal's `Read` method
+/// with the given appearance settings, sending `signals` after the last setting has been read.
+fn portal(
+    settings: Vec<(&'static str, Value)>,
+    signals: Vec<Message>,
+) -> impl Fn(&Message) -> Vec<Message> + Send + 'static {
+    move |call| {
+        if call.member.as_deref() == Some("AddMatch") {
+            assert_eq!(call.destination.as_deref(), Some("org.freedesktop.DBus"));
+            assert_eq!(
+                call.body[0].as_str(),
+                Some(
+                    "type='signal',interface='org.freedesktop.portal.Settings',\
+                     member='SettingChanged',arg0='org.freedesktop.appearance'"
+                )
+            );
+            return vec![Message::method_return(call, Vec::new())];
+        }
+        assert_eq!(
+            call.destination.as_deref(),
+            Some("org.freedesktop.portal.Desktop")
+        );
+        assert_eq!(
+            call.interface.as_deref(),
+            Some("org.freedesktop.portal.Settings")
+        );
+        assert_eq!(call.member.as_deref(), Some("Read"));
+        assert_eq!(call.body[0].as_str(), Some("org.freedesktop.appearance"));
+        let key = call.body[1].as_str().unwrap();
+        let mut replies = match settings.iter().find(|(k, _)| *k == key) {
+            // `Read` wraps the value in two variants.
+            Some((_, value)) => vec![Message::method_return(
+                call,
+                vec![Value::Variant(Box::new(Value::Variant(Box::new(
+                    value.clone(),
+                ))))],
+            )],
+            None => vec![Message::error(
+                call,
+                "org.freedesktop.portal.Error.NotFound",
+                "Requested setting not found",
+            )],
+        };
+        if key == "accent-color" {
+            replies.extend(signals.iter().cloned());
+        }
+        replies
+    }
+}
+
+#[test]
+fn dbus_portal_theme() {
+    // Old portals only know `color-scheme`.
+    let addr = dbus_stand_in(
+        "portal",
+        portal(vec![("color-scheme", Value::U32(1))], Vec::new()),
+    );
+    let mut conn = dbus::Connection::connect(&addr).unwrap();
+    assert_eq!(Theme::detect_portal(&mut conn).unwrap(), Theme::Dark);
+
+    let accent = Value::Struct(vec![
+        Value::Double(1.0),
+        Value::Double(0.5),
+        Value::Double(0.0),
+    ]);
+    let addr = dbus_stand_in(
+        "portal-accent",
+        portal(
+            vec![
+                ("color-scheme", Value::U32(0)),
+                ("contrast", Value::U32(0)),
+                ("accent-color", accent),
+            ],
+            Vec::new(),
+        ),
+    );
+    let mut conn = dbus::Connection::connect(&addr).unwrap();
+    let Theme::Custom(palette) = Theme::detect_portal(&mut conn).unwrap() else {
+        panic!("expected custom theme");
+    };
+    assert_eq!(palette.accent, Rgb(255, 128, 0));
+    assert_eq!(palette.focus, Rgb(255, 128, 0));
+    assert_eq!(palette.background, Palette::LIGHT.background);
+
+    let addr = dbus_stand_in(
+        "portal-contrast",
+        portal(
+            vec![("color-scheme", Value::U32(1)), ("contrast", Value::U32(1))],
+            Vec::new(),
+        ),
+    );
+    let mut conn = dbus::Connection::connect(&addr).unwrap();
+    assert_eq!(
+        Theme::detect_portal(&mut conn).unwrap(),
+        Theme::Custom(Palette::HIGH_CONTRAST_DARK)
+    );
+}
+
+#[test]
+fn dbus_error() {
+    let addr = dbus_stand_in("error", portal(Vec::new(), Vec::new()));
+    let mut conn = dbus::Connection::connect(&addr).unwrap();
+    let error = Theme::detect_portal(&mut conn).unwrap_err().to_string();
+    assert_eq!(
+        error,
+        "org.freedesktop.portal.Error.NotFound: Requested setting not found"
+    );
+}
+
+#[test]
+fn dbus_marshalling() {
+    let dict_entry = |k: &str, v| Value::DictEntry(Box::new(Value::String(k.into())), Box::new(v));
+    let mut msg = Message::method_call(
+        "org.example.Service",
+        "/org/example",
+        "org.example.Interface",
+        "Method",
+        vec![
+            Value::Byte(7),
+            Value::Bool(true),
+            Value::I16(-2),
+            Value::U64(u64::MAX),
+            Value::Double(0.5),
+            Value::Array(
+                "{sv}".into(),
+                vec![
+                    dict_entry("a", Value::Variant(Box::new(Value::I32(-1)))),
+                    dict_entry(
+                        "b",
+                        Value::Variant(Box::new(Value::Struct(vec![
+                            Value::Byte(1),
+                            Value::ObjectPath("/".into()),
+                            Value::Signature("a(yv)".into()),
+                        ]))),
+                    ),
+                ],
+            ),
+            Value::Array("x".into(), Vec::new()),
+            Value::Array(
+                "ay".into(),
+                vec![Value::Array("y".into(), vec![Value::Byte(1)])],
+            ),
+        ],
+    );
+    msg.serial = 42;
+    let decoded = Message::decode(&msg.encode()).unwrap();
+    assert_eq!(decoded.serial, 42);
+    assert_eq!(decoded.path.as_deref(), Some("/org/example"));
+    assert_eq!(decoded.member.as_deref(), Some("Method"));
+    assert_eq!(decoded.body, msg.body);
+
+    // Truncated messages are rejected instead of panicking.
+    let encoded = msg.encode();
+    for len in 0..encoded.len() {
+        assert!(Message::decode(&encoded[..len]).is_err());
+    }
+
+    // Empty structs are not allowed, and would make arrays of them endless.
+    for body in [
+        Value::Array("()".into(), Vec::new()),
+        Value::Variant(Box::new(Value::Array("{}".into(), Vec::new()))),
+    ] {
+        let msg = Message::method_call("a.b", "/", "a.b", "M", vec![body]);
+        assert!(Message::decode(&msg.encode()).is_err());
+    }
+
+    // Lengths that don't fit in memory are rejected instead of overflowing.
+    let mut header = b"l\x01\0\x01".to_vec();
+    header.extend_from_slice(&[0xff; 12]);
+    assert!(dbus::read_message(&mut &header[..]).is_err());
+}
+
+#[test]
+fn dbus_theme_watcher() {
+    let setting_changed = |key: &str, value| {
+        Message::signal(
+            "/org/freedesktop/portal/desktop",
+            "org.freedesktop.portal.Settings",
+            "SettingChanged",
+            vec![
+                Value::String("org.freedesktop.appearance".into()),
+                Value::String(key.into()),
+                Value::Variant(Box::new(value)),
+            ],
+        )
+    };
+    let addr = dbus_stand_in(
+        "watcher",
+        portal(
+            vec![("color-scheme", Value::U32(2))],
+            vec![
+                setting_changed("reduced-motion", Value::U32(1)),
+                setting_changed("color-scheme", Value::U32(1)),
+                setting_changed("contrast", Value::U32(1)),
+                setting_changed("contrast", Value::U32(0)),
+                setting_changed("color-scheme", Value::U32(0)),
+            ],
+        ),
+    );
+    let conn = dbus::Connection::connect(&addr).unwrap();
+    let (mut watcher, theme) = ThemeWatcher::subscribe(conn).unwrap();
+    assert_eq!(theme, Theme::Light);
+    assert_eq!(watcher.process().unwrap(), None);
+    assert_eq!(watcher.process().unwrap(), Some(Theme::Dark));
+    assert_eq!(
+        watcher.process().unwrap(),
+        Some(Theme::Custom(Palette::HIGH_CONTRAST_DARK))
+    );
+    assert_eq!(watcher.process().unwrap(), Some(Theme::Dark));
+    assert_eq!(watcher.process().unwrap(), Some(Theme::Light));
+}
+
+#[test]
+fn theme_change() {
+    let mut ui = Ui::new(
+        Icon::Warning,
+        Theme::Light,
+        "Title",
+        IPSUM,
+        &["OK", "Cancel"],
+    )
+    .with_details(BACKTRACE);
+    ui.process_event(WindowEvent::ThemeChanged(Theme::Dark));
+
+    let dark = Ui::new(
+        Icon::Warning,
+        Theme::Dark,
+        "Title",
+        IPSUM,
+        &["OK", "Cancel"],
+    )
+    .with_details(BACKTRACE);
+    assert!(ui.canvas.get_data() == dark.canvas.get_data());
+}
+
+#[test]
+fn high_contrast() {
+    let mut ui = Ui::new(
+        Icon::Error,
+        Theme::Custom(Palette::HIGH_CONTRAST),
+        "Title",
+        "The operation could not be completed.",
+        &["Retry", "Cancel"],
+    )
+    .with_details(BACKTRACE);
+    ui.redraw();
+    snap("high-contrast", &ui.canvas);
+}
+
+#[test]
+fn accent_on_default_button() {
+    let accent = Rgb(255, 0, 128);
+    let palette = Palette {
+        accent,
+        focus: Rgb(0, 128, 0),
+        // Thin outlines are antialiased, leaving no pixels of the exact color.
+        outline_width: 2,
+        ..Palette::LIGHT
+    };
+    let count = |ui: &Ui, Rgb(r, g, b): Rgb| {
+        let argb = u32::from_be_bytes([255, r, g, b]);
+        ui.canvas.get_data().iter().filter(|&&p| p == argb).count()
+    };
+    let mut ui = Ui::new(
+        Icon::Info,
+        Theme::Custom(palette),
+        "Title",
+        "Text",
+        &["OK", "Cancel"],
+    );
+    // The default button is outlined in the accent color from the start.
+    ui.redraw();
+    assert!(count(&ui, accent) > 100);
+    assert_eq!(count(&ui, palette.focus), 0);
+}
+
+/// Encodes XSETTINGS property data, like an XSETTINGS manager would.
+fn xsettings_data(big_endian: bool, settings: &[(&str, XSetting)]) -> Vec<u8> {
+    let card16 = |v: u16| {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    };
+    let card32 = |v: u32| {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    };
+    let pad = |data: &mut Vec<u8>| data.resize(data.len().next_multiple_of(4), 0);
+
+    let mut data = vec![big_endian as u8, 0, 0, 0];
+    data.extend(card32(7));
+    data.extend(card32(settings.len() as u32));
+    for (name, value) in settings {
+        let kind = match value {
+            XSetting::Int(_) => 0,
+            XSetting::String(_) => 1,
+            XSetting::Color(_) => 2,
+        };
+        data.extend([kind, 0]);
+        data.extend(card16(name.len() as u16));
+        data.extend(name.as_bytes());
+        pad(&mut data);
+        data.extend(card32(3));
+        match value {
+            XSetting::Int(i) => data.extend(card32(*i as u32)),
+            XSetting::String(s) => {
+                data.extend(card32(s.len() as u32));
+                data.extend(s.as_bytes());
+                pad(&mut data);
+            }
+            XSetting::Color(c) => c.iter().for_each(|c| data.extend(card16(*c))),
+        }
+    }
+    data
+}
+
+#[test]
+fn xsettings() {
+    let settings = [
+        ("Net/ThemeName", XSetting::String("Adwaita-dark".into())),
+        ("Net/IconThemeName", XSetting::String("Papirus".into())),
+        ("Gtk/FontName", XSetting::String("Noto Sans Bold 11".into())),
+        ("Xft/DPI", XSetting::Int(192 * 1024)),
+        (
+            "Gtk/ColorPalette",
+            XSetting::Color([0xffff, 0, 0x8000, 0xffff]),
+        ),
+    ];
+    for big_endian in [false, true] {
+        let xsettings = XSettings::parse(&xsettings_data(big_endian, &settings)).unwrap();
+        for (name, value) in &settings {
+            assert_eq!(xsettings.get(name), Some(value));
+        }
+        assert_eq!(xsettings.get("Net/CursorBlink"), None);
+        assert_eq!(xsettings.theme(), Some(Theme::Dark));
+        assert_eq!(
+            xsettings.font_prefs(),
+            FontPrefs {
+                family: Some("Noto Sans".into()),
+                size: Some(11.0),
+                dpi: Some(192.0),
+            }
+        );
+    }
+
+    let theme = |name: &str| {
+        let data = xsettings_data(false, &[("Net/ThemeName", XSetting::String(name.into()))]);
+        XSettings::parse(&data).unwrap().theme()
+    };
+    assert_eq!(theme("Adwaita"), Some(Theme::Light));
+    assert_eq!(
+        theme("HighContrast"),
+        Some(Theme::Custom(Palette::HIGH_CONTRAST))
+    );
+    assert_eq!(
+        theme("HighContrastInverse"),
+        Some(Theme::Custom(Palette::HIGH_CONTRAST_DARK))
+    );
+
+    // No settings at all (and `Xft/DPI` set to "default").
+    let xsettings = XSettings::parse(&xsettings_data(true, &[("Xft/DPI", XSetting::Int(-1))]));
+    let xsettings = xsettings.unwrap();
+    assert_eq!(xsettings.theme(), None);
+    assert_eq!(xsettings.font_prefs(), FontPrefs::default());
+
+    // Malformed data.
+    let data = xsettings_data(false, &settings);
+    assert!(XSettings::parse(&data[..data.len() - 1]).is_none());
+    assert!(XSettings::parse(&[2, 0, 0, 0]).is_none());
+    assert!(XSettings::parse(&[]).is_none());
+}
+
+#[test]
+fn font_names() {
+    let prefs = |name: &str| {
+        let prefs = FontPrefs::from_font_name(name);
+        (prefs.family, prefs.size)
+    };
+    assert_eq!(
+        prefs("Cantarell 11"),
+        (Some("Cantarell".into()), Some(11.0))
+    );
+    assert_eq!(
+        prefs("DejaVu Sans Bold Italic 10.5"),
+        (Some("DejaVu Sans".into()), Some(10.5))
+    );
+    assert_eq!(prefs("Ubuntu"), (Some("Ubuntu".into()), None));
+    assert_eq!(prefs("Sans 0"), (Some("Sans 0".into()), None));
+    assert_eq!(prefs(""), (None, None));
+}
+
+#[test]
+fn hidpi() {
+    let prefs = FontPrefs {
+        dpi: Some(144.0),
+        ..FontPrefs::from_font_name("Cantarell 10")
+    };
+    // 10pt at 144 DPI is 20px, slightly larger than the default.
+    assert_eq!(prefs.pixel_size(), 20.0);
+    let ui = Ui::new(
+        Icon::Warning,
+        Theme::Light,
+        "Title",
+        "Your desktop uses a larger font.",
+        &["OK"],
+    )
+    .with_details(BACKTRACE)
+    .with_font_prefs(&prefs);
+    snap("hidpi", &ui.canvas);
+}
+
+#[test]
+fn kde_color_scheme() {
+    // Excerpt from Breeze Dark.
+    let kdeglobals = "
+[General]
+ColorScheme=BreezeDark
+
+[Colors:Button]
+BackgroundNormal=41,44,48
+ForegroundNormal=252,252,252
+
+[Colors:View]
+BackgroundNormal=20,22,24
+DecorationFocus=61,174,233
+
+[Colors:Window]
+BackgroundNormal=32,35,38
+ForegroundLink=29,153,243
+ForegroundNormal=252,252,252
+";
+    let palette = theme::kde_palette(kdeglobals).unwrap();
+    assert_eq!(palette.background, Rgb(32, 35, 38));
+    assert_eq!(palette.text, Rgb(252, 252, 252));
+    assert_eq!(palette.button, Rgb(41, 44, 48));
+    assert_eq!(palette.accent, Rgb(29, 153, 243));
+    assert_eq!(palette.focus, Rgb(61, 174, 233));
+    assert_eq!(palette.outline_width, Palette::DARK.outline_width);
+
+    // The window colors are required.
+    assert_eq!(
+        theme::kde_palette("[Colors:Window]\nBackgroundNormal=1,2,3"),
+        None
+    );
+    assert_eq!(
+        theme::kde_palette("[Colors:Window]\nBackgroundNormal=1,2\nForegroundNormal=0,0,0"),
+        None
+    );
+}
+
+#[test]
+fn gtk_color_scheme() {
+    let dir = env::temp_dir().join(format!("alerta-{}-gtk-theme", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("colors.css"),
+        "@define-color theme_bg_color #f6f5f4;\n@define-color blue_3 rgb(53, 132, 228);",
+    )
+    .unwrap();
+
+    let mut colors = GtkColors::default();
+    colors.parse(
+        r#"
+        /* @define-color theme_fg_color #ff0000; */
+        @import url("colors.css");
+        @import url("resource:///org/gtk/libgtk/theme/Adwaita/gtk-contained.css");
+        @define-color theme_fg_color mix(#000000, #ffffff, 0.2);
+        @define-color theme_selected_bg_color alpha(@blue_3, 0.5);
+        window { background-color: @theme_bg_color; }
+        @define-color theme_unfocused_bg_color shade(@theme_bg_color, 1.1);
+        "#,
+        Some(&dir),
+        0,
+    );
+    let palette = colors.palette().unwrap();
+    assert_eq!(palette.background, Rgb(0xf6, 0xf5, 0xf4));
+    assert_eq!(palette.text, Rgb(51, 51, 51));
+    assert_eq!(palette.accent, Rgb(53, 132, 228));
+    assert_eq!(palette.focus, palette.accent);
+
+    // libadwaita names, and a reference cycle.
+    let mut colors = GtkColors::default();
+    colors.parse(
+        "@define-color window_bg_color #242424; @define-color window_fg_color @loop; \
+         @define-color loop @window_fg_color;",
+        None,
+        0,
+    );
+    assert_eq!(colors.palette(), None);
+    colors.parse(
+        "@define-color window_fg_color white; @define-color theme_fg_color #fff;",
+        None,
+        0,
+    );
+    let palette = colors.palette().unwrap();
+    assert_eq!(palette.background, Rgb(0x24, 0x24, 0x24));
+    assert_eq!(palette.text, Rgb(255, 255, 255));
+    assert_eq!(palette.accent, Palette::DARK.accent);
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn x_resources() {
+    let resources = XResources::parse(
+        "! comment\n\
+         *background:\t#101010\n\
+         alerta*background: #202020\n\
+         Alerta.background: #303030\n\
+         *Foreground: rgb:f/80/ffff\n\
+         xterm*foreground: #ff0000\n\
+         alerta*buttonBackground: #505050\n\
+         alerta.buttonBackground:#404040\n\
+         alerta*buttonBorderWidth: 3\n\
+         alerta*?.linkColor: #00ff00\n\
+         malformed line\n\
+         alerta.font: xft:DejaVu Sans:bold:size=12\n\
+         Xft.dpi: 120\n\
+         alerta.message: first\\n\\\n\
+         second\n",
+    );
+    // Names win over classes, and tight bindings over loose ones.
+    assert_eq!(resources.get("background", "Background"), Some("#202020"));
+    assert_eq!(
+        resources.get("buttonBackground", "Background"),
+        Some("#404040")
+    );
+    assert_eq!(
+        resources.get("foreground", "Foreground"),
+        Some("rgb:f/80/ffff")
+    );
+    // `?` needs exactly one component in between.
+    assert_eq!(resources.get("linkColor", "LinkColor"), None);
+    assert_eq!(resources.get("message", "Message"), Some("first\nsecond"));
+    assert_eq!(resources.get("title", "Title"), None);
+    // With a different `WM_CLASS`, resources for `alerta` don't match.
+    let renamed = XResources::parse(
+        "alerta.background: #202020\n\
+         Alerta.foreground: #303030\n\
+         myapp.background: #404040\n\
+         Myapp.message: #505050\n",
+    )
+    .with_name(x11::instance_name("myapp"), x11::class_name("myapp"));
+    assert_eq!(renamed.get("background", "Background"), Some("#404040"));
+    assert_eq!(renamed.get("foreground", "Foreground"), None);
+    assert_eq!(renamed.get("message", "Message"), Some("#505050"));
+    assert_eq!(x11::class_name("alerta"), "Alerta");
+    assert_eq!(x11::class_name("élan\0"), "Élan");
+    assert_eq!(x11::class_name(""), "");
+
+    let Theme::Custom(palette) = resources.apply_colors(Theme::Dark) else {
+        panic!("resources should produce a custom palette");
+    };
+    assert_eq!(
+        palette,
+        Palette {
+            background: Rgb(0x20, 0x20, 0x20),
+            text: Rgb(255, 128, 255),
+            button: Rgb(0x40, 0x40, 0x40),
+            outline_width: 3,
+            ..Palette::DARK
+        }
+    );
+    assert_eq!(
+        XResources::parse("xterm*background: #ffffff").apply_colors(Theme::Dark),
+        Theme::Dark
+    );
+
+    assert_eq!(
+        resources.apply_font_prefs(FontPrefs::default()),
+        FontPrefs {
+            family: Some("DejaVu Sans".into()),
+            size: Some(12.0),
+            dpi: Some(120.0),
+        }
+    );
+    // Explicit DPI settings (from XSETTINGS) win over `Xft.dpi`.
+    let prefs = XResources::parse("*font: Noto Sans-9\nXft.dpi: 120").apply_font_prefs(FontPrefs {
+        dpi: Some(96.0),
+        ..FontPrefs::from_font_name("Cantarell 11")
+    });
+    assert_eq!(
+        prefs,
+        FontPrefs {
+            family: Some("Noto Sans".into()),
+            size: Some(9.0),
+            dpi: Some(96.0),
+        }
+    );
+}
+
+#[test]
+fn locales() {
+    assert_eq!(Strings::for_locale("de_DE.UTF-8").close, "Schließen");
+    assert_eq!(Strings::for_locale("fr_CA@euro").cancel, "Annuler");
+    assert_eq!(Strings::for_locale("pt-BR").yes, "Sim");
+    assert_eq!(Strings::for_locale("PL").error, "Błąd");
+    assert_eq!(Strings::for_locale("C"), Strings::ENGLISH);
+    assert_eq!(Strings::for_locale("POSIX"), Strings::ENGLISH);
+    assert_eq!(Strings::for_locale("tlh_QO"), Strings::ENGLISH);
+    assert_eq!(Strings::for_locale(""), Strings::ENGLISH);
+
+    let strings = Strings::for_locale("es");
+    assert_eq!(
+        ButtonPreset::YesNoCancel.labels(&strings),
+        ["Sí", "No", "Cancelar"]
+    );
+    assert_eq!(ButtonPreset::Close.labels(&Strings::ENGLISH), ["Close"]);
+}
+
+#[test]
+fn translations_are_displayable() {
+    let font =
+        FontArc::try_from_slice(include_bytes!("../3rdparty/fonts/Cantarell-Regular.ttf")).unwrap();
+    for (lang, strings) in TRANSLATIONS {
+        let Strings {
+            close,
+            ok,
+            cancel,
+            retry,
+            yes,
+            no,
+            error,
+            warning,
+            info,
+            question,
+            show_details,
+            hide_details,
+        } = strings;
+        let texts = [
+            close,
+            ok,
+            cancel,
+            retry,
+            yes,
+            no,
+            error,
+            warning,
+            info,
+            question,
+            show_details,
+            hide_details,
+        ];
+        for c in texts.iter().flat_map(|text| text.chars()) {
+            assert_ne!(
+                font.glyph_id(c).0,
+                0,
+                "translation '{lang}' uses '{c}', which the bundled font is missing"
+            );
+        }
+    }
+}
+
+#[test]
+fn localized() {
+    let strings = Strings::for_locale("de");
+    let mut ui = Ui::new(
+        Icon::Question,
+        Theme::Light,
+        &strings.question,
+        "Sollen die Änderungen gespeichert werden?",
+        &ButtonPreset::YesNoCancel.labels(&strings),
+    )
+    .with_details("~/Dokumente/Brief.txt");
+    ui.set_details_labels(&strings.show_details, &strings.hide_details);
+    snap("localized", &ui.canvas);
+}
+
+#[test]
+fn accessibility() {
+    const ROOT: &str = "/org/a11y/atspi/accessible/root";
+    const DIALOG: &str = "/org/a11y/atspi/accessible/dialog";
+    const MESSAGE: &str = "/org/a11y/atspi/accessible/message";
+    const ACCESSIBLE: &str = "org.a11y.atspi.Accessible";
+    const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+    let button = |i: usize| format!("/org/a11y/atspi/accessible/button{i}");
+    let reference = |name: &str, path: &str| {
+        Value::Struct(vec![
+            Value::String(name.into()),
+            Value::ObjectPath(path.into()),
+        ])
+    };
+    let string = |s: &str| Value::String(s.into());
+    let variant = |v| Value::Variant(Box::new(v));
+
+    // Calls from a screen reader (`:1.0`) to the dialog (`:1.1`).
+    let calls = [
+        (DIALOG.to_string(), ACCESSIBLE, "GetRole", vec![]),
+        (ROOT.into(), ACCESSIBLE, "GetChildren", vec![]),
+        (
+            DIALOG.into(),
+            PROPERTIES,
+            "Get",
+            vec![string(ACCESSIBLE), string("Name")],
+        ),
+        (DIALOG.into(), ACCESSIBLE, "GetChildren", vec![]),
+        (
+            DIALOG.into(),
+            ACCESSIBLE,
+            "GetChildAtIndex",
+            vec![Value::I32(3)],
+        ),
+        (
+            MESSAGE.into(),
+            PROPERTIES,
+            "GetAll",
+            vec![string(ACCESSIBLE)],
+        ),
+        (button(0), ACCESSIBLE, "GetState", vec![]),
+        (button(1), ACCESSIBLE, "GetState", vec![]),
+        (
+            button(1),
+            "org.a11y.atspi.Action",
+            "GetName",
+            vec![Value::I32(0)],
+        ),
+        (
+            MESSAGE.into(),
+            "org.a11y.atspi.Action",
+            "DoAction",
+            vec![Value::I32(0)],
+        ),
+        ("/nonexistent".into(), ACCESSIBLE, "GetRole", vec![]),
+        (
+            ROOT.into(),
+            PROPERTIES,
+            "Set",
+            vec![
+                string("org.a11y.atspi.Application"),
+                string("Id"),
+                variant(Value::I32(5)),
+            ],
+        ),
+        (
+            ROOT.into(),
+            PROPERTIES,
+            "Get",
+            vec![string("org.a11y.atspi.Application"), string("Id")],
+        ),
+        (
+            button(1),
+            "org.a11y.atspi.Action",
+            "DoAction",
+            vec![Value::I32(0)],
+        ),
+    ]
+    .map(|(path, interface, member, body)| Message {
+        sender: Some(":1.0".into()),
+        ..Message::method_call(":1.1", &path, interface, member, body)
+    });
+
+    let (tx, rx) = mpsc::channel();
+    let addr = dbus_stand_in("a11y", move |msg| {
+        if msg.member.as_deref() != Some("Embed") {
+            tx.send(msg.clone()).unwrap();
+            return Vec::new();
+        }
+        assert_eq!(msg.destination.as_deref(), Some("org.a11y.atspi.Registry"));
+        assert_eq!(msg.path.as_deref(), Some(ROOT));
+        assert_eq!(msg.body, [reference(":1.1", ROOT)]);
+        // The first call arrives before the reply, so the dialog has to queue it.
+        let mut messages = vec![calls[0].clone()];
+        messages.push(Message::method_return(
+            msg,
+            vec![reference("org.a11y.atspi.Registry", ROOT)],
+        ));
+        messages.extend(calls[1..].iter().cloned());
+        messages
+    });
+    let conn = dbus::Connection::connect(&addr).unwrap();
+    let mut a11y =
+        Accessibility::register(conn, "Fries?", "Would you like fries?", &["Yes", "No"]).unwrap();
+    assert!(a11y.has_queued());
+    a11y.activate(0).unwrap();
+    a11y.set_focus(0).unwrap();
+    let pressed = (0..14).map(|_| a11y.process().unwrap()).collect::<Vec<_>>();
+    assert_eq!(pressed[..13], [None; 13]);
+    assert_eq!(pressed[13], Some(1));
+    a11y.set_focus(1).unwrap();
+
+    let next = || rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    let event = |path: &str, member: &str, detail: &str, detail1: i32| {
+        let msg = next();
+        assert_eq!(msg.kind, MessageType::Signal);
+        assert_eq!(msg.path.as_deref(), Some(path));
+        assert_eq!(msg.member.as_deref(), Some(member));
+        assert_eq!(
+            msg.body[..3],
+            [string(detail), Value::I32(detail1), Value::I32(0)]
+        );
+    };
+    event(DIALOG, "Activate", "", 0);
+    event(&button(0), "StateChanged", "focused", 1);
+
+    let reply = || {
+        let msg = next();
+        assert_eq!(msg.destination.as_deref(), Some(":1.0"));
+        msg
+    };
+    assert_eq!(reply().body, [Value::U32(2)]);
+    assert_eq!(
+        reply().body,
+        [Value::Array("(so)".into(), vec![reference(":1.1", DIALOG)])]
+    );
+    assert_eq!(reply().body, [variant(string("Fries?"))]);
+    let children = vec![
+        reference(":1.1", MESSAGE),
+        reference(":1.1", &button(0)),
+        reference(":1.1", &button(1)),
+    ];
+    assert_eq!(reply().body, [Value::Array("(so)".into(), children)]);
+    assert_eq!(reply().body, [reference(":1.1", "/org/a11y/atspi/null")]);
+    let Value::Array(_, properties) = &reply().body[0] else {
+        panic!("expected a dictionary");
+    };
+    assert!(properties.contains(&Value::DictEntry(
+        Box::new(string("Name")),
+        Box::new(variant(string("Would you like fries?"))),
+    )));
+    assert!(properties.contains(&Value::DictEntry(
+        Box::new(string("Parent")),
+        Box::new(variant(reference(":1.1", DIALOG))),
+    )));
+    let focused = |msg: Message| match &msg.body[..] {
+        [Value::Array(_, states)] => states[0].as_u32().unwrap() & 1 << 12 != 0,
+        _ => panic!("unexpected state set {:?}", msg.body),
+    };
+    assert!(focused(reply()));
+    assert!(!focused(reply()));
+    assert_eq!(reply().body, [string("click")]);
+    assert_eq!(
+        reply().error_name.as_deref(),
+        Some("org.freedesktop.DBus.Error.UnknownMethod")
+    );
+    assert_eq!(
+        reply().error_name.as_deref(),
+        Some("org.freedesktop.DBus.Error.UnknownObject")
+    );
+    assert_eq!(reply().body, []);
+    assert_eq!(reply().body, [variant(Value::I32(5))]);
+    assert_eq!(reply().body, [Value::Bool(true)]);
+
+    event(&button(0), "StateChanged", "focused", 0);
+    event(&button(1), "StateChanged", "focused", 1);
+}
+
+#[test]
+fn latin1_selection() {
+    assert_eq!(x11::latin1("Grüße"), Some(b"Gr\xfc\xdfe".to_vec()));
+    assert_eq!(x11::latin1(""), Some(Vec::new()));
+    assert_eq!(x11::latin1("5 €"), None);
+}
+
+#[test]
+fn startup_notification() {
+    assert_eq!(
+        x11::startup_time("xdg-launch-1234-host_TIME56789"),
+        Some(56789)
+    );
+    assert_eq!(x11::startup_time("3a8b2d1c-e43f"), None);
+    assert_eq!(
+        x11::startup_remove_message("gnome-shell/\"a b\"\\_TIME1"),
+        r#"remove: ID="gnome-shell/\"a b\"\\_TIME1""#
+    );
+}
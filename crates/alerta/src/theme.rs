@@ -0,0 +1,477 @@
+//! Color themes and detection of the desktop's preferred color scheme.
+
+use std::{
+    fmt,
+    os::fd::{AsFd, BorrowedFd},
+    str::FromStr,
+};
+
+use crate::{Error, InvalidValue, dbus, x11};
+
+mod scheme;
+
+#[cfg(test)]
+pub(crate) use scheme::{GtkColors, kde_palette};
+
+/// Color theme selection.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Theme {
+    /// Follows the preferred color scheme of the desktop environment (see [`Theme::detect`]).
+    ///
+    /// If the preference changes while a dialog is open, the dialog switches to the new theme.
+    #[default]
+    System,
+    /// Dark text on a light background.
+    Light,
+    /// Light text on a dark background.
+    Dark,
+    /// A custom set of colors.
+    Custom(Palette),
+}
+
+impl FromStr for Theme {
+    type Err = InvalidValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "system" => Self::System,
+            "light" => Self::Light,
+            "dark" => Self::Dark,
+            _ => return Err(InvalidValue::new()),
+        })
+    }
+}
+
+impl Theme {
+    /// Returns the colors used by this theme.
+    ///
+    /// For [`Theme::System`], this detects the desktop's current preference.
+    pub fn palette(&self) -> Palette {
+        match self {
+            Theme::System => Self::detect().palette(),
+            Theme::Light => Palette::LIGHT,
+            Theme::Dark => Palette::DARK,
+            Theme::Custom(palette) => *palette,
+        }
+    }
+
+    /// Detects the preferred color scheme of the desktop environment.
+    ///
+    /// Returns [`Theme::Light`] if there is no preference, or if it cannot be determined. If the
+    /// desktop specifies an accent color or requests high contrast, a [`Theme::Custom`] palette
+    /// reflecting that is returned.
+    ///
+    /// X11 doesn't have a built-in mechanism to do this, so we try these sources in order (using
+    /// the first one that works):
+    ///
+    /// - the `color-scheme` setting of the XDG desktop portal, queried over D-Bus
+    /// - the GTK theme announced by the XSETTINGS manager, if its name marks it as dark or high
+    ///   contrast
+    ///
+    /// The latter is only a guess, so the first option is preferred.
+    ///
+    /// Unless high contrast is requested, the colors are then taken from the installed KDE color
+    /// scheme (`kdeglobals`) or GTK theme (`gtk.css`), if those match the preferred color scheme.
+    pub fn detect() -> Theme {
+        Self::detect_dbus()
+            .or_else(|_| Self::detect_xsettings().map(Self::with_desktop_colors))
+            .unwrap_or_else(|_| Theme::Light.with_desktop_colors())
+    }
+
+    fn detect_dbus() -> Result<Theme, Error> {
+        Self::detect_portal(&mut dbus::Connection::session()?)
+    }
+
+    /// Replaces [`Theme::Light`] and [`Theme::Dark`] with the colors of the installed KDE or GTK
+    /// theme, if it matches.
+    pub(crate) fn with_desktop_colors(self) -> Theme {
+        let dark = match self {
+            Theme::Light => false,
+            Theme::Dark => true,
+            theme => return theme,
+        };
+        scheme::desktop_palette(dark).map_or(self, Theme::Custom)
+    }
+
+    pub(crate) fn detect_portal(conn: &mut dbus::Connection) -> Result<Theme, Error> {
+        Appearance::read(conn).map(|appearance| appearance.theme())
+    }
+
+    fn detect_xsettings() -> Result<Theme, Error> {
+        x11::Connection::connect()?
+            .xsettings()?
+            .theme()
+            .ok_or_else(|| Error::new("the XSETTINGS manager doesn't specify a GTK theme"))
+    }
+}
+
+/// An RGB color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+/// Parses a color in `#rrggbb` or `#rgb` notation.
+impl FromStr for Rgb {
+    type Err = InvalidValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidValue::with_reason(format!("expected `#rrggbb`, found `{s}`"));
+        let hex = s.strip_prefix('#').ok_or_else(invalid)?;
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let channel = |i: usize, len: usize| u8::from_str_radix(&hex[i * len..][..len], 16);
+        match hex.len() {
+            6 => Ok(Rgb(
+                channel(0, 2).unwrap(),
+                channel(1, 2).unwrap(),
+                channel(2, 2).unwrap(),
+            )),
+            // `#abc` is short for `#aabbcc`.
+            3 => Ok(Rgb(
+                channel(0, 1).unwrap() * 0x11,
+                channel(1, 1).unwrap() * 0x11,
+                channel(2, 1).unwrap() * 0x11,
+            )),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+/// The set of colors used to draw a dialog.
+///
+/// A palette can be created by modifying one of the built-in palettes, or parsed from a list of
+/// `name = #rrggbb` entries separated by commas or newlines. The names are those of the fields
+/// below (`outline_width` takes a number instead of a color). The special `base` entry selects the
+/// palette that unspecified values are taken from (`light`, `dark`, `high-contrast` or
+/// `high-contrast-dark`, defaulting to `light`). Lines starting with `#` are ignored.
+///
+/// ```
+/// use alerta::{Palette, Rgb};
+///
+/// let mut palette = Palette::DARK;
+/// palette.accent = Rgb(255, 136, 0);
+///
+/// let parsed = "base = dark, accent = #ff8800".parse::<Palette>()?;
+/// assert_eq!(parsed, palette);
+/// # Ok::<_, alerta::InvalidValue>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Palette {
+    /// Background of the dialog window.
+    pub background: Rgb,
+    pub text: Rgb,
+    /// Background of buttons, scrollbars and the details pane.
+    pub button: Rgb,
+    /// Background of the button under the mouse cursor.
+    pub button_hover: Rgb,
+    /// Background of a button while it is pressed, and of scrollbar thumbs.
+    pub button_pressed: Rgb,
+    pub button_outline: Rgb,
+    /// Used for links and the outline of the default button.
+    pub accent: Rgb,
+    /// Outline of the button that has keyboard focus.
+    ///
+    /// Dialogs don't move the keyboard focus between buttons yet, so this is currently unused.
+    pub focus: Rgb,
+    /// Background of selected text.
+    pub selection: Rgb,
+    /// Width of the button outlines, in pixels.
+    pub outline_width: u8,
+}
+
+impl Palette {
+    /// The colors of [`Theme::Light`].
+    pub const LIGHT: Self = Self {
+        background: Rgb(230, 230, 230),
+        text: Rgb(0, 0, 0),
+        button: Rgb(210, 210, 210),
+        button_hover: Rgb(180, 180, 180),
+        button_pressed: Rgb(150, 150, 150),
+        button_outline: Rgb(40, 40, 40),
+        accent: Rgb(30, 100, 200),
+        focus: Rgb(30, 100, 200),
+        selection: Rgb(150, 190, 240),
+        outline_width: 1,
+    };
+
+    /// The colors of [`Theme::Dark`].
+    pub const DARK: Self = Self {
+        background: Rgb(30, 30, 30),
+        text: Rgb(255, 255, 255),
+        button: Rgb(90, 90, 90),
+        button_hover: Rgb(110, 110, 110),
+        button_pressed: Rgb(160, 160, 160),
+        button_outline: Rgb(200, 200, 200),
+        accent: Rgb(120, 170, 255),
+        focus: Rgb(120, 170, 255),
+        selection: Rgb(40, 80, 140),
+        outline_width: 1,
+    };
+
+    /// Black text on a white background, for users who need high contrast.
+    pub const HIGH_CONTRAST: Self = Self {
+        background: Rgb(255, 255, 255),
+        text: Rgb(0, 0, 0),
+        button: Rgb(255, 255, 255),
+        button_hover: Rgb(210, 210, 210),
+        button_pressed: Rgb(160, 160, 160),
+        button_outline: Rgb(0, 0, 0),
+        accent: Rgb(0, 0, 190),
+        focus: Rgb(0, 0, 190),
+        selection: Rgb(255, 230, 0),
+        outline_width: 2,
+    };
+
+    /// White text on a black background, for users who need high contrast.
+    pub const HIGH_CONTRAST_DARK: Self = Self {
+        background: Rgb(0, 0, 0),
+        text: Rgb(255, 255, 255),
+        button: Rgb(0, 0, 0),
+        button_hover: Rgb(60, 60, 60),
+        button_pressed: Rgb(110, 110, 110),
+        button_outline: Rgb(255, 255, 255),
+        accent: Rgb(255, 255, 0),
+        focus: Rgb(255, 255, 0),
+        selection: Rgb(0, 0, 180),
+        outline_width: 2,
+    };
+
+    pub(crate) fn color_mut(&mut self, name: &str) -> Option<&mut Rgb> {
+        Some(match name {
+            "background" => &mut self.background,
+            "text" => &mut self.text,
+            "button" => &mut self.button,
+            "button_hover" => &mut self.button_hover,
+            "button_pressed" => &mut self.button_pressed,
+            "button_outline" => &mut self.button_outline,
+            "accent" => &mut self.accent,
+            "focus" => &mut self.focus,
+            "selection" => &mut self.selection,
+            _ => return None,
+        })
+    }
+}
+
+impl FromStr for Palette {
+    type Err = InvalidValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries = Vec::new();
+        for line in s.lines().map(str::trim) {
+            if line.starts_with('#') {
+                continue;
+            }
+            for entry in line.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+                let Some((name, value)) = entry.split_once('=') else {
+                    return Err(InvalidValue::with_reason(format!(
+                        "expected `name = #rrggbb`, found `{entry}`"
+                    )));
+                };
+                entries.push((name.trim(), value.trim()));
+            }
+        }
+
+        let mut palette = Palette::LIGHT;
+        if let Some((_, base)) = entries.iter().rfind(|(name, _)| *name == "base") {
+            palette = match *base {
+                "light" => Palette::LIGHT,
+                "dark" => Palette::DARK,
+                "high-contrast" => Palette::HIGH_CONTRAST,
+                "high-contrast-dark" => Palette::HIGH_CONTRAST_DARK,
+                _ => {
+                    return Err(InvalidValue::with_reason(format!(
+                        "unknown base palette `{base}`"
+                    )));
+                }
+            };
+        }
+        for (name, value) in entries.into_iter().filter(|(name, _)| *name != "base") {
+            if name == "outline_width" {
+                palette.outline_width = value.parse().map_err(|_| {
+                    InvalidValue::with_reason(format!("invalid outline width `{value}`"))
+                })?;
+                continue;
+            }
+            let color = value.parse::<Rgb>()?;
+            *palette.color_mut(name).ok_or_else(|| {
+                InvalidValue::with_reason(format!("unknown color name `{name}`"))
+            })? = color;
+        }
+        Ok(palette)
+    }
+}
+
+/// The appearance preferences published by the desktop portal.
+#[derive(Debug, Default, Clone, Copy)]
+struct Appearance {
+    dark: bool,
+    high_contrast: bool,
+    accent: Option<Rgb>,
+}
+
+impl Appearance {
+    fn read(conn: &mut dbus::Connection) -> Result<Self, Error> {
+        let mut appearance = Appearance::default();
+        let scheme = read_portal_setting(conn, APPEARANCE, "color-scheme")?;
+        if !appearance.set("color-scheme", &scheme) {
+            return Err(Error::new(format!(
+                "unknown color scheme preference: {scheme:?}"
+            )));
+        }
+        // These settings are newer, and older portals return an error for them.
+        for key in ["contrast", "accent-color"] {
+            if let Ok(value) = read_portal_setting(conn, APPEARANCE, key) {
+                appearance.set(key, &value);
+            }
+        }
+        Ok(appearance)
+    }
+
+    /// Updates the preference `key` from its value in the portal.
+    ///
+    /// Returns `false` if `key` or its value isn't recognized.
+    fn set(&mut self, key: &str, value: &dbus::Value) -> bool {
+        match (key, value.unwrap_variant()) {
+            // 0 means "no preference", 2 means "prefer light".
+            ("color-scheme", dbus::Value::U32(v @ 0..=2)) => self.dark = *v == 1,
+            ("contrast", dbus::Value::U32(v @ 0..=1)) => self.high_contrast = *v == 1,
+            // Channels outside of 0..=1 mean that there is no accent color.
+            ("accent-color", dbus::Value::Struct(rgb)) => {
+                let channels = rgb
+                    .iter()
+                    .map(|c| match c {
+                        dbus::Value::Double(c) if (0.0..=1.0).contains(c) => {
+                            Some((c * 255.0).round() as u8)
+                        }
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>();
+                self.accent = match channels.as_deref() {
+                    Some(&[r, g, b]) => Some(Rgb(r, g, b)),
+                    _ => None,
+                };
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn theme(&self) -> Theme {
+        let desktop_palette = || scheme::desktop_palette(self.dark);
+        let mut palette = match (self.dark, self.high_contrast) {
+            (false, false) => match desktop_palette() {
+                Some(palette) => palette,
+                None if self.accent.is_none() => return Theme::Light,
+                None => Palette::LIGHT,
+            },
+            (true, false) => match desktop_palette() {
+                Some(palette) => palette,
+                None if self.accent.is_none() => return Theme::Dark,
+                None => Palette::DARK,
+            },
+            (false, true) => Palette::HIGH_CONTRAST,
+            (true, true) => Palette::HIGH_CONTRAST_DARK,
+        };
+        // High-contrast palettes are carefully chosen, so the accent color doesn't apply to them.
+        if let Some(accent) = self.accent
+            && !self.high_contrast
+        {
+            palette.accent = accent;
+            palette.focus = accent;
+        }
+        Theme::Custom(palette)
+    }
+}
+
+/// Watches the desktop's appearance preferences for changes.
+pub(crate) struct ThemeWatcher {
+    bus: dbus::Connection,
+    appearance: Appearance,
+}
+
+impl ThemeWatcher {
+    /// Subscribes to changes of the color scheme on the session bus, and returns the current theme.
+    ///
+    /// Fails if the desktop portal isn't available.
+    pub(crate) fn new() -> Result<(Self, Theme), Error> {
+        Self::subscribe(dbus::Connection::session()?)
+    }
+
+    pub(crate) fn subscribe(mut bus: dbus::Connection) -> Result<(Self, Theme), Error> {
+        // Subscribe first, so that no change is missed between reading the theme and subscribing.
+        bus.add_match(&format!(
+            "type='signal',interface='{SETTINGS}',member='SettingChanged',arg0='{APPEARANCE}'"
+        ))?;
+        let appearance = Appearance::read(&mut bus)?;
+        Ok((Self { bus, appearance }, appearance.theme()))
+    }
+
+    /// Returns the file descriptor to wait on before calling [`ThemeWatcher::process`].
+    pub(crate) fn fd(&self) -> BorrowedFd<'_> {
+        self.bus.as_fd()
+    }
+
+    /// Returns whether messages are waiting to be processed without `fd` becoming readable.
+    pub(crate) fn has_queued(&self) -> bool {
+        self.bus.has_queued()
+    }
+
+    /// Reads an incoming message, and returns the new theme if the appearance has changed.
+    pub(crate) fn process(&mut self) -> Result<Option<Theme>, Error> {
+        let msg = self.bus.receive()?;
+        if msg.kind != dbus::MessageType::Signal
+            || msg.interface.as_deref() != Some(SETTINGS)
+            || msg.member.as_deref() != Some("SettingChanged")
+        {
+            return Ok(None);
+        }
+        match &*msg.body {
+            [namespace, key, value] if namespace.as_str() == Some(APPEARANCE) => {
+                let changed = key
+                    .as_str()
+                    .is_some_and(|key| self.appearance.set(key, value));
+                Ok(changed.then(|| self.appearance.theme()))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+const SETTINGS: &str = "org.freedesktop.portal.Settings";
+const APPEARANCE: &str = "org.freedesktop.appearance";
+
+/// Reads a setting from the XDG desktop portal.
+///
+/// See <https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.Settings.html>.
+fn read_portal_setting(
+    conn: &mut dbus::Connection,
+    namespace: &str,
+    key: &str,
+) -> Result<dbus::Value, Error> {
+    // `Read` is deprecated in favor of `ReadOne`, but the latter is only available in newer
+    // versions of the portal. `Read` wraps the value in an additional variant, which callers
+    // ignore anyways.
+    let reply = conn.call(dbus::Message::method_call(
+        "org.freedesktop.portal.Desktop",
+        "/org/freedesktop/portal/desktop",
+        SETTINGS,
+        "Read",
+        vec![
+            dbus::Value::String(namespace.into()),
+            dbus::Value::String(key.into()),
+        ],
+    ))?;
+    reply
+        .body
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::new("empty reply from the desktop portal"))
+}
@@ -0,0 +1,335 @@
+//! Reads the color scheme of the installed KDE or GTK theme.
+//!
+//! Neither toolkit exposes its colors over a protocol we could query, so this reads their
+//! configuration files directly:
+//!
+//! - KDE: the `[Colors:*]` groups in `~/.config/kdeglobals`
+//! - GTK: the `@define-color` named colors of the theme selected in `gtk-3.0/settings.ini`, and of
+//!   the user's own `gtk-3.0/gtk.css`
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{Palette, Rgb};
+
+/// How deeply `@import`s and `@name` color references are followed.
+const MAX_DEPTH: u32 = 8;
+
+/// Returns the palette of the desktop's color scheme, if it is light (or dark, if `dark` is
+/// `true`).
+///
+/// Schemes that don't match the preferred color scheme are ignored, since they are likely stale
+/// or meant for other applications.
+pub(super) fn desktop_palette(dark: bool) -> Option<Palette> {
+    // Snapshot tests must not depend on the configuration of the system.
+    if cfg!(test) {
+        return None;
+    }
+    let config_home = config_home()?;
+    let kde = env::var("XDG_CURRENT_DESKTOP")
+        .is_ok_and(|desktop| desktop.split(':').any(|d| d.eq_ignore_ascii_case("kde")));
+    let kde_palette = || {
+        let kdeglobals = fs::read_to_string(config_home.join("kdeglobals")).ok()?;
+        kde_palette(&kdeglobals)
+    };
+    let palette = if kde {
+        kde_palette().or_else(|| gtk_palette(&config_home, dark))
+    } else {
+        gtk_palette(&config_home, dark)
+    };
+    palette.filter(|palette| is_dark(palette.background) == dark)
+}
+
+/// Maps the colors of a KDE color scheme (as found in `kdeglobals`) onto a palette.
+pub(crate) fn kde_palette(kdeglobals: &str) -> Option<Palette> {
+    let color = |group: &str, key: &str| {
+        let value = ini_value(kdeglobals, group, key)?;
+        let rgb = value
+            .split(',')
+            .map(|c| c.trim().parse::<u8>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        match *rgb {
+            // KDE also allows an alpha channel, which isn't meaningful for window backgrounds.
+            [r, g, b] | [r, g, b, _] => Some(Rgb(r, g, b)),
+            _ => None,
+        }
+    };
+    Some(
+        SchemeColors {
+            background: color("Colors:Window", "BackgroundNormal")?,
+            text: color("Colors:Window", "ForegroundNormal")?,
+            button: color("Colors:Button", "BackgroundNormal"),
+            accent: color("Colors:Window", "ForegroundLink"),
+            focus: color("Colors:View", "DecorationFocus"),
+        }
+        .palette(),
+    )
+}
+
+/// Reads the named colors of the user's GTK theme, and maps them onto a palette.
+fn gtk_palette(config_home: &Path, dark: bool) -> Option<Palette> {
+    let settings = fs::read_to_string(config_home.join("gtk-3.0/settings.ini")).unwrap_or_default();
+    let theme = ini_value(&settings, "Settings", "gtk-theme-name");
+    let dark = dark
+        || ini_value(&settings, "Settings", "gtk-application-prefer-dark-theme")
+            .is_some_and(|v| v == "1" || v == "true");
+
+    let mut colors = GtkColors::default();
+    if let Some(theme) = theme {
+        let theme_dir = theme_dirs()
+            .into_iter()
+            .map(|dir| dir.join(theme).join("gtk-3.0"))
+            .find(|dir| dir.is_dir());
+        if let Some(dir) = theme_dir {
+            let dark_css = dir.join("gtk-dark.css");
+            if dark && dark_css.is_file() {
+                colors.read(&dark_css, 0);
+            } else {
+                colors.read(&dir.join("gtk.css"), 0);
+            }
+        }
+    }
+    // The user's own stylesheet is loaded last, and overrides the theme.
+    colors.read(&config_home.join("gtk-3.0/gtk.css"), 0);
+    colors.palette()
+}
+
+/// Named colors defined in GTK stylesheets with `@define-color`.
+#[derive(Debug, Default)]
+pub(crate) struct GtkColors {
+    colors: HashMap<String, String>,
+}
+
+impl GtkColors {
+    /// Reads the named colors of the stylesheet at `path`, following its `@import`s.
+    ///
+    /// Stylesheets that cannot be read are ignored.
+    fn read(&mut self, path: &Path, depth: u32) {
+        if let Ok(css) = fs::read_to_string(path) {
+            self.parse(&css, path.parent(), depth);
+        }
+    }
+
+    /// Collects the named colors defined in `css`.
+    ///
+    /// Relative `@import`s are resolved against `dir`, or ignored if it is `None`.
+    pub(crate) fn parse(&mut self, css: &str, dir: Option<&Path>, depth: u32) {
+        let css = strip_comments(css);
+        for statement in css.split(';').map(str::trim) {
+            // Rule blocks don't end with a `;`, so the statement may be preceded by one.
+            let statement = statement.rsplit('}').next().unwrap_or_default().trim();
+            if let Some(definition) = statement.strip_prefix("@define-color") {
+                if let Some((name, value)) = definition.trim().split_once(char::is_whitespace) {
+                    self.colors.insert(name.into(), value.trim().into());
+                }
+            } else if let Some(import) = statement.strip_prefix("@import") {
+                let Some(dir) = dir.filter(|_| depth < MAX_DEPTH) else {
+                    continue;
+                };
+                let target = import
+                    .trim()
+                    .trim_start_matches("url(")
+                    .trim_end_matches(')')
+                    .trim_matches(['"', '\'']);
+                // `resource://` imports point into the compiled-in resources of GTK.
+                if !target.contains(':') {
+                    self.read(&dir.join(target), depth + 1);
+                }
+            }
+        }
+    }
+
+    /// Resolves the named color `name`.
+    fn get(&self, name: &str) -> Option<Rgb> {
+        self.resolve(self.colors.get(name)?, 0)
+    }
+
+    /// Evaluates a GTK color expression.
+    ///
+    /// Supports hex colors, `rgb()`/`rgba()`, references to other named colors, and the `mix()`
+    /// and `alpha()` functions (the alpha channel is ignored). Other expressions evaluate to `None`.
+    fn resolve(&self, value: &str, depth: u32) -> Option<Rgb> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let value = value.trim();
+        if let Some(name) = value.strip_prefix('@') {
+            return self.resolve(self.colors.get(name)?, depth + 1);
+        }
+        if value.starts_with('#') {
+            return value.parse().ok();
+        }
+        let (function, args) = value.strip_suffix(')')?.split_once('(')?;
+        let args = split_args(args);
+        match (function.trim(), &*args) {
+            ("rgb", [r, g, b]) | ("rgba", [r, g, b, _]) => {
+                let channel = |c: &str| -> Option<u8> {
+                    let c = c.trim();
+                    Some(match c.strip_suffix('%') {
+                        Some(percent) => (percent.parse::<f32>().ok()? * 2.55).round() as u8,
+                        None => c.parse::<f32>().ok()?.round() as u8,
+                    })
+                };
+                Some(Rgb(channel(r)?, channel(g)?, channel(b)?))
+            }
+            ("alpha", [color, _]) => self.resolve(color, depth + 1),
+            ("mix", [a, b, t]) => Some(mix(
+                self.resolve(a, depth + 1)?,
+                self.resolve(b, depth + 1)?,
+                t.trim().parse().ok()?,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Maps the named colors onto a palette.
+    ///
+    /// Supports the names used by GTK 3 themes, and the ones introduced by libadwaita.
+    pub(crate) fn palette(&self) -> Option<Palette> {
+        let color = |names: &[&str]| names.iter().find_map(|name| self.get(name));
+        Some(
+            SchemeColors {
+                background: color(&["theme_bg_color", "window_bg_color"])?,
+                text: color(&["theme_fg_color", "window_fg_color"])?,
+                button: None,
+                accent: color(&["theme_selected_bg_color", "accent_bg_color"]),
+                focus: None,
+            }
+            .palette(),
+        )
+    }
+}
+
+/// The colors a desktop color scheme specifies.
+///
+/// Whatever isn't specified is derived from the other colors.
+struct SchemeColors {
+    background: Rgb,
+    text: Rgb,
+    button: Option<Rgb>,
+    accent: Option<Rgb>,
+    focus: Option<Rgb>,
+}
+
+impl SchemeColors {
+    fn palette(&self) -> Palette {
+        let (base, button_amount) = if is_dark(self.background) {
+            (Palette::DARK, 0.25)
+        } else {
+            (Palette::LIGHT, 0.1)
+        };
+        let button = self
+            .button
+            .unwrap_or_else(|| mix(self.background, self.text, button_amount));
+        let accent = self.accent.unwrap_or(base.accent);
+        Palette {
+            background: self.background,
+            text: self.text,
+            button,
+            button_hover: mix(button, self.text, 0.1),
+            button_pressed: mix(button, self.text, 0.25),
+            button_outline: mix(self.background, self.text, 0.8),
+            accent,
+            focus: self.focus.unwrap_or(accent),
+            selection: mix(self.background, accent, 0.4),
+            ..base
+        }
+    }
+}
+
+/// Returns the value of `key` in the `[group]` section of an INI-style file.
+fn ini_value<'a>(ini: &'a str, group: &str, key: &str) -> Option<&'a str> {
+    let mut in_group = false;
+    let mut found = None;
+    for line in ini.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_group = name == group;
+        } else if in_group
+            && let Some((k, v)) = line.split_once('=')
+            && k.trim() == key
+        {
+            // Like KDE and GLib, let later entries override earlier ones.
+            found = Some(v.trim());
+        }
+    }
+    found
+}
+
+/// Removes `/* ... */` comments from a stylesheet.
+fn strip_comments(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        match rest[start + 2..].find("*/") {
+            Some(end) => rest = &rest[start + 2 + end + 2..],
+            None => rest = "",
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Splits function arguments at the commas that aren't nested in parentheses.
+fn split_args(args: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut nesting = 0;
+    let mut start = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => nesting += 1,
+            ')' => nesting -= 1,
+            ',' if nesting == 0 => {
+                parts.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&args[start..]);
+    parts
+}
+
+/// Blends `a` with `amount` of `b`.
+fn mix(a: Rgb, b: Rgb, amount: f32) -> Rgb {
+    let amount = amount.clamp(0.0, 1.0);
+    let channel =
+        |a: u8, b: u8| (f32::from(a) * (1.0 - amount) + f32::from(b) * amount).round() as u8;
+    Rgb(channel(a.0, b.0), channel(a.1, b.1), channel(a.2, b.2))
+}
+
+fn is_dark(color: Rgb) -> bool {
+    let luma = 0.299 * f32::from(color.0) + 0.587 * f32::from(color.1) + 0.114 * f32::from(color.2);
+    luma < 128.0
+}
+
+fn config_home() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| Some(Path::new(&env::var_os("HOME")?).join(".config")))
+}
+
+/// Returns the directories GTK looks for themes in, in order of precedence.
+fn theme_dirs() -> Vec<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".local/share")));
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+
+    let mut dirs = Vec::new();
+    dirs.extend(data_home.map(|dir| dir.join("themes")));
+    dirs.extend(home.map(|home| home.join(".themes")));
+    dirs.extend(
+        data_dirs
+            .split(':')
+            .map(|dir| Path::new(dir).join("themes")),
+    );
+    dirs
+}
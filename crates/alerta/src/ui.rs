@@ -1,19 +1,25 @@
 //! Handles drawing and layouting of the UI, and processes input events for the UI.
 
 mod font;
+mod frame;
+mod text_view;
 
-use std::{cmp, f32::consts::PI};
+pub(crate) use font::{FontPrefs, GlyphImage, Text};
+pub(crate) use frame::FRAME_MARGIN;
 
-use euclid::{Size2D, Transform2D, point2, size2};
+use std::{cmp, f32::consts::PI, mem, ops::Range};
+
+use euclid::{Size2D, Transform2D, UnknownUnit, default::Rect, point2, size2, vec2};
 use raqote::{
-    BlendMode, Color, DrawTarget, IntPoint, IntRect, Path, PathBuilder, SolidSource, Source,
+    BlendMode, Color, DrawTarget, Image, IntPoint, IntRect, Path, PathBuilder, SolidSource, Source,
     StrokeStyle,
 };
 
-use crate::{Answer, Icon, MouseButton, Theme, WindowEvent, ui::font::Font};
-
-#[derive(Debug, Clone, Copy)]
-struct Rgb(u8, u8, u8);
+use crate::{
+    Answer, CursorPos, Icon, Key, Modifiers, MouseButton, Palette, Rgb, Selection, Theme,
+    WindowEvent,
+    ui::{font::Font, frame::Frame, text_view::TextView},
+};
 
 impl From<Rgb> for Color {
     fn from(Rgb(r, g, b): Rgb) -> Self {
@@ -31,56 +37,70 @@ impl From<Rgb> for Source<'_> {
     }
 }
 
-const fn rgb(r: u8, g: u8, b: u8) -> Rgb {
-    Rgb(r, g, b)
-}
-
-struct Colors {
-    window_bg: Rgb,
-    text: Rgb,
-
-    button: Rgb,
-    button_hover: Rgb,
-    button_pressed: Rgb,
-    button_outline: Rgb,
-}
-
-static THEME_LIGHT: Colors = Colors {
-    window_bg: rgb(230, 230, 230),
-    text: rgb(0, 0, 0),
-    button: rgb(210, 210, 210),
-    button_hover: rgb(180, 180, 180),
-    button_pressed: rgb(150, 150, 150),
-    button_outline: rgb(40, 40, 40),
-};
-static THEME_DARK: Colors = Colors {
-    window_bg: rgb(30, 30, 30),
-    text: rgb(255, 255, 255),
-    button: rgb(90, 90, 90),
-    button_hover: rgb(110, 110, 110),
-    button_pressed: rgb(160, 160, 160),
-    button_outline: rgb(200, 200, 200),
-};
-
 const WINDOW_PADDING: i32 = 10;
 const BTN_PADDING: i32 = 12;
 const SPACING: i32 = 10;
 const BTN_RADIUS: f32 = 5.0;
+const SCROLLBAR_WIDTH: i32 = 8;
+/// Number of lines to scroll per mouse wheel notch.
+const SCROLL_LINES: i32 = 3;
+/// Size of the arrow next to the "Show details" label.
+const ARROW_SIZE: i32 = 8;
+/// Padding between the edge of the details pane and the text inside.
+const DETAILS_PADDING: i32 = 6;
+const DETAILS_MIN_WIDTH: i32 = 600;
+const DETAILS_MIN_HEIGHT: i32 = 80;
+const DETAILS_MAX_HEIGHT: i32 = 250;
+/// Padding above and below the title in the title bar.
+const TITLE_BAR_PADDING: i32 = 6;
+
+/// Separator line used when copying the dialog contents (matches the format used on Windows).
+const COPY_SEPARATOR: &str = "---------------------------";
+
+/// A request from the [`Ui`] to the windowing system.
+#[derive(Debug)]
+pub(crate) enum Action {
+    /// The dialog has been answered and should be closed.
+    Answer(Answer),
+    /// The user is dragging the window around.
+    StartDrag,
+    /// The given text should be placed in a selection buffer.
+    SetSelection(Selection, String),
+}
 
 pub(crate) struct Ui {
-    colors: &'static Colors,
+    colors: Palette,
+    font: Font,
+    font_prefs: FontPrefs,
     pub(crate) canvas: DrawTarget,
+    /// Size of the dialog, which is also the size of the canvas.
+    size: Size2D<i32, UnknownUnit>,
+    title: String,
     icon: DrawTarget,
     icon_pos: IntPoint,
-    message: DrawTarget,
-    message_pos: IntPoint,
+    message: TextView,
+    details: Option<Details>,
+    title_bar: Option<TitleBar>,
+    /// The rounded corners and shadow of translucent windows (see [`Ui::with_frame`]).
+    frame: Option<Frame>,
+    framed: bool,
     buttons: Vec<Button>,
+    max_height: i32,
+    /// Window size imposed from the outside (see [`Ui::resize`]).
+    fixed_width: Option<i32>,
+    fixed_height: Option<i32>,
+    min_size: Size2D<i32, ()>,
     cursor_pos: Option<IntPoint>,
     mouse_pressed: bool,
     mouse_dragging: bool,
+    /// The parts as they were last drawn.
+    drawn: Vec<Part>,
+    /// Whether the whole canvas has to be redrawn (for example, after a new layout).
+    full_damage: bool,
 }
 
 struct Button {
+    label: String,
     /// Text size + padding.
     min_size: Size2D<i32, ()>,
     size: Size2D<i32, ()>,
@@ -88,6 +108,147 @@ struct Button {
     text: DrawTarget,
 }
 
+/// The expandable "details" section.
+struct Details {
+    /// Monospace font for the details text.
+    font: Font,
+    view: TextView,
+    /// Texts of the toggle in the collapsed and expanded state.
+    labels: [String; 2],
+    show_label: DrawTarget,
+    hide_label: DrawTarget,
+    /// The clickable "Show details" / "Hide details" toggle.
+    toggle: IntRect,
+    /// The background of the details text, only shown when expanded.
+    pane: IntRect,
+    expanded: bool,
+}
+
+/// The title bar that is drawn when there is no window manager to decorate the window.
+struct TitleBar {
+    text: DrawTarget,
+    height: i32,
+    /// The close button at the right end.
+    close: IntRect,
+}
+
+impl TitleBar {
+    /// Draws the title bar, except for the close button.
+    fn draw(&self, painter: &mut dyn Painter, colors: &Palette, width: i32) {
+        fill(
+            painter,
+            IntRect::from_size(size2(width, self.height)),
+            colors.button,
+        );
+        fill(
+            painter,
+            IntRect::new(point2(0, self.height - 1), point2(width, self.height)),
+            colors.button_outline,
+        );
+
+        // Cut the title off before it runs into the close button.
+        let text_width = cmp::min(
+            self.text.width(),
+            self.close.min.x - SPACING - WINDOW_PADDING,
+        );
+        painter.draw_image(
+            &self.text,
+            IntRect::from_size(size2(cmp::max(text_width, 0), self.text.height())),
+            point2(WINDOW_PADDING, (self.height - self.text.height()) / 2),
+        );
+    }
+
+    /// Draws the close button on a `background` that depends on whether it is hovered or pressed.
+    ///
+    /// Returns the area that was drawn.
+    fn draw_close(&self, painter: &mut dyn Painter, colors: &Palette, background: Rgb) -> IntRect {
+        // Leave out the separator line at the bottom.
+        let rect = IntRect::new(
+            self.close.min,
+            point2(self.close.max.x, self.close.max.y - 1),
+        );
+        fill(painter, rect, background);
+
+        // An "X" in the middle of the button.
+        let (x, y) = (
+            (self.close.min.x + self.close.max.x) as f32 / 2.0,
+            (self.close.min.y + self.close.max.y) as f32 / 2.0,
+        );
+        let r = ARROW_SIZE as f32 / 2.0;
+        let mut pb = PathBuilder::new();
+        pb.move_to(x - r, y - r);
+        pb.line_to(x + r, y + r);
+        pb.move_to(x + r, y - r);
+        pb.line_to(x - r, y + r);
+        painter.stroke_path(&pb.finish(), colors.text, 1.5);
+        rect
+    }
+}
+
+impl Details {
+    /// Draws the "Show details" / "Hide details" toggle, and returns the area that was drawn.
+    fn draw_toggle(&self, painter: &mut dyn Painter, colors: &Palette, hovered: bool) -> IntRect {
+        // Include the underline below the label.
+        let rect = IntRect::new(
+            self.toggle.min,
+            point2(self.toggle.max.x, self.toggle.max.y + 2),
+        );
+        fill(painter, rect, colors.background);
+
+        // A triangle pointing right (collapsed) or down (expanded).
+        let (x, y) = (self.toggle.min.x as f32, self.toggle.min.y as f32);
+        let size = ARROW_SIZE as f32;
+        let y = y + (self.toggle.height() as f32 - size) / 2.0;
+        let mut pb = PathBuilder::new();
+        if self.expanded {
+            pb.move_to(x, y + size * 0.25);
+            pb.line_to(x + size, y + size * 0.25);
+            pb.line_to(x + size * 0.5, y + size * 0.75 + 1.0);
+        } else {
+            pb.move_to(x + size * 0.25, y);
+            pb.line_to(x + size * 0.75 + 1.0, y + size * 0.5);
+            pb.line_to(x + size * 0.25, y + size);
+        }
+        pb.close();
+        painter.fill_path(&pb.finish(), colors.accent);
+
+        let label = if self.expanded {
+            &self.hide_label
+        } else {
+            &self.show_label
+        };
+        let label_pos = point2(
+            self.toggle.min.x + ARROW_SIZE + SPACING,
+            self.toggle.min.y + (self.toggle.height() - label.height()) / 2,
+        );
+        painter.place_image(label, label_pos);
+        if hovered {
+            // Underline the label, like a link.
+            fill(
+                painter,
+                IntRect::from_origin_and_size(
+                    point2(label_pos.x, label_pos.y + label.height() + 1),
+                    size2(label.width(), 1),
+                ),
+                colors.accent,
+            );
+        }
+        rect
+    }
+
+    /// Draws the expanded details pane, and returns the area that was drawn.
+    fn draw_pane(&self, painter: &mut dyn Painter, colors: &Palette) -> IntRect {
+        fill(painter, self.pane, colors.background);
+        let path =
+            rounded_rect(size2(self.pane.width(), self.pane.height()), BTN_RADIUS).transform(
+                &Transform2D::translation(self.pane.min.x as f32, self.pane.min.y as f32),
+            );
+        painter.fill_path(&path, colors.button);
+        self.view.draw(painter, colors);
+        self.pane
+    }
+}
+
 impl Button {
     fn contains(&self, pt: IntPoint) -> bool {
         pt.x >= self.pos.x
@@ -95,107 +256,600 @@ impl Button {
             && pt.x < self.pos.x + self.size.width
             && pt.y < self.pos.y + self.size.height
     }
+
+    /// Draws the button with the given fill color, and returns the area that was drawn.
+    ///
+    /// The default button (the first one) is outlined in the accent color.
+    fn draw(
+        &self,
+        painter: &mut dyn Painter,
+        colors: &Palette,
+        color: Rgb,
+        default: bool,
+    ) -> IntRect {
+        let width = f32::from(colors.outline_width);
+        let outline = if default {
+            colors.accent
+        } else {
+            colors.button_outline
+        };
+        // The outline is centered on the edge of the button, so half of it lies outside.
+        let margin = width.ceil() as i32 / 2 + 1;
+        let rect =
+            IntRect::from_origin_and_size(self.pos, self.size.cast_unit()).inflate(margin, margin);
+        fill(painter, rect, colors.background);
+
+        let path = rounded_rect(self.size, BTN_RADIUS).transform(&Transform2D::translation(
+            self.pos.x as f32,
+            self.pos.y as f32,
+        ));
+        painter.fill_path(&path, color);
+        painter.stroke_path(&path, outline, width);
+
+        let text_x = self.pos.x + self.size.width / 2 - self.text.width() / 2;
+        painter.place_image(&self.text, point2(text_x, self.pos.y + BTN_PADDING));
+        rect
+    }
 }
 
-impl Ui {
-    pub(crate) fn new(icon: Icon, theme: Theme, text: &str, buttons: &[&str]) -> Self {
-        const MIN_WIDTH: i32 = 400;
-        const MIN_HEIGHT: i32 = 100;
+/// A part of the dialog that can be redrawn on its own, along with everything that determines its
+/// appearance.
+///
+/// Comparing the parts before and after processing events tells which areas have to be redrawn.
+/// Changes to anything else require a new layout, which redraws the whole dialog.
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Message {
+        scroll: i32,
+        selection: Range<usize>,
+    },
+    Close {
+        background: Rgb,
+    },
+    Toggle {
+        hovered: bool,
+    },
+    Details {
+        scroll: i32,
+        selection: Range<usize>,
+    },
+    Button {
+        index: usize,
+        color: Rgb,
+        default: bool,
+    },
+}
 
-        let colors = match theme {
-            Theme::Light => &THEME_LIGHT,
-            Theme::Dark => &THEME_DARK,
-        };
+impl Ui {
+    pub(crate) fn new(icon: Icon, theme: Theme, title: &str, text: &str, buttons: &[&str]) -> Self {
+        let colors = theme.palette();
 
         let icon = icon.get();
         let font = Font::load();
 
-        // Compute sizes of the individual components first.
-        let message_pos_x = icon.width() + WINDOW_PADDING + SPACING;
-        let message_space = MIN_WIDTH - message_pos_x - WINDOW_PADDING;
-        let message = font
-            .render(text)
-            .with_max_width(message_space as f32)
-            .with_color(colors.text)
-            .finish();
-
-        let mut btn_height = 0;
-        let mut buttons = buttons
+        // Button labels are rendered by `render_labels`, since they depend on the colors.
+        let buttons = buttons
             .iter()
-            .map(|txt| {
-                let text = font.render(txt).with_color(colors.text).finish();
-                let w = text.width() + 2 * BTN_PADDING;
-                let h = text.height() + 2 * BTN_PADDING;
-                btn_height = cmp::max(btn_height, h);
-                Button {
-                    min_size: size2(w, h),
-                    size: Size2D::zero(),
-                    pos: IntPoint::zero(),
-                    text,
-                }
+            .map(|txt| Button {
+                label: txt.to_string(),
+                min_size: Size2D::zero(),
+                size: Size2D::zero(),
+                pos: IntPoint::zero(),
+                text: DrawTarget::new(0, 0),
             })
             .collect::<Vec<_>>();
+        // The message is rendered by `layout`, since it depends on the available space.
+        let message = TextView::new(text.to_string(), font.render("").finish_text());
 
-        // Now we can compute the required window size.
+        let mut this = Self {
+            colors,
+            font,
+            font_prefs: FontPrefs::default(),
+            canvas: DrawTarget::new(0, 0),
+            size: Size2D::zero(),
+            title: title.to_string(),
+            icon,
+            icon_pos: point2(WINDOW_PADDING, WINDOW_PADDING),
+            message,
+            details: None,
+            title_bar: None,
+            frame: None,
+            framed: false,
+            buttons,
+            max_height: i32::MAX,
+            fixed_width: None,
+            fixed_height: None,
+            min_size: Size2D::zero(),
+            cursor_pos: None,
+            mouse_pressed: false,
+            mouse_dragging: false,
+            drawn: Vec::new(),
+            full_damage: true,
+        };
+        this.render_labels();
+        this.layout();
+        this
+    }
 
-        let mut win_width = cmp::max(
-            MIN_WIDTH,
-            icon.width() + SPACING + message.width() + 2 * WINDOW_PADDING,
-        );
-        let win_height_message = message.height() + btn_height + SPACING + 2 * WINDOW_PADDING;
-        let win_height_icon = icon.height() + btn_height + SPACING * 2 + WINDOW_PADDING;
-        let win_height = cmp::max(MIN_HEIGHT, cmp::max(win_height_message, win_height_icon));
+    /// Adds an expandable section containing `details` below the message.
+    ///
+    /// The section is collapsed by default.
+    pub(crate) fn with_details(mut self, details: &str) -> Self {
+        let font = Font::load_monospace(self.font_prefs.monospace_pixel_size());
+        let view = TextView::new(details.to_string(), font.render("").finish_text());
+        self.details = Some(Details {
+            font,
+            view,
+            labels: ["Show details".into(), "Hide details".into()],
+            show_label: DrawTarget::new(0, 0),
+            hide_label: DrawTarget::new(0, 0),
+            toggle: IntRect::zero(),
+            pane: IntRect::zero(),
+            expanded: false,
+        });
+        self.render_labels();
+        self.layout();
+        self
+    }
 
-        let message_space_y = win_height - btn_height - WINDOW_PADDING * 2 - SPACING;
-        let message_pos_y = (message_space_y - message.height()) / 2 + WINDOW_PADDING;
+    /// Adds a title bar with the dialog title and a close button.
+    ///
+    /// This is used when there is no window manager that would decorate the window.
+    pub(crate) fn with_title_bar(mut self) -> Self {
+        self.title_bar = Some(TitleBar {
+            text: DrawTarget::new(0, 0),
+            height: 0,
+            close: IntRect::zero(),
+        });
+        self.render_labels();
+        self.layout();
+        self
+    }
+
+    /// Gives the dialog rounded corners and a shadow, for windows that can be translucent.
+    ///
+    /// The shadow is drawn in a margin of [`FRAME_MARGIN`] around the dialog, which is included in
+    /// the window size and coordinates.
+    pub(crate) fn with_frame(mut self) -> Self {
+        self.framed = true;
+        self.layout();
+        self
+    }
+
+    /// Applies the desktop's font preferences, reloading the fonts.
+    pub(crate) fn with_font_prefs(mut self, prefs: &FontPrefs) -> Self {
+        self.font_prefs = prefs.clone();
+        self.font = Font::load_preferred(prefs);
+        if let Some(details) = &mut self.details {
+            details.font = Font::load_monospace(prefs.monospace_pixel_size());
+        }
+        self.render_labels();
+        self.layout();
+        self
+    }
+
+    /// Puts the mouse cursor on the button at `index`, as if it was hovered (or pressed, if
+    /// `pressed` is set).
+    pub(crate) fn highlight_button(&mut self, index: usize, pressed: bool) {
+        if let Some(center) = self.button_center(index) {
+            self.cursor_pos = Some(center);
+            self.mouse_pressed = pressed;
+        }
+    }
+
+    /// Returns the position of the button at `index` in the window, for clicking it.
+    pub(crate) fn button_position(&self, index: usize) -> Option<CursorPos> {
+        let pos = self.button_center(index)? + vec2(self.margin(), self.margin());
+        Some(CursorPos {
+            x: pos.x as i16,
+            y: pos.y as i16,
+        })
+    }
+
+    fn button_center(&self, index: usize) -> Option<IntPoint> {
+        let button = self.buttons.get(index)?;
+        Some(button.pos + vec2(button.size.width / 2, button.size.height / 2))
+    }
+
+    /// Sets the texts of the details toggle (which default to English).
+    pub(crate) fn set_details_labels(&mut self, show: &str, hide: &str) {
+        if let Some(details) = &mut self.details {
+            details.labels = [show.into(), hide.into()];
+            self.render_labels();
+            self.layout();
+        }
+    }
+
+    /// Switches to the colors of a different theme.
+    pub(crate) fn set_theme(&mut self, theme: Theme) {
+        self.colors = theme.palette();
+        self.render_labels();
+        self.layout();
+    }
+
+    /// Renders the text of the buttons, the details toggle and the title bar, which depend on the
+    /// colors.
+    fn render_labels(&mut self) {
+        for btn in &mut self.buttons {
+            btn.text = self
+                .font
+                .render(&btn.label)
+                .with_color(self.colors.text)
+                .finish();
+            btn.min_size = size2(
+                btn.text.width() + 2 * BTN_PADDING,
+                btn.text.height() + 2 * BTN_PADDING,
+            );
+        }
+        if let Some(details) = &mut self.details {
+            let label = |text| {
+                self.font
+                    .render(text)
+                    .with_color(self.colors.accent)
+                    .finish()
+            };
+            details.show_label = label(&details.labels[0]);
+            details.hide_label = label(&details.labels[1]);
+        }
+        if let Some(bar) = &mut self.title_bar {
+            bar.text = self
+                .font
+                .render(&self.title)
+                .with_color(self.colors.text)
+                .finish();
+        }
+    }
+
+    /// Limits the height of the dialog.
+    ///
+    /// If the message doesn't fit, it will be made scrollable.
+    pub(crate) fn set_max_height(&mut self, max_height: i32) {
+        self.max_height = max_height;
+        self.layout();
+    }
+
+    /// Lays the dialog out for a window of the given size (for example, after the user resized
+    /// it).
+    ///
+    /// The size is clamped to [`Ui::min_size`].
+    pub(crate) fn resize(&mut self, width: i32, height: i32) {
+        let (width, height) = (width - 2 * self.margin(), height - 2 * self.margin());
+        if size2(width, height) == self.size {
+            return;
+        }
+        self.fixed_width = Some(width);
+        self.fixed_height = Some(height);
+        self.layout();
+    }
+
+    /// Returns the smallest window size the dialog can be laid out in.
+    pub(crate) fn min_size(&self) -> Size2D<i32, ()> {
+        self.min_size + size2(2 * self.margin(), 2 * self.margin())
+    }
+
+    /// Returns the width of the space around the dialog (see [`Ui::with_frame`]).
+    pub(crate) fn margin(&self) -> i32 {
+        if self.framed { FRAME_MARGIN } else { 0 }
+    }
+
+    /// Returns the window contents: the canvas, along with the frame around it (if any).
+    pub(crate) fn contents(&self) -> &DrawTarget {
+        match &self.frame {
+            Some(frame) => &frame.image,
+            None => &self.canvas,
+        }
+    }
+
+    /// Computes the positions and sizes of all components, and (re)allocates the canvas.
+    ///
+    /// By default, the window is sized to fit its contents. After [`Ui::resize`], the contents are
+    /// fitted into the given size instead.
+    fn layout(&mut self) {
+        const MIN_WIDTH: i32 = 400;
+        const MIN_HEIGHT: i32 = 100;
+
+        let title_height = self
+            .title_bar
+            .as_ref()
+            .map_or(0, |bar| bar.text.height() + 2 * TITLE_BAR_PADDING);
+        self.icon_pos = point2(WINDOW_PADDING, WINDOW_PADDING + title_height);
+
+        let btn_height = self
+            .buttons
+            .iter()
+            .map(|btn| btn.min_size.height)
+            .max()
+            .unwrap_or(0);
 
         // Absolute minimum required width of the button row.
-        let width_sum = buttons.iter().map(|btn| btn.min_size.width).sum::<i32>();
-        let required_width =
-            width_sum + 2 * WINDOW_PADDING + (buttons.len().saturating_sub(1) as i32) * SPACING;
-        win_width = cmp::max(win_width, required_width);
+        let width_sum = self
+            .buttons
+            .iter()
+            .map(|btn| btn.min_size.width)
+            .sum::<i32>();
+        let required_width = width_sum
+            + 2 * WINDOW_PADDING
+            + (self.buttons.len().saturating_sub(1) as i32) * SPACING;
+        let mut min_width = cmp::max(MIN_WIDTH, required_width);
+        if self
+            .details
+            .as_ref()
+            .is_some_and(|details| details.expanded)
+        {
+            min_width = cmp::max(min_width, DETAILS_MIN_WIDTH);
+        }
+
+        let message_pos_x = self.icon.width() + WINDOW_PADDING + SPACING;
+        let (win_width, mut message_space) = match self.fixed_width {
+            Some(width) => {
+                let win_width = cmp::max(width, min_width);
+                let message_space = win_width - message_pos_x - WINDOW_PADDING;
+                self.message.set_text(self.render_message(message_space));
+                (win_width, message_space)
+            }
+            None => {
+                let message_space = MIN_WIDTH - message_pos_x - WINDOW_PADDING;
+                self.message.set_text(self.render_message(message_space));
+                let win_width = cmp::max(
+                    min_width,
+                    self.icon.width() + SPACING + self.message.content_width() + 2 * WINDOW_PADDING,
+                );
+                (win_width, message_space)
+            }
+        };
+
+        // Vertical space taken up by the details toggle and pane, including spacing.
+        let mut toggle_height = 0;
+        let mut pane_natural = 0;
+        let mut pane_min = 0;
+        if let Some(details) = &mut self.details {
+            toggle_height = self.font.line_height() as i32 + SPACING;
+            if details.expanded {
+                let text_space = win_width
+                    - 2 * WINDOW_PADDING
+                    - 2 * DETAILS_PADDING
+                    - SPACING
+                    - SCROLLBAR_WIDTH;
+                details.view.set_text(
+                    details
+                        .font
+                        .render(details.view.string())
+                        .with_max_width(text_space as f32)
+                        .with_color(self.colors.text)
+                        .finish_text(),
+                );
+                pane_natural = cmp::min(details.view.content_height(), DETAILS_MAX_HEIGHT)
+                    + 2 * DETAILS_PADDING;
+                pane_min = cmp::min(pane_natural, DETAILS_MIN_HEIGHT);
+            }
+        }
+        // Height of everything except the message area and the details pane.
+        let fixed_height = title_height
+            + btn_height
+            + SPACING
+            + 2 * WINDOW_PADDING
+            + toggle_height
+            + if pane_natural > 0 { SPACING } else { 0 };
+
+        // The message area is at least as tall as the icon.
+        let top_min = cmp::max(
+            self.icon.height() + SPACING - WINDOW_PADDING,
+            MIN_HEIGHT - btn_height - 2 * WINDOW_PADDING - SPACING,
+        );
+        let top_natural = cmp::max(top_min, self.message.content_height());
+
+        let min_height = fixed_height + top_min + pane_min;
+        let win_height = match self.fixed_height {
+            Some(height) => cmp::max(height, min_height),
+            None => cmp::max(
+                min_height,
+                cmp::min(fixed_height + top_natural + pane_natural, self.max_height),
+            ),
+        };
+        self.min_size = size2(min_width, min_height);
+
+        // The message gets the space it needs, and the details pane takes the rest. If there
+        // isn't enough room, the details pane is shrunk first (it is scrollable anyways).
+        let available = win_height - fixed_height;
+        let pane_height = if pane_natural > 0 {
+            cmp::max(pane_min, available - top_natural)
+        } else {
+            0
+        };
+        let message_space_y = available - pane_height;
+
+        if self.message.content_height() > message_space_y {
+            // The message doesn't fit, so it has to be scrolled. Make room for the scrollbar.
+            message_space -= SCROLLBAR_WIDTH + SPACING;
+            self.message.set_text(self.render_message(message_space));
+        }
+
+        let message_width = cmp::min(
+            self.message.content_width(),
+            win_width - message_pos_x - WINDOW_PADDING,
+        );
+        let message_height = cmp::min(self.message.content_height(), message_space_y);
+        let message_pos_y = (message_space_y - message_height) / 2 + WINDOW_PADDING + title_height;
+        self.message.set_viewport(IntRect::from_origin_and_size(
+            point2(message_pos_x, message_pos_y),
+            size2(message_width, message_height),
+        ));
+
+        if let Some(details) = &mut self.details {
+            let toggle_y = title_height + WINDOW_PADDING + message_space_y + SPACING;
+            details.toggle = IntRect::from_origin_and_size(
+                point2(WINDOW_PADDING, toggle_y),
+                size2(
+                    ARROW_SIZE
+                        + SPACING
+                        + cmp::max(details.show_label.width(), details.hide_label.width()),
+                    toggle_height - SPACING,
+                ),
+            );
+            details.pane = IntRect::from_origin_and_size(
+                point2(WINDOW_PADDING, toggle_y + toggle_height),
+                size2(win_width - 2 * WINDOW_PADDING, pane_height),
+            );
+            details.view.set_viewport(IntRect::from_origin_and_size(
+                details.pane.min + vec2(DETAILS_PADDING, DETAILS_PADDING),
+                size2(
+                    details.pane.width() - 2 * DETAILS_PADDING - SPACING - SCROLLBAR_WIDTH,
+                    pane_height - 2 * DETAILS_PADDING,
+                ),
+            ));
+        }
+
+        if let Some(bar) = &mut self.title_bar {
+            bar.height = title_height;
+            bar.close = IntRect::from_origin_and_size(
+                point2(win_width - title_height, 0),
+                size2(title_height, title_height),
+            );
+        }
 
         let mut x = WINDOW_PADDING;
-        let btn_width = (win_width - WINDOW_PADDING * 2 - SPACING * (buttons.len() as i32 - 1))
-            / buttons.len() as i32;
-        for btn in &mut buttons {
+        let btn_width =
+            (win_width - WINDOW_PADDING * 2 - SPACING * (self.buttons.len() as i32 - 1))
+                / self.buttons.len() as i32;
+        for btn in &mut self.buttons {
             btn.size = size2(btn_width, btn_height);
             btn.pos = point2(x, win_height - WINDOW_PADDING - btn_height);
 
             x += btn.size.width + SPACING;
         }
 
-        let mut this = Self {
-            colors,
-            canvas: DrawTarget::new(win_width, win_height),
-            icon,
-            icon_pos: point2(WINDOW_PADDING, WINDOW_PADDING),
-            message,
-            message_pos: point2(message_pos_x, message_pos_y),
-            buttons,
-            cursor_pos: None,
-            mouse_pressed: false,
-            mouse_dragging: false,
-        };
-        this.redraw();
-        this
+        self.size = size2(win_width, win_height);
+        self.canvas = DrawTarget::new(win_width, win_height);
+        self.frame = self.framed.then(|| Frame::new(self.size));
+        self.full_damage = true;
+        self.redraw();
+    }
+
+    fn render_message(&self, max_width: i32) -> Text {
+        self.font
+            .render(self.message.string())
+            .with_max_width(max_width as f32)
+            .with_color(self.colors.text)
+            .finish_text()
+    }
+
+    /// Returns the text views that are currently visible.
+    fn views_mut(&mut self) -> impl Iterator<Item = &mut TextView> {
+        let details = self
+            .details
+            .as_mut()
+            .filter(|details| details.expanded)
+            .map(|details| &mut details.view);
+        [&mut self.message].into_iter().chain(details)
+    }
+
+    /// Returns the text view that should be scrolled by keyboard and mouse wheel input.
+    ///
+    /// This is the one under the mouse cursor, or else the details pane (if it is expanded).
+    fn scroll_target(&mut self) -> &mut TextView {
+        let cursor = self.cursor_pos;
+        if let Some(details) = &mut self.details
+            && details.expanded
+            && cursor.is_none_or(|p| !self.message.contains(p))
+        {
+            return &mut details.view;
+        }
+        &mut self.message
     }
 
-    pub(crate) fn process_event(&mut self, event: WindowEvent) -> Option<Answer> {
+    pub(crate) fn process_event(&mut self, event: WindowEvent) -> Option<Action> {
+        let line_height = self.font.line_height() as i32;
         match event {
-            WindowEvent::CloseRequested => return Some(Answer::Closed),
-            WindowEvent::CursorEnter(pos) | WindowEvent::CursorMove(pos) => {
-                self.cursor_pos = Some(point2(pos.x.into(), pos.y.into()));
+            WindowEvent::CloseRequested => return Some(Action::Answer(Answer::Closed)),
+            WindowEvent::KeyPress(Key::Escape, mods) if mods == Modifiers::NONE => {
+                // ESC closes the dialog.
+                return Some(Action::Answer(Answer::Closed));
+            }
+            WindowEvent::KeyPress(Key::Char('c'), mods) if mods == Modifiers::CTRL => {
+                return Some(Action::SetSelection(Selection::Clipboard, self.copy_text()));
+            }
+            WindowEvent::KeyPress(key, Modifiers::NONE) => {
+                let view = self.scroll_target();
+                let page = view.viewport().height() - line_height;
+                match key {
+                    Key::Up => view.scroll_by(-line_height),
+                    Key::Down => view.scroll_by(line_height),
+                    Key::PageUp => view.scroll_by(-page),
+                    Key::PageDown => view.scroll_by(page),
+                    Key::Home => view.scroll_to(0),
+                    Key::End => view.scroll_to(i32::MAX),
+                    _ => {}
+                }
+            }
+            WindowEvent::Scroll(notches) => {
+                self.scroll_target()
+                    .scroll_by(i32::from(notches) * SCROLL_LINES * line_height);
+            }
+            WindowEvent::CursorEnter(pos) => {
+                self.cursor_pos = Some(self.cursor_point(pos));
                 self.mouse_dragging = self.mouse_pressed;
             }
+            WindowEvent::CursorMove(pos) => {
+                let pos = self.cursor_point(pos);
+                self.cursor_pos = Some(pos);
+                if let Some(view) = self.views_mut().find(|view| view.is_active()) {
+                    view.drag(pos);
+                } else if self.mouse_pressed {
+                    self.mouse_dragging = true;
+                    return Some(Action::StartDrag);
+                }
+            }
             WindowEvent::CursorLeave => self.cursor_pos = None,
-            WindowEvent::ButtonPress(MouseButton::Left) => self.mouse_pressed = true,
+            WindowEvent::ButtonPress(MouseButton::Left) => {
+                self.mouse_pressed = true;
+                if let Some(p) = self.cursor_pos
+                    && self.views_mut().any(|view| view.contains(p))
+                {
+                    // Select text or scroll instead of dragging the window.
+                    // Only one view can have a selection at a time.
+                    for view in self.views_mut() {
+                        if view.contains(p) {
+                            view.press(p);
+                        } else {
+                            view.clear_selection();
+                        }
+                    }
+                }
+            }
+            WindowEvent::Resized(width, height) => self.resize(width.into(), height.into()),
+            // The window contents were lost, so all of it has to be sent again.
+            WindowEvent::RedrawRequested => self.full_damage = true,
+            WindowEvent::ThemeChanged(theme) => self.set_theme(theme),
             WindowEvent::ButtonRelease(MouseButton::Left) => {
+                let released = self
+                    .views_mut()
+                    .find(|view| view.is_active())
+                    .map(|view| view.release().map(str::to_string));
+                if let Some(selected) = released {
+                    self.mouse_pressed = false;
+                    return selected.map(|text| Action::SetSelection(Selection::Primary, text));
+                }
                 if let Some(p) = self.cursor_pos
-                    && let Some(i) = self.buttons.iter().position(|btn| btn.contains(p))
                     && !self.mouse_dragging
                 {
-                    return Some(Answer::Button(i));
+                    if let Some(i) = self.buttons.iter().position(|btn| btn.contains(p)) {
+                        return Some(Action::Answer(Answer::Button(i)));
+                    }
+                    if self
+                        .title_bar
+                        .as_ref()
+                        .is_some_and(|bar| bar.close.contains(p))
+                    {
+                        return Some(Action::Answer(Answer::Closed));
+                    }
+                    if let Some(details) = &mut self.details
+                        && details.toggle.contains(p)
+                    {
+                        details.expanded = !details.expanded;
+                        // Grow or shrink the window to fit the pane, but keep its width.
+                        self.fixed_height = None;
+                        self.layout();
+                    }
                 }
                 self.mouse_pressed = false;
                 self.mouse_dragging = false;
@@ -206,65 +860,271 @@ impl Ui {
         None
     }
 
-    pub(crate) fn redraw(&mut self) {
-        self.canvas.clear(self.colors.window_bg.into());
-
-        self.canvas.place_surface(&self.icon, self.icon_pos);
-        self.canvas.place_surface(&self.message, self.message_pos);
-
-        for btn in &self.buttons {
-            let mut color = self.colors.button;
-            if let Some(pos) = self.cursor_pos
-                && btn.contains(pos)
-            {
-                color = if self.mouse_pressed {
-                    self.colors.button_pressed
-                } else {
-                    self.colors.button_hover
-                };
+    /// Converts a pointer position in the window into canvas coordinates.
+    fn cursor_point(&self, pos: CursorPos) -> IntPoint {
+        let margin = self.margin();
+        point2(i32::from(pos.x) - margin, i32::from(pos.y) - margin)
+    }
+
+    /// Returns the text that should be copied to the clipboard when pressing Ctrl+C.
+    ///
+    /// If part of the message is selected, that is what gets copied. Otherwise, the whole dialog
+    /// contents (title, message and buttons) are copied.
+    fn copy_text(&self) -> String {
+        let details = self.details.as_ref().map(|details| &details.view);
+        for view in [&self.message].into_iter().chain(details) {
+            let selected = view.selected_text();
+            if !selected.is_empty() {
+                return selected.to_string();
             }
+        }
 
-            let mut pb = PathBuilder::new();
-            pb.rect(
-                btn.pos.x as f32,
-                btn.pos.y as f32,
-                btn.size.width as f32,
-                btn.size.height as f32,
-            );
-            let path = rounded_rect(btn.size, BTN_RADIUS).transform(&Transform2D::translation(
-                btn.pos.x as f32,
-                btn.pos.y as f32,
-            ));
-            self.canvas.fill(&path, &color.into(), &Default::default());
-            self.canvas.stroke(
-                &path,
-                &self.colors.button_outline.into(),
-                &StrokeStyle::default(),
-                &Default::default(),
-            );
+        let buttons = self
+            .buttons
+            .iter()
+            .map(|btn| &*btn.label)
+            .collect::<Vec<_>>()
+            .join("   ");
+        let mut text = format!(
+            "{COPY_SEPARATOR}\n{}\n{COPY_SEPARATOR}\n{}\n{COPY_SEPARATOR}\n",
+            self.title,
+            self.message.string(),
+        );
+        if let Some(details) = &self.details {
+            text += &format!("{}\n{COPY_SEPARATOR}\n", details.view.string());
+        }
+        text += &format!("{buttons}\n{COPY_SEPARATOR}\n");
+        text
+    }
+
+    /// Returns the current state of every part of the dialog that can be redrawn on its own.
+    fn parts(&self) -> Vec<Part> {
+        let hovered = |contains: &dyn Fn(IntPoint) -> bool| self.cursor_pos.is_some_and(contains);
+        // Buttons are highlighted while hovered, and more so while pressed.
+        let highlight = |hovered: bool, color: Rgb| match (hovered, self.mouse_pressed) {
+            (false, _) => color,
+            (true, false) => self.colors.button_hover,
+            (true, true) => self.colors.button_pressed,
+        };
+
+        let mut parts = vec![Part::Message {
+            scroll: self.message.scroll(),
+            selection: self.message.selection_range(),
+        }];
+        if let Some(bar) = &self.title_bar {
+            parts.push(Part::Close {
+                background: highlight(hovered(&|p| bar.close.contains(p)), self.colors.button),
+            });
+        }
+        if let Some(details) = &self.details {
+            parts.push(Part::Toggle {
+                hovered: hovered(&|p| details.toggle.contains(p)),
+            });
+            if details.expanded {
+                parts.push(Part::Details {
+                    scroll: details.view.scroll(),
+                    selection: details.view.selection_range(),
+                });
+            }
+        }
+        for (index, btn) in self.buttons.iter().enumerate() {
+            parts.push(Part::Button {
+                index,
+                color: highlight(hovered(&|p| btn.contains(p)), self.colors.button),
+                default: index == 0,
+            });
+        }
+        parts
+    }
 
-            let text_x = btn.pos.x + btn.size.width / 2 - btn.text.width() / 2;
-            self.canvas
-                .place_surface(&btn.text, point2(text_x, btn.pos.y + BTN_PADDING));
+    /// Draws `part`, and returns the area that was drawn.
+    fn draw_part(&self, painter: &mut dyn Painter, part: &Part) -> IntRect {
+        let colors = &self.colors;
+        match *part {
+            Part::Message { .. } => {
+                let rect = self.message.bounds();
+                fill(painter, rect, colors.background);
+                self.message.draw(painter, colors);
+                rect
+            }
+            Part::Close { background } => match &self.title_bar {
+                Some(bar) => bar.draw_close(painter, colors, background),
+                None => IntRect::zero(),
+            },
+            Part::Toggle { hovered } => match &self.details {
+                Some(details) => details.draw_toggle(painter, colors, hovered),
+                None => IntRect::zero(),
+            },
+            Part::Details { .. } => match &self.details {
+                Some(details) => details.draw_pane(painter, colors),
+                None => IntRect::zero(),
+            },
+            Part::Button {
+                index,
+                color,
+                default,
+            } => self.buttons[index].draw(painter, colors, color, default),
+        }
+    }
+
+    /// Draws the whole dialog.
+    pub(crate) fn redraw(&mut self) {
+        let mut canvas = mem::replace(&mut self.canvas, DrawTarget::new(0, 0));
+        self.redraw_with(&mut canvas);
+        self.canvas = canvas;
+        if let Some(frame) = &mut self.frame {
+            frame.compose(&self.canvas, &[IntRect::from_size(self.size)]);
+        }
+    }
+
+    fn redraw_with(&mut self, painter: &mut dyn Painter) {
+        fill(painter, self.bounds(), self.colors.background);
+
+        if let Some(bar) = &self.title_bar {
+            bar.draw(painter, &self.colors, self.size.width);
+        }
+        painter.place_image(&self.icon, self.icon_pos);
+
+        let parts = self.parts();
+        for part in &parts {
+            self.draw_part(painter, part);
+        }
+        self.drawn = parts;
+    }
+
+    /// Redraws the parts of the dialog that changed since they were last drawn.
+    ///
+    /// Returns the areas of the [window contents][Ui::contents] that were redrawn, which are all
+    /// that has to be sent to the window.
+    pub(crate) fn update(&mut self) -> Vec<IntRect> {
+        let mut canvas = mem::replace(&mut self.canvas, DrawTarget::new(0, 0));
+        let damage = self.update_with(&mut canvas);
+        self.canvas = canvas;
+        match &mut self.frame {
+            Some(frame) => frame.compose(&self.canvas, &damage),
+            None => damage,
         }
     }
+
+    /// Like [`Ui::update`], but draws with `painter` instead of on the canvas.
+    ///
+    /// The canvas is left alone, so it is out of date afterwards. Call [`Ui::invalidate`] before
+    /// going back to drawing on the canvas. The frame (see [`Ui::with_frame`]) isn't drawn.
+    pub(crate) fn update_with(&mut self, painter: &mut dyn Painter) -> Vec<IntRect> {
+        if mem::take(&mut self.full_damage) {
+            self.redraw_with(painter);
+            return vec![self.bounds()];
+        }
+        let parts = self.parts();
+        let mut damage = Vec::new();
+        for (part, old) in parts.iter().zip(&self.drawn) {
+            if part != old {
+                damage.push(self.draw_part(painter, part));
+            }
+        }
+        self.drawn = parts;
+        damage
+    }
+
+    /// Makes the next [`Ui::update`] redraw the whole dialog.
+    pub(crate) fn invalidate(&mut self) {
+        self.full_damage = true;
+    }
+
+    /// Returns the area covered by the dialog.
+    fn bounds(&self) -> IntRect {
+        IntRect::from_size(self.size)
+    }
 }
 
-trait DrawTargetExt {
-    /// Renders `surface` at `position` in `self`.
+/// Something the dialog can be drawn on.
+///
+/// Usually, that is the [`Ui::canvas`], which is then copied into the window. Other painters send
+/// the drawing operations to the X server instead.
+pub(crate) trait Painter {
+    fn fill_rect(&mut self, rect: Rect<f32>, color: Rgb);
+    fn fill_path(&mut self, path: &Path, color: Rgb);
+    fn stroke_path(&mut self, path: &Path, color: Rgb, width: f32);
+    /// Draws the part `src` of `image` with its top left corner at `pos`.
     ///
-    /// The entire surface will be rendered, and this will use the standard `SrcOver` blend mode.
-    fn place_surface(&mut self, surface: &DrawTarget, position: IntPoint);
+    /// `image` is premultiplied, and blended with the standard `SrcOver` mode.
+    fn draw_image(&mut self, image: &DrawTarget, src: IntRect, pos: IntPoint);
+    /// Draws `text` with its top left corner at `pos`.
+    fn draw_text(&mut self, text: &Text, pos: IntPoint);
+    /// Restricts drawing to `rect`, until the matching [`Painter::pop_clip`].
+    fn push_clip(&mut self, rect: IntRect);
+    fn pop_clip(&mut self);
+
+    /// Draws all of `image` with its top left corner at `pos`.
+    fn place_image(&mut self, image: &DrawTarget, pos: IntPoint) {
+        self.draw_image(
+            image,
+            IntRect::from_size(size2(image.width(), image.height())),
+            pos,
+        );
+    }
 }
-impl DrawTargetExt for DrawTarget {
-    fn place_surface(&mut self, surface: &DrawTarget, position: IntPoint) {
-        self.blend_surface(
-            surface,
-            IntRect::from_size(size2(surface.width(), surface.height())),
-            position,
-            BlendMode::SrcOver,
+
+impl Painter for DrawTarget {
+    fn fill_rect(&mut self, rect: Rect<f32>, color: Rgb) {
+        DrawTarget::fill_rect(
+            self,
+            rect.min_x(),
+            rect.min_y(),
+            rect.width(),
+            rect.height(),
+            &color.into(),
+            &Default::default(),
+        );
+    }
+
+    fn fill_path(&mut self, path: &Path, color: Rgb) {
+        self.fill(path, &color.into(), &Default::default());
+    }
+
+    fn stroke_path(&mut self, path: &Path, color: Rgb, width: f32) {
+        self.stroke(
+            path,
+            &color.into(),
+            &StrokeStyle {
+                width,
+                ..Default::default()
+            },
+            &Default::default(),
         );
     }
+
+    fn draw_image(&mut self, image: &DrawTarget, src: IntRect, pos: IntPoint) {
+        self.blend_surface(image, src, pos, BlendMode::SrcOver);
+    }
+
+    fn draw_text(&mut self, text: &Text, pos: IntPoint) {
+        // (`blend_surface` would ignore the clip rect)
+        let surface = &text.surface;
+        self.draw_image_at(
+            pos.x as f32,
+            pos.y as f32,
+            &Image {
+                width: surface.width(),
+                height: surface.height(),
+                data: surface.get_data(),
+            },
+            &Default::default(),
+        );
+    }
+
+    fn push_clip(&mut self, rect: IntRect) {
+        self.push_clip_rect(rect);
+    }
+
+    fn pop_clip(&mut self) {
+        DrawTarget::pop_clip(self);
+    }
+}
+
+/// Fills `rect` with a solid color.
+fn fill(painter: &mut dyn Painter, rect: IntRect, color: Rgb) {
+    painter.fill_rect(rect.to_rect().to_f32(), color);
 }
 
 fn rounded_rect(size: Size2D<i32, ()>, radius: f32) -> Path {
@@ -1,27 +1,163 @@
-use ab_glyph::{Font as _, Glyph, OutlinedGlyph, PxScaleFont, ScaleFont, point};
-use raqote::DrawTarget;
+use std::{
+    env, fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
-use crate::ui::{Rgb, rgb};
+use ab_glyph::{Font as _, FontArc, Glyph, PxScaleFont, ScaleFont, point};
+use euclid::{
+    default::{Point2D, Rect},
+    point2, rect,
+};
+use raqote::{DrawTarget, IntPoint};
+
+use crate::Rgb;
 
 const FALLBACK_FONT: &[u8] = include_bytes!("../../3rdparty/fonts/Cantarell-Regular.ttf");
 
+/// Locations of common monospace fonts on various Linux distributions.
+const MONOSPACE_FONTS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/dejavu-sans-mono-fonts/DejaVuSansMono.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationMono-Regular.ttf",
+    "/usr/share/fonts/liberation-mono/LiberationMono-Regular.ttf",
+    "/usr/share/fonts/liberation/LiberationMono-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSansMono-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSansMono-Regular.ttf",
+];
+
+/// Directories that fonts are installed in (searched recursively).
+const FONT_DIRS: &[&str] = &["/usr/share/fonts", "/usr/local/share/fonts"];
+
+/// Style words that can follow the family name in a font name, like in `Noto Sans Bold 11`.
+const FONT_STYLES: &[&str] = &[
+    "regular",
+    "book",
+    "medium",
+    "bold",
+    "semi-bold",
+    "light",
+    "italic",
+    "oblique",
+    "condensed",
+];
+
+/// Font preferences of the desktop environment.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct FontPrefs {
+    /// Family name of the preferred font, like `Noto Sans`.
+    pub(crate) family: Option<String>,
+    /// Preferred font size, in points.
+    pub(crate) size: Option<f32>,
+    /// Resolution of the screen, in dots per inch.
+    pub(crate) dpi: Option<f32>,
+}
+
+impl FontPrefs {
+    /// Parses a Pango-style font name like `Cantarell 11` or `DejaVu Sans Bold 10`.
+    pub(crate) fn from_font_name(name: &str) -> Self {
+        let mut words = name.split_whitespace().collect::<Vec<_>>();
+        let size = match words.last().map(|w| w.parse::<f32>()) {
+            Some(Ok(size)) if size > 0.0 => {
+                words.pop();
+                Some(size)
+            }
+            _ => None,
+        };
+        while words
+            .last()
+            .is_some_and(|w| FONT_STYLES.contains(&&*w.to_ascii_lowercase()))
+        {
+            words.pop();
+        }
+        let family = words.join(" ");
+        Self {
+            family: (!family.is_empty()).then_some(family),
+            size,
+            dpi: None,
+        }
+    }
+
+    /// Returns the size of the dialog font, in pixels.
+    pub(crate) fn pixel_size(&self) -> f32 {
+        let dpi = self.dpi.unwrap_or(96.0);
+        match self.size {
+            Some(pt) => pt * dpi / 72.0,
+            None => FONT_SIZE * dpi / 96.0,
+        }
+    }
+
+    /// Returns the size of the monospace font, in pixels.
+    pub(crate) fn monospace_pixel_size(&self) -> f32 {
+        self.pixel_size() * MONOSPACE_FONT_SIZE / FONT_SIZE
+    }
+}
+
 pub(crate) struct Font {
-    font: PxScaleFont<ab_glyph::FontRef<'static>>,
+    font: PxScaleFont<FontArc>,
+    /// If set, every character is placed in a cell of this width.
+    cell_width: Option<f32>,
 }
 
 const FONT_SIZE: f32 = 18.0;
+const MONOSPACE_FONT_SIZE: f32 = 14.0;
 
 impl Font {
     /// Loads the font to use for the dialog contents.
     pub(crate) fn load() -> Self {
-        // TODO: invoke `fc-match` to find a system font
-        let inner = ab_glyph::FontRef::try_from_slice(FALLBACK_FONT).unwrap();
+        Self::load_preferred(&FontPrefs::default())
+    }
+
+    /// Loads the font to use for the dialog contents, taking the desktop's preferences into
+    /// account.
+    ///
+    /// If the preferred font family cannot be found, the bundled fallback font is used instead.
+    pub(crate) fn load_preferred(prefs: &FontPrefs) -> Self {
+        let system_font = prefs
+            .family
+            .as_deref()
+            .and_then(find_font)
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|data| FontArc::try_from_vec(data).ok());
+        let inner = system_font.unwrap_or_else(|| FontArc::try_from_slice(FALLBACK_FONT).unwrap());
 
         Self {
-            font: inner.into_scaled(FONT_SIZE),
+            font: inner.into_scaled(prefs.pixel_size()),
+            cell_width: None,
         }
     }
 
+    /// Loads a monospace font, for displaying things like logs and backtraces.
+    ///
+    /// If no monospace font is installed, the fallback font is used, but every character is
+    /// placed in a fixed-width cell so that columns still line up.
+    pub(crate) fn load_monospace(size: f32) -> Self {
+        // Snapshot tests must not depend on the fonts installed on the system.
+        let system_font = if cfg!(test) {
+            None
+        } else {
+            MONOSPACE_FONTS
+                .iter()
+                .filter_map(|path| fs::read(path).ok())
+                .find_map(|data| FontArc::try_from_vec(data).ok())
+        };
+        let inner = system_font.unwrap_or_else(|| FontArc::try_from_slice(FALLBACK_FONT).unwrap());
+
+        let font = inner.into_scaled(size);
+        let cell_width = font.h_advance(font.glyph_id('0'));
+        Self {
+            font,
+            cell_width: Some(cell_width),
+        }
+    }
+
+    /// Returns the distance between the baselines of two consecutive lines of text.
+    pub(crate) fn line_height(&self) -> f32 {
+        self.font.height() + self.font.line_gap()
+    }
+
     /// Returns a [`Renderer`] for rendering `text`.
     ///
     /// By default, `text` will be drawn in white and without soft wrapping.
@@ -29,12 +165,63 @@ impl Font {
         Renderer {
             font: self,
             text,
-            color: rgb(255, 255, 255),
+            color: Rgb(255, 255, 255),
             max_width: f32::MAX,
         }
     }
 }
 
+/// Searches the installed fonts for the regular style of `family`.
+///
+/// This matches font file names against the family name, which works for the vast majority of
+/// fonts without having to parse every font file (or depending on fontconfig).
+fn find_font(family: &str) -> Option<PathBuf> {
+    let normalize = |s: &str| {
+        s.chars()
+            .filter(char::is_ascii_alphanumeric)
+            .collect::<String>()
+            .to_ascii_lowercase()
+    };
+    let family = normalize(family);
+    // The bundled fallback font is Cantarell, so there is no need to look for it.
+    if family == "cantarell" || cfg!(test) {
+        return None;
+    }
+    let candidates = [family.clone(), format!("{family}regular")];
+
+    let mut dirs = FONT_DIRS.iter().map(PathBuf::from).collect::<Vec<_>>();
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")));
+    dirs.extend(data_home.map(|dir| dir.join("fonts")));
+    dirs.extend(env::var_os("HOME").map(|home| Path::new(&home).join(".fonts")));
+
+    // Font directories are usually 1-3 levels deep.
+    let mut stack = dirs.into_iter().map(|dir| (dir, 0)).collect::<Vec<_>>();
+    while let Some((dir, depth)) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                if depth < 4 {
+                    stack.push((path, depth + 1));
+                }
+                continue;
+            }
+            let is_font = path.extension().is_some_and(|ext| {
+                ext.eq_ignore_ascii_case("ttf") || ext.eq_ignore_ascii_case("otf")
+            });
+            let stem = path.file_stem().and_then(|s| s.to_str()).map(normalize);
+            if is_font && stem.is_some_and(|stem| candidates.contains(&stem)) {
+                return Some(path);
+            }
+        }
+    }
+    None
+}
+
 pub(crate) struct Renderer<'a> {
     font: &'a Font,
     text: &'a str,
@@ -52,7 +239,23 @@ impl<'a> Renderer<'a> {
     }
 
     pub(crate) fn finish(self) -> DrawTarget {
-        let glyphs = self.layout();
+        self.finish_text().surface
+    }
+
+    /// Renders the text and returns it along with its layout information.
+    ///
+    /// This is used for text that can be selected by the user.
+    pub(crate) fn finish_text(self) -> Text {
+        let chars = self.layout();
+        let glyphs = chars
+            .iter()
+            .filter(|c| c.visible)
+            .filter_map(|c| {
+                let mut glyph = c.glyph.clone();
+                glyph.position.x += c.bearing;
+                self.font.font.outline_glyph(glyph)
+            })
+            .collect::<Vec<_>>();
 
         let bounds = glyphs
             .iter()
@@ -73,11 +276,20 @@ impl<'a> Renderer<'a> {
         let mut target = DrawTarget::new(width.into(), height.into());
         let pixels = target.get_data_mut();
 
+        let mut images = Vec::with_capacity(glyphs.len());
         for g in glyphs {
             let glyph_bounds = g.px_bounds();
             let offset = glyph_bounds.min - bounds.min;
             let (off_x, off_y) = (offset.x as u32, offset.y as u32);
+            let (glyph_width, glyph_height) =
+                (glyph_bounds.width() as u16, glyph_bounds.height() as u16);
+            let mut coverage = vec![0; usize::from(glyph_width) * usize::from(glyph_height)];
             g.draw(|x, y, c| {
+                let a = (c * 255.0).round() as u32;
+                if let Some(cov) = coverage.get_mut((y * u32::from(glyph_width) + x) as usize) {
+                    *cov = a as u8;
+                }
+
                 let idx = (off_y + y) * width as u32 + off_x + x;
                 let Some(pix) = pixels.get_mut(idx as usize) else {
                     return;
@@ -85,28 +297,57 @@ impl<'a> Renderer<'a> {
 
                 // `DrawTarget` expects pre-multiplied alpha.
                 let Rgb(r, g, b) = self.color;
-                let a = (c * 255.0).round() as u32;
                 let r = r as u32 * a / 255;
                 let g = g as u32 * a / 255;
                 let b = b as u32 * a / 255;
                 *pix = (a << 24) | (r << 16) | (g << 8) | b;
             });
+            images.push(GlyphImage {
+                pos: point2(off_x as i32, off_y as i32),
+                width: glyph_width,
+                height: glyph_height,
+                coverage,
+            });
         }
 
-        target
+        // Translate the character boxes into the coordinate system of the surface.
+        let ascent = self.font.font.ascent();
+        let line_height = self.font.font.height() + self.font.font.line_gap();
+        let chars = chars
+            .into_iter()
+            .map(|c| CharBox {
+                bytes: c.bytes,
+                rect: rect(
+                    c.glyph.position.x - bounds.min.x,
+                    c.glyph.position.y - ascent - bounds.min.y,
+                    c.advance,
+                    line_height,
+                ),
+            })
+            .collect();
+
+        Text {
+            surface: target,
+            color: self.color,
+            glyphs: images,
+            chars,
+        }
     }
 
-    /// Calculates the text layout and computes glyph outlines.
+    /// Calculates the text layout.
     ///
     /// This will respect hard line breaks (`\n`) and attempt to perform soft wrapping when a line
     /// exceeds the configured max width.
     /// Soft-wrapping may fail if the rendered text contains no (or insufficient) permissible line
     /// break opportunities, in which case the text will exceed the intended width.
-    fn layout(&self) -> Vec<OutlinedGlyph> {
-        let mut glyphs: Vec<Glyph> = Vec::new();
+    ///
+    /// Returns every character of the text (except line breaks), including invisible ones.
+    fn layout(&self) -> Vec<LaidOutChar> {
+        let mut chars: Vec<LaidOutChar> = Vec::new();
 
         let mut y = 0.0;
         for line in self.text.lines() {
+            let line_offset = line.as_ptr() as usize - self.text.as_ptr() as usize;
             let mut x = 0.0;
 
             let mut last_softbreak: Option<usize> = None;
@@ -114,44 +355,139 @@ impl<'a> Renderer<'a> {
             // ID of the last glyph we placed; used to apply kerning.
             let mut last = None;
 
-            for c in line.chars() {
+            for (i, c) in line.char_indices() {
                 let mut glyph = self.font.font.scaled_glyph(c);
-                if let Some(last) = last {
-                    x += self.font.font.kern(last, glyph.id);
-                }
-                glyph.position = point(x, y);
-                last = Some(glyph.id);
-
-                x += self.font.font.h_advance(glyph.id);
-
-                if c == ' ' || c == ZWSP {
-                    last_softbreak = Some(glyphs.len());
-                } else {
-                    glyphs.push(glyph);
-
-                    if x > self.max_width
-                        && let Some(i) = last_softbreak
-                    {
-                        // Out of space on this line. Perform a soft line break.
-                        // Glyph at index `i` and later will be moved to the next line.
-                        y += self.font.font.height() + self.font.font.line_gap();
-                        let x_diff = glyphs.get(i).map(|g| g.position.x).unwrap_or(0.0);
-                        for glyph in &mut glyphs[i..] {
-                            glyph.position.x -= x_diff;
-                            glyph.position.y = y;
+                let (advance, bearing) = match self.font.cell_width {
+                    Some(cell_width) => {
+                        // Center the glyph in its cell.
+                        let bearing = (cell_width - self.font.font.h_advance(glyph.id)) / 2.0;
+                        (cell_width, bearing)
+                    }
+                    None => {
+                        if let Some(last) = last {
+                            x += self.font.font.kern(last, glyph.id);
                         }
-                        x -= x_diff;
+                        last = Some(glyph.id);
+                        (self.font.font.h_advance(glyph.id), 0.0)
+                    }
+                };
+                glyph.position = point(x, y);
+                x += advance;
+
+                let visible = c != ' ' && c != ZWSP;
+                chars.push(LaidOutChar {
+                    glyph,
+                    advance,
+                    bearing,
+                    bytes: line_offset + i..line_offset + i + c.len_utf8(),
+                    visible,
+                });
 
-                        last_softbreak = None;
+                if !visible {
+                    last_softbreak = Some(chars.len());
+                } else if x > self.max_width
+                    && let Some(i) = last_softbreak
+                {
+                    // Out of space on this line. Perform a soft line break.
+                    // Glyph at index `i` and later will be moved to the next line.
+                    y += self.font.font.height() + self.font.font.line_gap();
+                    let x_diff = chars.get(i).map(|c| c.glyph.position.x).unwrap_or(0.0);
+                    for c in &mut chars[i..] {
+                        c.glyph.position.x -= x_diff;
+                        c.glyph.position.y = y;
                     }
+                    x -= x_diff;
+
+                    last_softbreak = None;
                 }
             }
             y += self.font.font.height() + self.font.font.line_gap();
         }
-        glyphs
-            .into_iter()
-            .filter_map(|g| self.font.font.outline_glyph(g))
-            .collect()
+        chars
+    }
+}
+
+struct LaidOutChar {
+    /// The glyph, positioned at the left edge of the space reserved for it.
+    glyph: Glyph,
+    advance: f32,
+    /// Horizontal offset of the glyph outline from `glyph.position`.
+    bearing: f32,
+    bytes: Range<usize>,
+    visible: bool,
+}
+
+/// Rendered text, along with the information needed to map positions to characters.
+pub(crate) struct Text {
+    pub(crate) surface: DrawTarget,
+    pub(crate) color: Rgb,
+    /// The glyphs that make up `surface`, for drawing the text without uploading all of it.
+    pub(crate) glyphs: Vec<GlyphImage>,
+    chars: Vec<CharBox>,
+}
+
+/// The coverage mask of a single rendered glyph.
+pub(crate) struct GlyphImage {
+    /// Position of the mask's top left corner, in surface coordinates.
+    pub(crate) pos: IntPoint,
+    pub(crate) width: u16,
+    pub(crate) height: u16,
+    /// One byte per pixel, row by row.
+    pub(crate) coverage: Vec<u8>,
+}
+
+struct CharBox {
+    /// Byte range of the character in the source string.
+    bytes: Range<usize>,
+    /// The area occupied by the character, in surface coordinates.
+    rect: Rect<f32>,
+}
+
+impl Text {
+    /// Returns the byte offset of the character boundary closest to `pt`.
+    ///
+    /// `pt` is given in surface coordinates. Points outside the text are clamped to the nearest
+    /// line.
+    pub(crate) fn hit_test(&self, pt: Point2D<f32>) -> usize {
+        let Some(first) = self.chars.first() else {
+            return 0;
+        };
+
+        // Find the line containing `pt` (or the closest one), identified by its top edge.
+        let mut line_y = first.rect.min_y();
+        for c in &self.chars {
+            if c.rect.min_y() <= pt.y {
+                line_y = c.rect.min_y();
+            }
+        }
+
+        let mut end = 0;
+        for c in self.chars.iter().filter(|c| c.rect.min_y() == line_y) {
+            if pt.x < c.rect.center().x {
+                return c.bytes.start;
+            }
+            end = c.bytes.end;
+        }
+        end
+    }
+
+    /// Returns the rectangles covered by the characters in the byte range `bytes`.
+    ///
+    /// Adjacent characters on the same line are merged into a single rectangle.
+    pub(crate) fn range_rects(&self, bytes: Range<usize>) -> Vec<Rect<f32>> {
+        let mut rects: Vec<Rect<f32>> = Vec::new();
+        for c in &self.chars {
+            if c.bytes.start < bytes.start || c.bytes.end > bytes.end {
+                continue;
+            }
+            match rects.last_mut() {
+                Some(last) if last.min_y() == c.rect.min_y() && last.max_x() >= c.rect.min_x() => {
+                    *last = last.union(&c.rect);
+                }
+                _ => rects.push(c.rect),
+            }
+        }
+        rects
     }
 }
 
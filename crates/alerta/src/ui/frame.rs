@@ -0,0 +1,151 @@
+//! The rounded corners and soft shadow around the dialog in translucent windows.
+//!
+//! The dialog is drawn on the canvas as usual. Its pixels are then copied onto a pre-rendered
+//! shadow, leaving out what lies outside of the rounded corners.
+
+use euclid::{Size2D, UnknownUnit, vec2};
+use raqote::{DrawTarget, IntRect, SolidSource};
+
+use crate::ui::rounded_rect;
+
+/// Space around the dialog that the shadow is drawn in.
+pub(crate) const FRAME_MARGIN: i32 = 16;
+const CORNER_RADIUS: f32 = 8.0;
+/// How far the shadow is shifted down, as if lit from above.
+const SHADOW_OFFSET: i32 = 3;
+/// Radius of the box filter that softens the shadow (which is applied twice).
+const SHADOW_BLUR: usize = 5;
+/// Opacity of the shadow right below the dialog, out of 255.
+const SHADOW_OPACITY: u32 = 90;
+
+pub(crate) struct Frame {
+    /// Coverage of the rounded dialog shape, one byte per canvas pixel.
+    mask: Vec<u8>,
+    shadow: Vec<u32>,
+    /// The window contents: the canvas on top of the shadow.
+    pub(crate) image: DrawTarget,
+}
+
+impl Frame {
+    /// Prepares the frame around a canvas of the given size.
+    pub(crate) fn new(size: Size2D<i32, UnknownUnit>) -> Self {
+        let mut shape = DrawTarget::new(size.width, size.height);
+        shape.fill(
+            &rounded_rect(size.cast_unit(), CORNER_RADIUS),
+            &SolidSource::from_unpremultiplied_argb(255, 0, 0, 0).into(),
+            &Default::default(),
+        );
+        let mask = shape
+            .get_data()
+            .iter()
+            .map(|pixel| (pixel >> 24) as u8)
+            .collect::<Vec<_>>();
+
+        let width = (size.width + 2 * FRAME_MARGIN) as usize;
+        let height = (size.height + 2 * FRAME_MARGIN) as usize;
+        let mut alpha = vec![0; width * height];
+        for (y, row) in mask.chunks(size.width.max(1) as usize).enumerate() {
+            let start = (y + (FRAME_MARGIN + SHADOW_OFFSET) as usize) * width;
+            let start = start + FRAME_MARGIN as usize;
+            for (dst, &a) in alpha[start..start + row.len()].iter_mut().zip(row) {
+                *dst = u32::from(a);
+            }
+        }
+        for _ in 0..2 {
+            box_blur(&mut alpha, width, SHADOW_BLUR);
+        }
+        // Black, so the premultiplied pixels only have an alpha channel.
+        let shadow = alpha
+            .iter()
+            .map(|&a| (a * SHADOW_OPACITY / 255) << 24)
+            .collect::<Vec<_>>();
+
+        let mut image = DrawTarget::new(width as i32, height as i32);
+        image.get_data_mut().copy_from_slice(&shadow);
+        Self {
+            mask,
+            shadow,
+            image,
+        }
+    }
+
+    /// Copies the areas `rects` of `canvas` into the window contents, and returns the areas of the
+    /// window contents that changed.
+    pub(crate) fn compose(&mut self, canvas: &DrawTarget, rects: &[IntRect]) -> Vec<IntRect> {
+        let bounds = IntRect::from_size(Size2D::new(canvas.width(), canvas.height()));
+        let (src_width, dst_width) = (canvas.width() as usize, self.image.width() as usize);
+        let src = canvas.get_data();
+        let dst = self.image.get_data_mut();
+        rects
+            .iter()
+            .filter_map(|rect| rect.intersection(&bounds))
+            .map(|rect| {
+                for y in rect.min.y as usize..rect.max.y as usize {
+                    for x in rect.min.x as usize..rect.max.x as usize {
+                        let i = y * src_width + x;
+                        let j = (y + FRAME_MARGIN as usize) * dst_width + x + FRAME_MARGIN as usize;
+                        dst[j] = blend(src[i], self.shadow[j], self.mask[i]);
+                    }
+                }
+                rect.translate(vec2(FRAME_MARGIN, FRAME_MARGIN))
+            })
+            .collect()
+    }
+}
+
+/// Blends the premultiplied pixels `a` and `b`, weighted by `t` and `255 - t`.
+fn blend(a: u32, b: u32, t: u8) -> u32 {
+    match t {
+        255 => a,
+        0 => b,
+        _ => {
+            let t = u32::from(t);
+            let channel = |shift: u32| {
+                let (a, b) = ((a >> shift) & 0xff, (b >> shift) & 0xff);
+                ((a * t + b * (255 - t) + 127) / 255) << shift
+            };
+            channel(24) | channel(16) | channel(8) | channel(0)
+        }
+    }
+}
+
+/// Blurs the rows of `width` values in `values` with a box filter, horizontally and vertically.
+///
+/// Values outside of the buffer count as zero.
+fn box_blur(values: &mut [u32], width: usize, radius: usize) {
+    let height = values.len() / width;
+    let mut line = Vec::new();
+    for y in 0..height {
+        blur_line(values, y * width, 1, width, radius, &mut line);
+    }
+    for x in 0..width {
+        blur_line(values, x, width, height, radius, &mut line);
+    }
+}
+
+/// Blurs `len` values, starting at `start` and `stride` apart.
+fn blur_line(
+    values: &mut [u32],
+    start: usize,
+    stride: usize,
+    len: usize,
+    radius: usize,
+    line: &mut Vec<u32>,
+) {
+    line.clear();
+    line.extend((0..len).map(|i| values[start + i * stride]));
+    let window = 2 * radius + 1;
+    // The sum of the `window` values up to `i`, which is the window centered on `i - radius`.
+    let mut sum = 0;
+    for i in 0..len + radius {
+        if i < len {
+            sum += line[i];
+        }
+        if i >= window {
+            sum -= line[i - window];
+        }
+        if i >= radius {
+            values[start + (i - radius) * stride] = sum / window as u32;
+        }
+    }
+}
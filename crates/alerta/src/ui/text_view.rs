@@ -0,0 +1,230 @@
+use std::{cmp, ops::Range};
+
+use euclid::{Transform2D, point2, size2};
+use raqote::{IntPoint, IntRect};
+
+use crate::{
+    Palette,
+    ui::{Painter, SCROLLBAR_WIDTH, SPACING, font::Text, rounded_rect},
+};
+
+/// Minimum length of the scrollbar thumb, so that it stays grabbable for very long texts.
+const MIN_THUMB_HEIGHT: i32 = 20;
+
+/// A block of text that can be scrolled and selected with the mouse.
+///
+/// If the text is taller than the viewport, a scrollbar is drawn to the right of the viewport.
+pub(crate) struct TextView {
+    string: String,
+    text: Text,
+    /// The visible area of the text, in canvas coordinates.
+    viewport: IntRect,
+    scroll: i32,
+    /// Anchor and focus of the text selection, as byte offsets into `string`.
+    selection: (usize, usize),
+    selecting: bool,
+    /// While the scrollbar thumb is being dragged: offset of the pointer from the thumb's top edge.
+    thumb_grab: Option<i32>,
+}
+
+impl TextView {
+    pub(crate) fn new(string: String, text: Text) -> Self {
+        Self {
+            string,
+            text,
+            viewport: IntRect::zero(),
+            scroll: 0,
+            selection: (0, 0),
+            selecting: false,
+            thumb_grab: None,
+        }
+    }
+
+    pub(crate) fn string(&self) -> &str {
+        &self.string
+    }
+
+    /// Replaces the rendered text (for example, after it was re-wrapped to a different width).
+    ///
+    /// The selection is preserved.
+    pub(crate) fn set_text(&mut self, text: Text) {
+        self.text = text;
+        self.scroll_to(self.scroll);
+    }
+
+    /// Returns the width of the whole text (which may exceed the viewport).
+    pub(crate) fn content_width(&self) -> i32 {
+        self.text.surface.width()
+    }
+
+    /// Returns the height of the whole text (which may exceed the viewport).
+    pub(crate) fn content_height(&self) -> i32 {
+        self.text.surface.height()
+    }
+
+    pub(crate) fn viewport(&self) -> IntRect {
+        self.viewport
+    }
+
+    pub(crate) fn set_viewport(&mut self, viewport: IntRect) {
+        self.viewport = viewport;
+        self.scroll_to(self.scroll);
+    }
+
+    /// Returns the area covered by the view, including the scrollbar.
+    pub(crate) fn bounds(&self) -> IntRect {
+        match self.scrollbar_rect() {
+            Some(bar) => self.viewport.union(&bar),
+            None => self.viewport,
+        }
+    }
+
+    pub(crate) fn scroll(&self) -> i32 {
+        self.scroll
+    }
+
+    fn max_scroll(&self) -> i32 {
+        cmp::max(0, self.content_height() - self.viewport.height())
+    }
+
+    pub(crate) fn is_scrollable(&self) -> bool {
+        self.max_scroll() > 0
+    }
+
+    pub(crate) fn scroll_by(&mut self, delta: i32) {
+        self.scroll_to(self.scroll.saturating_add(delta));
+    }
+
+    pub(crate) fn scroll_to(&mut self, y: i32) {
+        self.scroll = y.clamp(0, self.max_scroll());
+    }
+
+    fn scrollbar_rect(&self) -> Option<IntRect> {
+        if !self.is_scrollable() {
+            return None;
+        }
+        Some(IntRect::from_origin_and_size(
+            point2(self.viewport.max.x + SPACING, self.viewport.min.y),
+            size2(SCROLLBAR_WIDTH, self.viewport.height()),
+        ))
+    }
+
+    fn thumb_rect(&self) -> Option<IntRect> {
+        let bar = self.scrollbar_rect()?;
+        let height = cmp::max(
+            MIN_THUMB_HEIGHT,
+            bar.height() * self.viewport.height() / self.content_height(),
+        );
+        let y = bar.min.y + self.scroll * (bar.height() - height) / self.max_scroll();
+        Some(IntRect::from_origin_and_size(
+            point2(bar.min.x, y),
+            size2(bar.width(), height),
+        ))
+    }
+
+    /// Returns whether `pt` is inside the text or its scrollbar.
+    pub(crate) fn contains(&self, pt: IntPoint) -> bool {
+        self.viewport.contains(pt) || self.scrollbar_rect().is_some_and(|bar| bar.contains(pt))
+    }
+
+    /// Returns whether the user is currently selecting text or dragging the scrollbar.
+    pub(crate) fn is_active(&self) -> bool {
+        self.selecting || self.thumb_grab.is_some()
+    }
+
+    /// Handles a mouse button press at `pt`, which must be [contained][Self::contains] in the view.
+    pub(crate) fn press(&mut self, pt: IntPoint) {
+        if let Some(thumb) = self.thumb_rect()
+            && self.scrollbar_rect().is_some_and(|bar| bar.contains(pt))
+        {
+            let grab = if thumb.contains(pt) {
+                pt.y - thumb.min.y
+            } else {
+                // Clicking the track centers the thumb on the pointer.
+                thumb.height() / 2
+            };
+            self.thumb_grab = Some(grab);
+            self.drag(pt);
+        } else {
+            let offset = self.hit_test(pt);
+            self.selection = (offset, offset);
+            self.selecting = true;
+        }
+    }
+
+    /// Handles pointer movement while a mouse button is held down.
+    pub(crate) fn drag(&mut self, pt: IntPoint) {
+        if let Some(grab) = self.thumb_grab
+            && let (Some(bar), Some(thumb)) = (self.scrollbar_rect(), self.thumb_rect())
+        {
+            let travel = bar.height() - thumb.height();
+            if travel > 0 {
+                let y = pt.y - grab - bar.min.y;
+                self.scroll_to(y * self.max_scroll() / travel);
+            }
+        } else if self.selecting {
+            self.selection.1 = self.hit_test(pt);
+        }
+    }
+
+    /// Handles a mouse button release.
+    ///
+    /// Returns the selected text if the user has just finished selecting some.
+    pub(crate) fn release(&mut self) -> Option<&str> {
+        self.thumb_grab = None;
+        if !self.selecting {
+            return None;
+        }
+        self.selecting = false;
+        let text = self.selected_text();
+        if text.is_empty() { None } else { Some(text) }
+    }
+
+    pub(crate) fn clear_selection(&mut self) {
+        self.selection = (0, 0);
+    }
+
+    pub(crate) fn selected_text(&self) -> &str {
+        &self.string[self.selection_range()]
+    }
+
+    pub(crate) fn selection_range(&self) -> Range<usize> {
+        let (a, b) = self.selection;
+        cmp::min(a, b)..cmp::max(a, b)
+    }
+
+    fn hit_test(&self, pt: IntPoint) -> usize {
+        let origin = self.text_origin();
+        self.text
+            .hit_test(point2(pt.x - origin.x, pt.y - origin.y).to_f32())
+    }
+
+    /// Position of the text surface's origin in canvas coordinates.
+    fn text_origin(&self) -> IntPoint {
+        point2(self.viewport.min.x, self.viewport.min.y - self.scroll)
+    }
+
+    pub(crate) fn draw(&self, painter: &mut dyn Painter, colors: &Palette) {
+        let origin = self.text_origin();
+
+        painter.push_clip(self.viewport);
+        for rect in self.text.range_rects(self.selection_range()) {
+            painter.fill_rect(
+                rect.translate(origin.to_f32().to_vector()),
+                colors.selection,
+            );
+        }
+        painter.draw_text(&self.text, origin);
+        painter.pop_clip();
+
+        if let (Some(bar), Some(thumb)) = (self.scrollbar_rect(), self.thumb_rect()) {
+            let radius = SCROLLBAR_WIDTH as f32 / 2.0;
+            for (rect, color) in [(bar, colors.button), (thumb, colors.button_pressed)] {
+                let path = rounded_rect(size2(rect.width(), rect.height()), radius).transform(
+                    &Transform2D::translation(rect.min.x as f32, rect.min.y as f32),
+                );
+                painter.fill_path(&path, color);
+            }
+        }
+    }
+}
@@ -1,35 +1,95 @@
-use std::{ops::Deref, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    cmp,
+    fs::File,
+    ops::Deref,
+    os::{
+        fd::{BorrowedFd, OwnedFd},
+        unix::fs::FileExt as _,
+    },
+    process,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
-use raqote::DrawTarget;
+use euclid::size2;
+use raqote::{DrawTarget, IntRect};
+use rustix::{
+    event::{PollFd, PollFlags, Timespec, poll},
+    fs::{MemfdFlags, memfd_create},
+    io::Errno,
+};
 use x11rb::{
-    connection::Connection as _,
-    properties::WmSizeHints,
+    connection::{Connection as _, RequestConnection as _},
+    properties::{WmHints, WmSizeHints},
     protocol::{
-        Event,
+        Event, randr,
+        shm::{self, ConnectionExt as _},
+        xkb::{self, ConnectionExt as _},
         xproto::{
-            self, AtomEnum, ClientMessageEvent, ConfigureWindowAux, ConnectionExt as _,
-            CreateWindowAux, EventMask, ImageFormat, KeyButMask, PropMode, StackMode, VisualClass,
+            self, Atom, AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ColormapAlloc,
+            ConfigureWindowAux, ConnectionExt as _, CreateWindowAux, EventMask, ImageFormat,
+            ImageOrder, InputFocus, KeyButMask, Mapping, PropMode, Property, Rectangle,
+            SelectionNotifyEvent, SelectionRequestEvent, StackMode, Timestamp, VisualClass,
             WindowClass,
         },
     },
+    reexports::x11rb_protocol::parse_display::parse_display,
     rust_connection::RustConnection,
     wrapper::ConnectionExt as _,
 };
 
-use crate::{CursorPos, Error, MouseButton, WindowEvent, error::err};
+pub(crate) mod pixels;
+pub(crate) mod render;
+
+pub(crate) use render::Renderer;
+
+use self::pixels::{CUBE_LEVELS, PixelFormat, cube_color};
+
+use crate::{
+    CursorPos, Error, Key, Modifiers, MouseButton, Palette, Rgb, Selection, Theme, WindowEvent,
+    error::err, ui::FontPrefs,
+};
 
 x11rb::atom_manager! {
     pub Atoms: AtomCookie {
         UTF8_STRING,
 
+        CLIPBOARD,
+        CLIPBOARD_MANAGER,
+        SAVE_TARGETS,
+        TARGETS,
+        INCR,
+
         WM_PROTOCOLS,
         WM_DELETE_WINDOW,
 
         _NET_WM_NAME,
+        _NET_WM_PID,
+        _NET_WM_PING,
+        _NET_WM_USER_TIME,
+
+        _NET_STARTUP_ID,
+        _NET_STARTUP_INFO_BEGIN,
+        _NET_STARTUP_INFO,
         _NET_WM_WINDOW_TYPE,
         _NET_WM_WINDOW_TYPE_DIALOG,
 
         _NET_WM_MOVERESIZE,
+
+        _NET_WM_STATE,
+        _NET_WM_STATE_ABOVE,
+        _NET_WM_STATE_STICKY,
+        _NET_WM_STATE_SKIP_TASKBAR,
+        _NET_WM_STATE_SKIP_PAGER,
+        _NET_WM_STATE_DEMANDS_ATTENTION,
+
+        _NET_SUPPORTING_WM_CHECK,
+        _GTK_FRAME_EXTENTS,
+        _NET_WORKAREA,
+        _NET_CURRENT_DESKTOP,
+
+        _XSETTINGS_SETTINGS,
     }
 }
 
@@ -37,20 +97,808 @@ enum WindowType {
     Dialog,
 }
 
+/// How the window manager should treat the window, set when it is created.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct WindowState {
+    /// Keep the window above other windows.
+    pub(crate) above: bool,
+    /// Show the window on every workspace.
+    pub(crate) sticky: bool,
+    /// Don't list the window in taskbars.
+    pub(crate) skip_taskbar: bool,
+    /// Don't show the window in pagers and workspace switchers.
+    pub(crate) skip_pager: bool,
+    /// Draw the user's attention to the window (see [`X11Window::create`]).
+    pub(crate) urgent: bool,
+    /// There is no window manager, so the window has to be placed, moved and focused by us (see
+    /// [`Connection::has_window_manager`]).
+    pub(crate) unmanaged: bool,
+    /// Use a visual with an alpha channel (see [`Connection::supports_translucency`]).
+    pub(crate) translucent: bool,
+}
+
+/// The visual of the window, along with what is needed to draw into it.
+struct Visual {
+    id: xproto::Visualid,
+    depth: u8,
+    colormap: xproto::Colormap,
+    /// The format of images sent to the window.
+    format: PixelFormat,
+}
+
 #[derive(Clone)]
 pub(crate) struct Connection {
     inner: Rc<RustConnection>,
     screen: usize,
+    atoms: Atoms,
 }
 
 impl Connection {
     pub(crate) fn connect() -> Result<Self, Error> {
         let (conn, screen) = x11rb::connect(None).map_err(err)?;
+        let atoms = Atoms::new(&conn).map_err(err)?.reply().map_err(err)?;
         Ok(Self {
             inner: Rc::new(conn),
             screen,
+            atoms,
+        })
+    }
+
+    fn screen(&self) -> Result<&xproto::Screen, Error> {
+        self.inner
+            .setup()
+            .roots
+            .get(self.screen)
+            .ok_or_else(|| Error::new(format!("screen '{}' does not exist on server", self.screen)))
+    }
+
+    /// Returns whether a window manager is running.
+    ///
+    /// Not every window manager is EWMH compliant (twm and other old ones aren't), so this also
+    /// checks for the ICCCM `WM_Sn` selection.
+    pub(crate) fn has_window_manager(&self) -> Result<bool, Error> {
+        Ok(self.has_ewmh_window_manager()?
+            || self.has_selection_owner(&format!("WM_S{}", self.screen))?)
+    }
+
+    /// Returns whether an EWMH compliant window manager is running.
+    ///
+    /// Window managers announce themselves by pointing the root window's `_NET_SUPPORTING_WM_CHECK`
+    /// property at a child window that has the same property pointing to itself. A stale property
+    /// left behind by a window manager that exited points at a window that no longer exists.
+    fn has_ewmh_window_manager(&self) -> Result<bool, Error> {
+        let check_window = |window| -> Result<Option<xproto::Window>, Error> {
+            let reply = self
+                .get_property(
+                    false,
+                    window,
+                    self.atoms._NET_SUPPORTING_WM_CHECK,
+                    AtomEnum::WINDOW,
+                    0,
+                    1,
+                )
+                .map_err(err)?
+                .reply();
+            Ok(reply.ok().and_then(|r| r.value32()?.next()))
+        };
+        let Some(child) = check_window(self.screen()?.root)? else {
+            return Ok(false);
+        };
+        Ok(check_window(child)? == Some(child))
+    }
+
+    /// Returns whether the selection with the given name has an owner.
+    fn has_selection_owner(&self, selection: &str) -> Result<bool, Error> {
+        let selection = self
+            .intern_atom(false, selection.as_bytes())
+            .map_err(err)?
+            .reply()
+            .map_err(err)?
+            .atom;
+        let owner = self
+            .get_selection_owner(selection)
+            .map_err(err)?
+            .reply()
+            .map_err(err)?
+            .owner;
+        Ok(owner != x11rb::NONE)
+    }
+
+    /// Returns whether windows can be translucent.
+    ///
+    /// That takes a compositing manager, which owns the `_NET_WM_CM_Sn` selection of the screen,
+    /// and a 32-bit visual.
+    pub(crate) fn supports_translucency(&self) -> Result<bool, Error> {
+        Ok(
+            self.has_selection_owner(&format!("_NET_WM_CM_S{}", self.screen))?
+                && self.argb_visual()?.is_some(),
+        )
+    }
+
+    /// Returns a 32-bit TrueColor visual, whose extra bits are used as the alpha channel.
+    fn argb_visual(&self) -> Result<Option<&xproto::Visualtype>, Error> {
+        Ok(self
+            .screen()?
+            .allowed_depths
+            .iter()
+            .filter(|d| d.depth == 32)
+            .flat_map(|d| &d.visuals)
+            .find(|vis| vis.class == VisualClass::TRUE_COLOR))
+    }
+
+    /// Picks the visual for the window.
+    ///
+    /// The usual 24-bit TrueColor visual is preferred, since the canvas pixels can be sent to it
+    /// as they are. Other TrueColor visuals and 8-bit PseudoColor visuals (on old hardware and some
+    /// VNC servers) need the pixels to be converted. Translucent windows need a 32-bit visual.
+    fn choose_visual(&self, translucent: bool) -> Result<Visual, Error> {
+        let screen = self.screen()?;
+        if translucent && let Some(vis) = self.argb_visual()? {
+            let masks = [vis.red_mask, vis.green_mask, vis.blue_mask];
+            let alpha = !masks.iter().fold(0, |all, mask| all | mask);
+            return self.visual(vis, 32, [alpha, masks[0], masks[1], masks[2]]);
+        }
+        let rank = |vis: &xproto::Visualtype, depth: u8| match (vis.class, depth) {
+            (VisualClass::TRUE_COLOR, 24)
+                if (vis.red_mask, vis.green_mask, vis.blue_mask) == (0xff0000, 0xff00, 0xff) =>
+            {
+                Some(0)
+            }
+            (VisualClass::TRUE_COLOR, 24 | 30) => Some(1),
+            (VisualClass::TRUE_COLOR, 15 | 16) => Some(2),
+            (VisualClass::PSEUDO_COLOR, 8) => Some(3),
+            _ => None,
+        };
+        let (_, vis, depth) = screen
+            .allowed_depths
+            .iter()
+            .flat_map(|d| d.visuals.iter().map(move |vis| (vis, d.depth)))
+            .filter_map(|(vis, depth)| Some((rank(vis, depth)?, vis, depth)))
+            .min_by_key(|(rank, ..)| *rank)
+            .ok_or_else(|| Error::new("could not find a compatible X11 visual"))?;
+        self.visual(vis, depth, [0, vis.red_mask, vis.green_mask, vis.blue_mask])
+    }
+
+    /// Prepares drawing into windows with the visual `vis`, whose pixels have the given alpha, red,
+    /// green and blue masks (unless it uses indexed colors).
+    fn visual(
+        &self,
+        vis: &xproto::Visualtype,
+        depth: u8,
+        masks: [u32; 4],
+    ) -> Result<Visual, Error> {
+        let screen = self.screen()?;
+        // Windows with a different visual than the root window need their own colormap.
+        let colormap = if vis.visual_id == screen.root_visual {
+            screen.default_colormap
+        } else {
+            let colormap = self.generate_id().map_err(err)?;
+            self.create_colormap(ColormapAlloc::NONE, colormap, screen.root, vis.visual_id)
+                .map_err(err)?;
+            colormap
+        };
+        let (bits_per_pixel, scanline_pad, msb_first) = self.image_layout(depth)?;
+        let format = if vis.class == VisualClass::PSEUDO_COLOR {
+            let cube = self.alloc_color_cube(colormap, depth)?;
+            PixelFormat::indexed(bits_per_pixel, scanline_pad, msb_first, cube)
+        } else {
+            PixelFormat::direct(bits_per_pixel, scanline_pad, msb_first, masks)
+        }
+        .ok_or_else(|| {
+            Error::new(format!(
+                "unsupported X11 pixel format ({bits_per_pixel} bits per pixel)"
+            ))
+        })?;
+        Ok(Visual {
+            id: vis.visual_id,
+            depth,
+            colormap,
+            format,
+        })
+    }
+
+    /// Returns the bits per pixel, the scanline padding and whether the most significant byte
+    /// comes first for images of the given depth.
+    fn image_layout(&self, depth: u8) -> Result<(u8, u8, bool), Error> {
+        let setup = self.setup();
+        let format = setup
+            .pixmap_formats
+            .iter()
+            .find(|format| format.depth == depth)
+            .ok_or_else(|| Error::new(format!("X server has no {depth}-bit image format")))?;
+        Ok((
+            format.bits_per_pixel,
+            format.scanline_pad,
+            setup.image_byte_order == ImageOrder::MSB_FIRST,
+        ))
+    }
+
+    /// Allocates the color cube for indexed colors (see [`PixelFormat::indexed`]) in `colormap`.
+    ///
+    /// If the colormap is full, the closest colors it already contains are used instead.
+    fn alloc_color_cube(&self, colormap: xproto::Colormap, depth: u8) -> Result<Vec<u32>, Error> {
+        let cookies = (0..CUBE_LEVELS.pow(3))
+            .map(|i| {
+                let [r, g, b] = cube_color(i);
+                self.alloc_color(colormap, r, g, b)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(err)?;
+        let mut pixels = cookies
+            .into_iter()
+            .map(|cookie| cookie.reply().ok().map(|reply| reply.pixel))
+            .collect::<Vec<_>>();
+        if pixels.iter().all(Option::is_some) {
+            return Ok(pixels.into_iter().flatten().collect());
+        }
+
+        let entries = (0..1u32 << depth).collect::<Vec<_>>();
+        let existing = self
+            .query_colors(colormap, &entries)
+            .map_err(err)?
+            .reply()
+            .map_err(err)?
+            .colors;
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            let [r, g, b] = cube_color(i).map(i64::from);
+            let distance = |c: &xproto::Rgb| {
+                (i64::from(c.red) - r).pow(2)
+                    + (i64::from(c.green) - g).pow(2)
+                    + (i64::from(c.blue) - b).pow(2)
+            };
+            pixel.get_or_insert_with(|| {
+                existing
+                    .iter()
+                    .zip(&entries)
+                    .min_by_key(|(color, _)| distance(color))
+                    .map_or(0, |(_, &pixel)| pixel)
+            });
+        }
+        Ok(pixels.into_iter().flatten().collect())
+    }
+
+    /// Returns whether the X server runs on this machine (connected via a Unix domain socket).
+    fn is_local(&self) -> bool {
+        parse_display(None)
+            .is_ok_and(|dpy| dpy.host.is_empty() || dpy.protocol.as_deref() == Some("unix"))
+    }
+
+    /// Returns whether window contents can be transferred through shared memory.
+    fn supports_shm(&self) -> bool {
+        // The memory is shared by passing a file descriptor, which requires a Unix domain socket.
+        self.is_local()
+            && self
+                .shm_query_version()
+                .ok()
+                .and_then(|cookie| cookie.reply().ok())
+                .is_some_and(|v| (v.major_version, v.minor_version) >= (1, 2))
+    }
+
+    /// Returns the area of the screen that windows should be placed in.
+    ///
+    /// This excludes panels and docks (via `_NET_WORKAREA`), and is restricted to the monitor the
+    /// mouse cursor is on (if the RandR extension is available).
+    pub(crate) fn work_area(&self) -> Result<Rectangle, Error> {
+        let screen = self.screen()?;
+        let mut area = Rectangle {
+            x: 0,
+            y: 0,
+            width: screen.width_in_pixels,
+            height: screen.height_in_pixels,
+        };
+
+        let desktop = self
+            .get_property(
+                false,
+                screen.root,
+                self.atoms._NET_CURRENT_DESKTOP,
+                AtomEnum::CARDINAL,
+                0,
+                1,
+            )
+            .map_err(err)?
+            .reply()
+            .map_err(err)?
+            .value32()
+            .and_then(|mut v| v.next())
+            .unwrap_or(0);
+        let workarea = self
+            .get_property(
+                false,
+                screen.root,
+                self.atoms._NET_WORKAREA,
+                AtomEnum::CARDINAL,
+                desktop * 4,
+                4,
+            )
+            .map_err(err)?
+            .reply()
+            .map_err(err)?;
+        if let Some(v) = workarea.value32()
+            && let [x, y, width, height] = v.collect::<Vec<_>>()[..]
+        {
+            area = Rectangle {
+                x: x as i16,
+                y: y as i16,
+                width: width as u16,
+                height: height as u16,
+            };
+        }
+
+        // `_NET_WORKAREA` spans all monitors, so restrict it to the one with the mouse cursor.
+        let pointer = self
+            .query_pointer(screen.root)
+            .map_err(err)?
+            .reply()
+            .map_err(err)?;
+        let monitors = match randr::get_monitors(&*self.inner, screen.root, true) {
+            Ok(cookie) => cookie.reply().map(|r| r.monitors).unwrap_or_default(),
+            // RandR isn't supported by the server.
+            Err(_) => Vec::new(),
+        };
+        for mon in monitors {
+            let mon = Rectangle {
+                x: mon.x,
+                y: mon.y,
+                width: mon.width,
+                height: mon.height,
+            };
+            if contains(mon, pointer.root_x, pointer.root_y) {
+                area = intersect(area, mon);
+                break;
+            }
+        }
+
+        Ok(area)
+    }
+
+    /// Reads the settings published by the XSETTINGS manager.
+    ///
+    /// Returns empty settings if there is no XSETTINGS manager.
+    pub(crate) fn xsettings(&self) -> Result<XSettings, Error> {
+        // https://specifications.freedesktop.org/xsettings-spec/latest/
+        let selection = self
+            .intern_atom(true, format!("_XSETTINGS_S{}", self.screen).as_bytes())
+            .map_err(err)?
+            .reply()
+            .map_err(err)?
+            .atom;
+        if selection == x11rb::NONE {
+            return Ok(XSettings::default());
+        }
+        let owner = self
+            .get_selection_owner(selection)
+            .map_err(err)?
+            .reply()
+            .map_err(err)?
+            .owner;
+        if owner == x11rb::NONE {
+            return Ok(XSettings::default());
+        }
+        let data = self
+            .get_property(
+                false,
+                owner,
+                self.atoms._XSETTINGS_SETTINGS,
+                self.atoms._XSETTINGS_SETTINGS,
+                0,
+                u32::MAX / 4,
+            )
+            .map_err(err)?
+            .reply()
+            .map_err(err)?
+            .value;
+        XSettings::parse(&data).ok_or_else(|| Error::new("malformed XSETTINGS data"))
+    }
+
+    /// Reads the X resources loaded into the server (usually from `~/.Xresources`, by `xrdb`).
+    pub(crate) fn resources(&self) -> Result<XResources, Error> {
+        let data = self
+            .get_property(
+                false,
+                self.screen()?.root,
+                AtomEnum::RESOURCE_MANAGER,
+                AtomEnum::STRING,
+                0,
+                u32::MAX / 4,
+            )
+            .map_err(err)?
+            .reply()
+            .map_err(err)?
+            .value;
+        // The property is Latin-1, but non-ASCII characters are unlikely to matter for us.
+        Ok(XResources::parse(&String::from_utf8_lossy(&data)))
+    }
+}
+
+/// Desktop settings, as published by the XSETTINGS manager.
+#[derive(Debug, Default)]
+pub(crate) struct XSettings {
+    settings: Vec<(String, XSetting)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum XSetting {
+    Int(i32),
+    String(String),
+    /// Red, green, blue and alpha.
+    Color([u16; 4]),
+}
+
+impl XSettings {
+    /// Parses the contents of the `_XSETTINGS_SETTINGS` property.
+    pub(crate) fn parse(data: &[u8]) -> Option<Self> {
+        let mut pos = 0usize;
+        let mut take = |n: usize| {
+            let bytes = data.get(pos..pos.checked_add(n)?)?;
+            pos += n.next_multiple_of(4);
+            Some(bytes)
+        };
+        let big_endian = match take(4)?[0] {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        let card32 = |b: &[u8]| {
+            let b = b.try_into().unwrap();
+            if big_endian {
+                u32::from_be_bytes(b)
+            } else {
+                u32::from_le_bytes(b)
+            }
+        };
+        let card16 = |b: &[u8]| {
+            let b = b.try_into().unwrap();
+            if big_endian {
+                u16::from_be_bytes(b)
+            } else {
+                u16::from_le_bytes(b)
+            }
+        };
+
+        let _serial = take(4)?;
+        let count = card32(take(4)?);
+        let mut settings = Vec::new();
+        for _ in 0..count {
+            let header = take(4)?;
+            let name = take(card16(&header[2..]).into())?;
+            let name = String::from_utf8(name.to_vec()).ok()?;
+            let _last_change_serial = take(4)?;
+            let value = match header[0] {
+                0 => XSetting::Int(card32(take(4)?) as i32),
+                1 => {
+                    let len = card32(take(4)?);
+                    XSetting::String(String::from_utf8(take(len as usize)?.to_vec()).ok()?)
+                }
+                2 => {
+                    let c = take(8)?;
+                    XSetting::Color([0, 2, 4, 6].map(|i| card16(&c[i..i + 2])))
+                }
+                _ => return None,
+            };
+            settings.push((name, value));
+        }
+        Some(Self { settings })
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&XSetting> {
+        self.settings
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value)
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            XSetting::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn int(&self, name: &str) -> Option<i32> {
+        match self.get(name)? {
+            XSetting::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Guesses the color theme from the name of the GTK theme (`Net/ThemeName`).
+    pub(crate) fn theme(&self) -> Option<Theme> {
+        let name = self.string("Net/ThemeName")?.to_ascii_lowercase();
+        Some(if name.starts_with("highcontrastinverse") {
+            Theme::Custom(Palette::HIGH_CONTRAST_DARK)
+        } else if name.starts_with("highcontrast") {
+            Theme::Custom(Palette::HIGH_CONTRAST)
+        } else if name.contains("dark") {
+            Theme::Dark
+        } else {
+            Theme::Light
         })
     }
+
+    /// Returns the font preferences from `Gtk/FontName` and `Xft/DPI`.
+    pub(crate) fn font_prefs(&self) -> FontPrefs {
+        let mut prefs = self
+            .string("Gtk/FontName")
+            .map(FontPrefs::from_font_name)
+            .unwrap_or_default();
+        // `Xft/DPI` is in 1024ths of a dot per inch, with -1 meaning "default". It already
+        // includes the desktop's scaling factor (`Gdk/WindowScalingFactor`).
+        if let Some(dpi) = self.int("Xft/DPI").filter(|dpi| *dpi > 0) {
+            prefs.dpi = Some(dpi as f32 / 1024.0);
+        }
+        prefs
+    }
+}
+
+/// X resources (`RESOURCE_MANAGER`), like those set in `~/.Xresources`.
+#[derive(Debug, Default)]
+pub(crate) struct XResources {
+    /// Resource specifiers (split into bindings and components) and their values, in the order they
+    /// were defined in.
+    entries: Vec<(Vec<(Binding, String)>, String)>,
+    /// The name and class resources are looked up with, if not [`XResources::NAME`] and
+    /// [`XResources::CLASS`].
+    app: Option<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Binding {
+    /// `.`, which binds to the next component directly.
+    Tight,
+    /// `*`, which can skip any number of components.
+    Loose,
+}
+
+/// Returns the instance name that [`X11Window::with_class`] writes to `WM_CLASS` for `app_id`.
+pub(crate) fn instance_name(app_id: &str) -> String {
+    app_id.replace('\0', "")
+}
+
+/// Returns the class name that [`X11Window::with_class`] writes to `WM_CLASS` for `app_id`.
+///
+/// By convention, that is the instance name with its first letter capitalized (`myapp` becomes
+/// `Myapp`).
+pub(crate) fn class_name(app_id: &str) -> String {
+    let name = instance_name(app_id);
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+/// Resources that override colors of the palette: name, class, and the color they override.
+///
+/// Only `background` and `foreground` use the classes common to all X applications; the others
+/// have classes of their own, so that `*Background` doesn't make the buttons indistinguishable.
+const COLOR_RESOURCES: &[(&str, &str, &str)] = &[
+    ("background", "Background", "background"),
+    ("foreground", "Foreground", "text"),
+    ("buttonBackground", "ButtonBackground", "button"),
+    (
+        "buttonHoverBackground",
+        "ButtonHoverBackground",
+        "button_hover",
+    ),
+    (
+        "buttonPressedBackground",
+        "ButtonPressedBackground",
+        "button_pressed",
+    ),
+    ("buttonBorderColor", "ButtonBorderColor", "button_outline"),
+    ("linkColor", "LinkColor", "accent"),
+    ("focusColor", "FocusColor", "focus"),
+    ("selectBackground", "SelectBackground", "selection"),
+];
+
+impl XResources {
+    /// The default name resources are looked up with (the default instance name in `WM_CLASS`).
+    const NAME: &str = "alerta";
+    /// The default class resources are looked up with (the default class name in `WM_CLASS`).
+    const CLASS: &str = "Alerta";
+
+    /// Parses the contents of the `RESOURCE_MANAGER` property.
+    ///
+    /// Malformed lines are ignored, like `xrdb` does.
+    pub(crate) fn parse(data: &str) -> Self {
+        let mut entries = Vec::new();
+        // Lines ending in a backslash continue on the next line.
+        let data = data.replace("\\\n", "");
+        for line in data.lines().map(str::trim_start) {
+            if line.starts_with('!') || line.starts_with('#') {
+                continue;
+            }
+            let Some((specifier, value)) = line.split_once(':') else {
+                continue;
+            };
+            let mut components = Vec::new();
+            let mut binding = Binding::Tight;
+            let mut name = String::new();
+            for c in specifier.trim().chars() {
+                match c {
+                    '.' | '*' => {
+                        if !name.is_empty() {
+                            components.push((binding, std::mem::take(&mut name)));
+                            binding = Binding::Tight;
+                        }
+                        if c == '*' {
+                            binding = Binding::Loose;
+                        }
+                    }
+                    _ => name.push(c),
+                }
+            }
+            if name.is_empty() {
+                continue;
+            }
+            components.push((binding, name));
+            let value = value
+                .trim()
+                .replace("\\\\", "\0")
+                .replace("\\n", "\n")
+                .replace('\0', "\\");
+            entries.push((components, value));
+        }
+        Self { entries, app: None }
+    }
+
+    /// Looks up resources with `name` and `class` instead of `alerta` and `Alerta`, to match the
+    /// instance and class names in `WM_CLASS` (see [`instance_name`] and [`class_name`]).
+    pub(crate) fn with_name(mut self, name: String, class: String) -> Self {
+        self.app = Some((name, class));
+        self
+    }
+
+    /// Looks up the value of a resource of the dialog, like `alerta.background`.
+    ///
+    /// When several entries match, the most specific one wins, following the precedence rules of
+    /// Xlib: matching a name beats matching a class, which beats matching `?`, which beats skipping
+    /// the component with a `*`; and tight bindings beat loose ones.
+    pub(crate) fn get(&self, name: &str, class: &str) -> Option<&str> {
+        let (app, app_class) = match &self.app {
+            Some((app, app_class)) => (app.as_str(), app_class.as_str()),
+            None => (Self::NAME, Self::CLASS),
+        };
+        self.lookup(&[(app, app_class), (name, class)])
+    }
+
+    /// Looks up the value of a resource by its full name and class.
+    fn lookup(&self, query: &[(&str, &str)]) -> Option<&str> {
+        let mut best = None;
+        for (components, value) in &self.entries {
+            if let Some(score) = Self::score(components, query)
+                && best.as_ref().is_none_or(|(best, _)| score >= *best)
+            {
+                best = Some((score, value.as_str()));
+            }
+        }
+        best.map(|(_, value)| value)
+    }
+
+    /// Matches `components` against `query`, and returns how specific the match is.
+    fn score(components: &[(Binding, String)], query: &[(&str, &str)]) -> Option<Vec<u8>> {
+        let Some(((binding, component), rest)) = components.split_first() else {
+            return query.is_empty().then(Vec::new);
+        };
+        let ((name, class), query_rest) = query.split_first()?;
+        let level = match component.as_str() {
+            c if c == *name => 6,
+            c if c == *class => 4,
+            "?" => 2,
+            _ => 0,
+        };
+        let mut best = None;
+        if level > 0
+            && let Some(mut score) = Self::score(rest, query_rest)
+        {
+            let tight = u8::from(*binding == Binding::Tight);
+            score.insert(0, level + tight);
+            best = Some(score);
+        }
+        // A loose binding can also skip this level of the query.
+        if *binding == Binding::Loose
+            && let Some(mut score) = Self::score(components, query_rest)
+        {
+            score.insert(0, 0);
+            best = best.max(Some(score));
+        }
+        best
+    }
+
+    /// Applies the color resources to `theme`.
+    ///
+    /// Returns `theme` unchanged if no colors are set.
+    pub(crate) fn apply_colors(&self, theme: Theme) -> Theme {
+        let colors = COLOR_RESOURCES
+            .iter()
+            .filter_map(|(name, class, field)| Some((*field, parse_color(self.get(name, class)?)?)))
+            .collect::<Vec<_>>();
+        let width = self
+            .get("buttonBorderWidth", "ButtonBorderWidth")
+            .and_then(|w| w.parse().ok());
+        if colors.is_empty() && width.is_none() {
+            return theme;
+        }
+
+        let mut palette = theme.palette();
+        for (field, color) in colors {
+            *palette.color_mut(field).unwrap() = color;
+        }
+        if let Some(width) = width {
+            palette.outline_width = width;
+        }
+        Theme::Custom(palette)
+    }
+
+    /// Applies the `font` and `Xft.dpi` resources to `prefs`.
+    ///
+    /// `Xft.dpi` is only used if `prefs` doesn't specify the DPI already.
+    pub(crate) fn apply_font_prefs(&self, mut prefs: FontPrefs) -> FontPrefs {
+        if let Some(font) = self.get("font", "Font").and_then(parse_font) {
+            prefs.family = font.family;
+            prefs.size = font.size.or(prefs.size);
+        }
+        if prefs.dpi.is_none() {
+            prefs.dpi = self
+                .lookup(&[("Xft", "Xft"), ("dpi", "Dpi")])
+                .and_then(|dpi| dpi.parse().ok())
+                .filter(|dpi| *dpi > 0.0);
+        }
+        prefs
+    }
+}
+
+/// Parses a color in `#rrggbb`, `#rgb` or `rgb:r/g/b` notation (with 1 to 4 hex digits per
+/// channel).
+///
+/// Color names (like `lightyellow`) are not supported.
+fn parse_color(value: &str) -> Option<Rgb> {
+    if let Some(channels) = value.strip_prefix("rgb:") {
+        let channels = channels
+            .split('/')
+            .map(|c| {
+                let max = 16u32.checked_pow(u32::try_from(c.len()).ok()?)? - 1;
+                let value = u32::from_str_radix(c, 16).ok()?;
+                Some((value * 255 + max / 2) / max)
+            })
+            .collect::<Option<Vec<_>>>()?;
+        return match *channels {
+            [r, g, b] => Some(Rgb(r as u8, g as u8, b as u8)),
+            _ => None,
+        };
+    }
+    value.parse().ok()
+}
+
+/// Parses a font resource in Xft/fontconfig (`DejaVu Sans-11`, `xft:DejaVu Sans:size=11`) or Pango
+/// (`DejaVu Sans 11`) notation.
+///
+/// XLFD names (`-misc-fixed-...`) refer to core X fonts, which alerta doesn't use.
+fn parse_font(value: &str) -> Option<FontPrefs> {
+    let value = value.strip_prefix("xft:").unwrap_or(value);
+    if value.starts_with('-') || value.is_empty() {
+        return None;
+    }
+    if !value.contains([':', '-']) {
+        return Some(FontPrefs::from_font_name(value));
+    }
+    let mut props = value.split(':');
+    let name = props.next().unwrap_or_default();
+    let (family, mut size) = match name.rsplit_once('-') {
+        Some((family, size)) if size.parse::<f32>().is_ok() => (family, size.parse().ok()),
+        _ => (name, None),
+    };
+    for prop in props {
+        if let Some(s) = prop.strip_prefix("size=") {
+            size = s.parse().ok();
+        }
+    }
+    Some(FontPrefs {
+        family: (!family.trim().is_empty()).then(|| family.trim().to_string()),
+        size: size.filter(|size| *size > 0.0),
+        dpi: None,
+    })
 }
 
 impl Deref for Connection {
@@ -63,45 +911,136 @@ impl Deref for Connection {
 
 const MOVERESIZE_MOVE: u32 = 8;
 
-const KEYCODE_ESC: u8 = 9;
+const XK_ESCAPE: u32 = 0xff1b;
+const XK_HOME: u32 = 0xff50;
+const XK_UP: u32 = 0xff52;
+const XK_DOWN: u32 = 0xff54;
+const XK_PAGE_UP: u32 = 0xff55;
+const XK_PAGE_DOWN: u32 = 0xff56;
+const XK_END: u32 = 0xff57;
+const XK_KP_HOME: u32 = 0xff95;
+const XK_KP_UP: u32 = 0xff97;
+const XK_KP_DOWN: u32 = 0xff99;
+const XK_KP_PAGE_UP: u32 = 0xff9a;
+const XK_KP_PAGE_DOWN: u32 = 0xff9b;
+const XK_KP_END: u32 = 0xff9c;
 
-const WM_CLASS: &[u8] = b"alerta\0alerta\0"; // instance, class
+/// How long to wait for the clipboard manager to take over the clipboard contents.
+const CLIPBOARD_MANAGER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The reason [`X11Window::wait_for_event_or`] returned.
+pub(crate) enum Wakeup {
+    Event(WindowEvent),
+    /// The file descriptor with this index became readable (or was closed).
+    Readable(usize),
+}
 
 pub(crate) struct X11Window {
     atoms: Atoms,
     conn: Connection,
     window: xproto::Window,
+    visual: Visual,
     gc: xproto::Gcontext,
+    /// Reloaded when the keyboard mapping changes (for example, by switching layouts).
+    keymap: RefCell<Keymap>,
+    /// Timestamp of the last user input, needed for acquiring selections.
+    last_input_time: Cell<Timestamp>,
+    clipboard: RefCell<Option<String>>,
+    primary: RefCell<Option<String>>,
+    /// Selection contents that are sent in chunks, as the requestors delete the previous one.
+    transfers: RefCell<Vec<IncrTransfer>>,
+    /// Last known size of the window.
+    size: Cell<(u16, u16)>,
+    /// Whether the urgency hint is set, and has to be cleared once the user focuses the window.
+    urgent: Cell<bool>,
+    /// Whether there is no window manager (see [`WindowState::unmanaged`]).
+    unmanaged: bool,
+    /// While the window is moved without a window manager, the pointer position within the window.
+    drag_offset: Cell<Option<(i16, i16)>>,
+    /// Whether to transfer the window contents through shared memory.
+    use_shm: Cell<bool>,
+    shm: RefCell<Option<ShmSegment>>,
 }
 
-impl X11Window {
-    pub(crate) fn create(conn: Connection, width: u16, height: u16) -> Result<Self, Error> {
-        let atoms = Atoms::new(&conn.inner).map_err(err)?.reply().map_err(err)?;
+/// A MIT-SHM segment backed by a memory file, which the X server maps into its address space.
+///
+/// The window contents are written into the file instead of being sent over the connection.
+struct ShmSegment {
+    seg: shm::Seg,
+    file: File,
+    len: usize,
+}
 
-        let screen = conn.inner.setup().roots.get(conn.screen).ok_or_else(|| {
-            Error::new(format!("screen '{}' does not exist on server", conn.screen))
-        })?;
+impl ShmSegment {
+    fn new(conn: &Connection, len: usize) -> Result<Self, Error> {
+        let file = File::from(memfd_create("alerta", MemfdFlags::CLOEXEC).map_err(err)?);
+        file.set_len(len as u64).map_err(err)?;
+        let seg = conn.generate_id().map_err(err)?;
+        conn.shm_attach_fd(seg, OwnedFd::from(file.try_clone().map_err(err)?), true)
+            .map_err(err)?
+            .check()
+            .map_err(err)?;
+        Ok(Self { seg, file, len })
+    }
+}
 
-        let visuals = screen
-            .allowed_depths
-            .iter()
-            .flat_map(|d| d.visuals.iter().map(move |vis| (vis, d.depth)));
-        let mut vid = None;
-        for (vty, depth) in visuals {
-            if depth == 24
-                && vty.class == VisualClass::TRUE_COLOR
-                && vty.red_mask == 0xff0000
-                && vty.green_mask == 0xff00
-                && vty.blue_mask == 0xff
-            {
-                vid = Some(vty.visual_id);
-                break;
-            }
-        }
+/// A selection transfer of contents too large for a single request (ICCCM "INCR Properties").
+struct IncrTransfer {
+    requestor: xproto::Window,
+    property: Atom,
+    target: Atom,
+    data: Vec<u8>,
+    /// How many bytes of `data` were sent already.
+    sent: usize,
+}
 
-        let Some(vid) = vid else {
-            return Err(Error::new("could not find a compatible X11 visual"));
-        };
+/// Keycode to keysym mapping of the X server.
+struct Keymap {
+    min_keycode: u8,
+    keysyms_per_keycode: u8,
+    keysyms: Vec<u32>,
+}
+
+impl Keymap {
+    fn load(conn: &Connection) -> Result<Self, Error> {
+        let setup = conn.setup();
+        let (min_keycode, max_keycode) = (setup.min_keycode, setup.max_keycode);
+        let mapping = conn
+            .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)
+            .map_err(err)?
+            .reply()
+            .map_err(err)?;
+        Ok(Self {
+            min_keycode,
+            keysyms_per_keycode: mapping.keysyms_per_keycode,
+            keysyms: mapping.keysyms,
+        })
+    }
+
+    /// Returns the unshifted keysym of the first keyboard group bound to `keycode`.
+    fn keysym(&self, keycode: u8) -> u32 {
+        let index = usize::from(keycode.wrapping_sub(self.min_keycode))
+            * usize::from(self.keysyms_per_keycode);
+        self.keysyms.get(index).copied().unwrap_or(0)
+    }
+}
+
+impl X11Window {
+    /// Creates the (still hidden) dialog window.
+    ///
+    /// For urgent windows, this sets the ICCCM urgency hint and the EWMH
+    /// `_NET_WM_STATE_DEMANDS_ATTENTION` state, which window managers and taskbars typically
+    /// indicate by flashing the window's entry.
+    pub(crate) fn create(
+        conn: Connection,
+        width: u16,
+        height: u16,
+        state: WindowState,
+    ) -> Result<Self, Error> {
+        let atoms = conn.atoms;
+        let screen = conn.screen()?;
+
+        let visual = conn.choose_visual(state.translucent)?;
 
         let attrs = CreateWindowAux::new()
             .event_mask(
@@ -115,23 +1054,37 @@ impl X11Window {
                     | EventMask::ENTER_WINDOW
                     | EventMask::LEAVE_WINDOW
                     | EventMask::BUTTON_PRESS
-                    | EventMask::BUTTON_RELEASE,
+                    | EventMask::BUTTON_RELEASE
+                    | EventMask::FOCUS_CHANGE,
             )
             .border_pixel(0)
-            .colormap(0);
+            .colormap(visual.colormap);
 
+        // Window managers decide where new windows go, otherwise center the window ourselves.
+        let (x, y) = if state.unmanaged {
+            let area = conn.work_area()?;
+            let center = |start: i16, space: u16, size: u16| {
+                start.saturating_add((space.saturating_sub(size) / 2) as i16)
+            };
+            (
+                center(area.x, area.width, width),
+                center(area.y, area.height, height),
+            )
+        } else {
+            (0, 0)
+        };
         let window = conn.generate_id().map_err(err)?;
         conn.create_window(
-            24,
+            visual.depth,
             window,
             screen.root,
-            0,
-            0,
+            x,
+            y,
             width,
             height,
             0,
             WindowClass::INPUT_OUTPUT,
-            vid,
+            visual.id,
             &attrs,
         )
         .map_err(err)?
@@ -149,47 +1102,133 @@ impl X11Window {
         // By default, X11 seems to kill the damn application with SIGTERM when
         // the window is closed (!?).
         // Opt into getting a `ClientMessage` event instead.
+        // `_NET_WM_PING` lets the window manager check whether we're still responsive.
         conn.change_property32(
             PropMode::REPLACE,
             window,
             atoms.WM_PROTOCOLS,
             AtomEnum::ATOM,
-            &[atoms.WM_DELETE_WINDOW],
+            &[atoms.WM_DELETE_WINDOW, atoms._NET_WM_PING],
         )
         .map_err(err)?;
 
-        // Configure size hints to prevent resizing the window.
-        WmSizeHints {
-            max_size: Some((width.into(), height.into())),
-            min_size: Some((width.into(), height.into())),
-            ..Default::default()
-        }
-        .set_normal_hints(&conn.inner, window)
-        .map_err(err)?
-        .check()
-        .map_err(err)?;
+        let keymap = Keymap::load(&conn)?;
+        let conn_supports_shm = conn.supports_shm();
 
         let mut win = X11Window {
             atoms,
             conn,
             window,
+            visual,
             gc,
+            keymap: RefCell::new(keymap),
+            last_input_time: Cell::new(x11rb::CURRENT_TIME),
+            clipboard: RefCell::new(None),
+            primary: RefCell::new(None),
+            transfers: RefCell::new(Vec::new()),
+            size: Cell::new((width, height)),
+            urgent: Cell::new(false),
+            unmanaged: state.unmanaged,
+            drag_offset: Cell::new(None),
+            use_shm: Cell::new(conn_supports_shm),
+            shm: RefCell::new(None),
         };
         win = win
-            .with_class(WM_CLASS)?
-            .with_window_type(WindowType::Dialog)?;
+            .with_client()?
+            .with_window_type(WindowType::Dialog)?
+            .with_state(state)?;
+        win.set_hints(state.urgent)?;
+        win.set_size_limits((width, height), Some((width, height)))?;
 
         Ok(win)
     }
 
-    fn with_class(self, cls: &[u8]) -> Result<Self, Error> {
+    /// Configures the size hints that limit how the user can resize the window.
+    ///
+    /// If `max` is [`None`], the window can be made arbitrarily large. By default, the window
+    /// cannot be resized.
+    pub(crate) fn set_size_limits(
+        &self,
+        min: (u16, u16),
+        max: Option<(u16, u16)>,
+    ) -> Result<(), Error> {
+        let cvt = |(w, h): (u16, u16)| (i32::from(w), i32::from(h));
+        WmSizeHints {
+            max_size: max.map(cvt),
+            min_size: Some(cvt(min)),
+            ..Default::default()
+        }
+        .set_normal_hints(&self.conn.inner, self.window)
+        .map_err(err)?
+        .check()
+        .map_err(err)?;
+        Ok(())
+    }
+
+    /// Returns the last known size of the window.
+    pub(crate) fn size(&self) -> (u16, u16) {
+        self.size.get()
+    }
+
+    /// Changes the size of the window.
+    ///
+    /// The new size has to be within the limits set by [`X11Window::set_size_limits`].
+    pub(crate) fn resize(&self, width: u16, height: u16) -> Result<(), Error> {
+        self.size.set((width, height));
+        self.conn
+            .configure_window(
+                self.window,
+                &ConfigureWindowAux::new()
+                    .width(u32::from(width))
+                    .height(u32::from(height)),
+            )
+            .map_err(err)?;
+        Ok(())
+    }
+
+    /// Sets `WM_CLASS`, which window managers use for matching window rules.
+    ///
+    /// The instance and class names are derived from `app_id` (see [`instance_name`] and
+    /// [`class_name`]).
+    pub(crate) fn with_class(self, app_id: &str) -> Result<Self, Error> {
+        let cls = format!("{}\0{}\0", instance_name(app_id), class_name(app_id));
         self.conn
             .change_property8(
                 PropMode::REPLACE,
                 self.window,
                 AtomEnum::WM_CLASS,
                 AtomEnum::STRING,
-                cls,
+                cls.as_bytes(),
+            )
+            .map_err(err)?
+            .check()
+            .map_err(err)?;
+        Ok(self)
+    }
+
+    /// Sets `_NET_WM_PID` and `WM_CLIENT_MACHINE`, which identify the process owning the window.
+    ///
+    /// Window managers use them to kill unresponsive clients.
+    fn with_client(self) -> Result<Self, Error> {
+        let uname = rustix::system::uname();
+        self.conn
+            .change_property8(
+                PropMode::REPLACE,
+                self.window,
+                AtomEnum::WM_CLIENT_MACHINE,
+                AtomEnum::STRING,
+                uname.nodename().to_bytes(),
+            )
+            .map_err(err)?
+            .check()
+            .map_err(err)?;
+        self.conn
+            .change_property32(
+                PropMode::REPLACE,
+                self.window,
+                self.atoms._NET_WM_PID,
+                AtomEnum::CARDINAL,
+                &[process::id()],
             )
             .map_err(err)?
             .check()
@@ -215,6 +1254,86 @@ impl X11Window {
         Ok(self)
     }
 
+    /// Sets the initial `_NET_WM_STATE`, which the window manager reads when the window is mapped.
+    fn with_state(self, state: WindowState) -> Result<Self, Error> {
+        let atoms = [
+            (state.above, self.atoms._NET_WM_STATE_ABOVE),
+            (state.sticky, self.atoms._NET_WM_STATE_STICKY),
+            (state.skip_taskbar, self.atoms._NET_WM_STATE_SKIP_TASKBAR),
+            (state.skip_pager, self.atoms._NET_WM_STATE_SKIP_PAGER),
+            (state.urgent, self.atoms._NET_WM_STATE_DEMANDS_ATTENTION),
+        ];
+        let atoms: Vec<Atom> = atoms
+            .into_iter()
+            .filter_map(|(set, atom)| set.then_some(atom))
+            .collect();
+        if !atoms.is_empty() {
+            self.conn
+                .change_property32(
+                    PropMode::REPLACE,
+                    self.window,
+                    self.atoms._NET_WM_STATE,
+                    AtomEnum::ATOM,
+                    &atoms,
+                )
+                .map_err(err)?
+                .check()
+                .map_err(err)?;
+        }
+        Ok(self)
+    }
+
+    /// Associates the window with the startup notification sequence `id`.
+    ///
+    /// This lets the window manager know that the window was opened in response to launching
+    /// alerta, and when that happened, so that focus stealing prevention doesn't keep it in the
+    /// background.
+    pub(crate) fn with_startup_id(self, id: &str) -> Result<Self, Error> {
+        self.conn
+            .change_property8(
+                PropMode::REPLACE,
+                self.window,
+                self.atoms._NET_STARTUP_ID,
+                self.atoms.UTF8_STRING,
+                id.as_bytes(),
+            )
+            .map_err(err)?
+            .check()
+            .map_err(err)?;
+        if let Some(time) = startup_time(id) {
+            self.set_user_time(time);
+        }
+        Ok(self)
+    }
+
+    /// Ends the startup notification sequence `id`, which stops the launch feedback (like a busy
+    /// cursor).
+    ///
+    /// Has to be called after the window was shown.
+    pub(crate) fn complete_startup(&self, id: &str) -> Result<(), Error> {
+        let root = self.conn.screen()?.root;
+        let mut msg = startup_remove_message(id).into_bytes();
+        msg.push(0);
+        // The message is split into client messages of 20 bytes each, the first of which has a
+        // different type.
+        let mut ty = self.atoms._NET_STARTUP_INFO_BEGIN;
+        for chunk in msg.chunks(20) {
+            let mut data = [0; 20];
+            data[..chunk.len()].copy_from_slice(chunk);
+            self.conn
+                .send_event(
+                    false,
+                    root,
+                    EventMask::PROPERTY_CHANGE,
+                    ClientMessageEvent::new(8, self.window, ty, data),
+                )
+                .map_err(err)?;
+            ty = self.atoms._NET_STARTUP_INFO;
+        }
+        self.conn.flush().map_err(err)?;
+        Ok(())
+    }
+
     pub(crate) fn with_title(self, mut title: String) -> Result<Self, Error> {
         if !title.ends_with('\0') {
             title.push('\0');
@@ -246,26 +1365,213 @@ impl X11Window {
         Ok(self)
     }
 
-    pub(crate) fn set_contents(&self, pixmap: &DrawTarget) -> Result<(), Error> {
+    /// Sets `WM_HINTS`, which tell the window manager to give the window input focus and whether
+    /// it is urgent.
+    fn set_hints(&self, urgent: bool) -> Result<(), Error> {
+        self.urgent.set(urgent);
+        WmHints {
+            input: Some(true),
+            urgent,
+            ..WmHints::new()
+        }
+        .set(&self.conn.inner, self.window)
+        .map_err(err)?;
+        Ok(())
+    }
+
+    /// Rings the keyboard bell.
+    ///
+    /// The bell is rung through XKB when possible, which passes `name` (like `dialog-error`) on to
+    /// desktops that play event sounds instead of beeping. Otherwise, the core protocol bell is
+    /// used.
+    pub(crate) fn ring_bell(&self, name: &str) -> Result<(), Error> {
+        let xkb = self
+            .conn
+            .xkb_use_extension(1, 0)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .is_some_and(|reply| reply.supported);
+        if xkb {
+            let name = self
+                .conn
+                .intern_atom(false, name.as_bytes())
+                .map_err(err)?
+                .reply()
+                .map_err(err)?
+                .atom;
+            self.conn
+                .xkb_bell(
+                    xkb::ID::USE_CORE_KBD.into(),
+                    xkb::ID::DFLT_XI_CLASS.into(),
+                    xkb::ID::DFLT_XI_ID.into(),
+                    0,
+                    false,
+                    false,
+                    0,
+                    0,
+                    name,
+                    self.window,
+                )
+                .map_err(err)?;
+        } else {
+            self.conn.bell(0).map_err(err)?;
+        }
+        Ok(())
+    }
+
+    /// Tells the window manager that the outer `margin` of the window is a client-side shadow.
+    ///
+    /// Window managers then leave it out when placing, snapping and tiling the window, and don't
+    /// accept clicks there.
+    pub(crate) fn set_frame_extents(&self, margin: u32) -> Result<(), Error> {
         self.conn
-            .put_image(
-                ImageFormat::Z_PIXMAP,
+            .change_property32(
+                PropMode::REPLACE,
                 self.window,
-                self.gc,
-                pixmap.width().try_into().unwrap(),
-                pixmap.height().try_into().unwrap(),
-                0,
-                0,
-                0,
-                24,
-                pixmap.get_data_u8(),
+                self.atoms._GTK_FRAME_EXTENTS,
+                AtomEnum::CARDINAL,
+                &[margin; 4],
             )
-            .map_err(err)?
-            .check()
             .map_err(err)?;
         Ok(())
     }
 
+    /// Returns a renderer for drawing the window contents on the server, if that is worthwhile.
+    ///
+    /// It is if the X server runs on a different machine (and supports the X Rendering Extension),
+    /// since uploading the rendered pixels would then take much more bandwidth.
+    pub(crate) fn renderer(&self) -> Option<Renderer> {
+        if self.conn.is_local() {
+            return None;
+        }
+        Renderer::new(self).ok()
+    }
+
+    /// Copies the areas `rects` of `pixmap` into the window.
+    ///
+    /// The image is transferred through shared memory if the X server supports it (and runs on the
+    /// same machine). Otherwise, it is sent in strips that fit within the maximum request length.
+    pub(crate) fn set_contents(&self, pixmap: &DrawTarget, rects: &[IntRect]) -> Result<(), Error> {
+        let bounds = IntRect::from_size(size2(pixmap.width(), pixmap.height()));
+        let rects = rects
+            .iter()
+            .filter_map(|rect| rect.intersection(&bounds))
+            .filter(|rect| !rect.is_empty())
+            .collect::<Vec<_>>();
+        if self.use_shm.get() {
+            match self.put_image_shm(pixmap, &rects) {
+                Ok(()) => return Ok(()),
+                // Keep using the slow path, since the server could not access our memory.
+                Err(_) => self.use_shm.set(false),
+            }
+        }
+        self.put_image_strips(pixmap, &rects)
+    }
+
+    fn put_image_shm(&self, pixmap: &DrawTarget, rects: &[IntRect]) -> Result<(), Error> {
+        let (width, height) = (pixmap.width() as u16, pixmap.height() as u16);
+        let row_len = self.visual.format.row_len(width.into());
+        let len = row_len * usize::from(height);
+        let mut shm = self.shm.borrow_mut();
+        if let Some(segment) = shm.take_if(|segment| segment.len < len) {
+            self.conn.shm_detach(segment.seg).map_err(err)?;
+        }
+        let segment = match &mut *shm {
+            Some(segment) => segment,
+            None => shm.insert(ShmSegment::new(&self.conn, len)?),
+        };
+
+        let pixels = pixmap.get_data();
+        let mut cookies = Vec::new();
+        for rect in rects {
+            let (start, end) = (rect.min.y as usize, rect.max.y as usize);
+            let data = self.visual.format.convert(
+                &pixels[start * usize::from(width)..end * usize::from(width)],
+                width.into(),
+            );
+            segment
+                .file
+                .write_all_at(&data, (start * row_len) as u64)
+                .map_err(err)?;
+            cookies.push(
+                self.conn
+                    .shm_put_image(
+                        self.window,
+                        self.gc,
+                        width,
+                        height,
+                        rect.min.x as u16,
+                        rect.min.y as u16,
+                        rect.width() as u16,
+                        rect.height() as u16,
+                        rect.min.x as i16,
+                        rect.min.y as i16,
+                        self.visual.depth,
+                        ImageFormat::Z_PIXMAP.into(),
+                        false,
+                        segment.seg,
+                        0,
+                    )
+                    .map_err(err)?,
+            );
+        }
+        // Waiting for the requests to be processed ensures that the server is done reading the
+        // segment before we overwrite it with the next frame.
+        for cookie in cookies {
+            cookie.check().map_err(err)?;
+        }
+        Ok(())
+    }
+
+    fn put_image_strips(&self, pixmap: &DrawTarget, rects: &[IntRect]) -> Result<(), Error> {
+        // Size of the `PutImage` request without the image data.
+        const HEADER_LEN: usize = 24;
+
+        let pixels = pixmap.get_data();
+        let stride = pixmap.width() as usize;
+        let mut cookies = Vec::new();
+        for rect in rects {
+            let width = rect.width() as usize;
+            let row_len = self.visual.format.row_len(width);
+            // Uses BIG-REQUESTS if the server supports it.
+            let max_rows = cmp::max(
+                (self.conn.maximum_request_bytes() - HEADER_LEN) / row_len,
+                1,
+            );
+            let mut y = rect.min.y;
+            while y < rect.max.y {
+                let rows = cmp::min(max_rows as i32, rect.max.y - y);
+                let mut strip = Vec::with_capacity(rows as usize * width);
+                for row in y..y + rows {
+                    let start = row as usize * stride + rect.min.x as usize;
+                    strip.extend_from_slice(&pixels[start..start + width]);
+                }
+                let strip = self.visual.format.convert(&strip, width);
+                cookies.push(
+                    self.conn
+                        .put_image(
+                            ImageFormat::Z_PIXMAP,
+                            self.window,
+                            self.gc,
+                            rect.width() as u16,
+                            rows as u16,
+                            rect.min.x as i16,
+                            y as i16,
+                            0,
+                            self.visual.depth,
+                            &strip,
+                        )
+                        .map_err(err)?,
+                );
+                y += rows;
+            }
+        }
+        for cookie in cookies {
+            cookie.check().map_err(err)?;
+        }
+        Ok(())
+    }
+
     /// Makes the window visible and raises it to the foreground.
     pub(crate) fn show(&self) -> Result<(), Error> {
         self.conn.map_window(self.window).map_err(err)?;
@@ -280,11 +1586,32 @@ impl X11Window {
         Ok(())
     }
 
-    pub(crate) fn wait_for_event(&self) -> Result<WindowEvent, Error> {
+    /// Waits until either an event arrives or one of `fds` becomes readable.
+    ///
+    /// Entries of `fds` that are [`None`] are ignored.
+    pub(crate) fn wait_for_event_or(
+        &self,
+        fds: &[Option<BorrowedFd<'_>>],
+    ) -> Result<Wakeup, Error> {
         loop {
-            let ev = self.conn.wait_for_event().map_err(err)?;
-            if let Some(ev) = self.cvt_event(ev) {
-                return Ok(ev);
+            if let Some(ev) = self.poll_for_event()? {
+                return Ok(Wakeup::Event(ev));
+            }
+            self.conn.flush().map_err(err)?;
+            let mut poll_fds = vec![PollFd::new(self.conn.stream(), PollFlags::IN)];
+            let mut indices = Vec::new();
+            for (i, fd) in fds.iter().enumerate() {
+                if let Some(fd) = fd {
+                    poll_fds.push(PollFd::from_borrowed_fd(*fd, PollFlags::IN));
+                    indices.push(i);
+                }
+            }
+            match poll(&mut poll_fds, None) {
+                Ok(_) | Err(Errno::INTR) => {}
+                Err(e) => return Err(err(e)),
+            }
+            if let Some(pos) = poll_fds[1..].iter().position(|fd| !fd.revents().is_empty()) {
+                return Ok(Wakeup::Readable(indices[pos]));
             }
         }
     }
@@ -307,33 +1634,393 @@ impl X11Window {
             Event::ClientMessage(msg) if msg.data.as_data32()[0] == self.atoms.WM_DELETE_WINDOW => {
                 WindowEvent::CloseRequested
             }
-            Event::KeyPress(press)
-                if press.event == self.window
-                    && press.detail == KEYCODE_ESC
-                    && !press
-                        .state
-                        .intersects(KeyButMask::CONTROL | KeyButMask::SHIFT | KeyButMask::MOD1) =>
-            {
-                // ESC closes the dialog.
-                WindowEvent::CloseRequested
+            Event::ClientMessage(msg) if msg.data.as_data32()[0] == self.atoms._NET_WM_PING => {
+                self.answer_ping(msg).ok();
+                return None;
+            }
+            Event::KeyPress(press) if press.event == self.window => {
+                self.set_user_time(press.time);
+                let key = key(self.keymap.borrow().keysym(press.detail))?;
+                let mods = Modifiers {
+                    ctrl: press.state.contains(KeyButMask::CONTROL),
+                    shift: press.state.contains(KeyButMask::SHIFT),
+                    alt: press.state.contains(KeyButMask::MOD1),
+                };
+                WindowEvent::KeyPress(key, mods)
+            }
+            Event::MapNotify(e) if e.window == self.window && self.unmanaged => {
+                // Without a window manager, the window only gets keyboard input if we ask for it,
+                // which is only possible once it is viewable.
+                self.conn
+                    .set_input_focus(InputFocus::PARENT, self.window, x11rb::CURRENT_TIME)
+                    .ok();
+                return None;
+            }
+            Event::MappingNotify(e) if e.request == Mapping::KEYBOARD => {
+                if let Ok(keymap) = Keymap::load(&self.conn) {
+                    *self.keymap.borrow_mut() = keymap;
+                }
+                return None;
             }
             Event::Expose(ex) if ex.count == 0 => WindowEvent::RedrawRequested,
+            Event::ConfigureNotify(e)
+                if e.window == self.window && (e.width, e.height) != self.size.get() =>
+            {
+                self.size.set((e.width, e.height));
+                WindowEvent::Resized(e.width, e.height)
+            }
             Event::EnterNotify(e) => WindowEvent::CursorEnter(CursorPos {
                 x: e.event_x,
                 y: e.event_y,
             }),
             Event::LeaveNotify(_) => WindowEvent::CursorLeave,
+            Event::MotionNotify(e) if let Some((dx, dy)) = self.drag_offset.get() => {
+                self.conn
+                    .configure_window(
+                        self.window,
+                        &ConfigureWindowAux::new()
+                            .x(i32::from(e.root_x - dx))
+                            .y(i32::from(e.root_y - dy)),
+                    )
+                    .ok();
+                return None;
+            }
             Event::MotionNotify(e) => WindowEvent::CursorMove(CursorPos {
                 x: e.event_x,
                 y: e.event_y,
             }),
-            Event::ButtonPress(e) => mouse_button(e.detail).map(WindowEvent::ButtonPress)?,
-            Event::ButtonRelease(e) => mouse_button(e.detail).map(WindowEvent::ButtonRelease)?,
+            Event::ButtonPress(e) => {
+                self.set_user_time(e.time);
+                match e.detail {
+                    // Buttons 4 and 5 are the mouse wheel.
+                    4 => WindowEvent::Scroll(-1),
+                    5 => WindowEvent::Scroll(1),
+                    _ => mouse_button(e.detail).map(WindowEvent::ButtonPress)?,
+                }
+            }
+            Event::ButtonRelease(e) => {
+                self.last_input_time.set(e.time);
+                self.drag_offset.set(None);
+                mouse_button(e.detail).map(WindowEvent::ButtonRelease)?
+            }
+            Event::FocusIn(e) if e.event == self.window && self.urgent.get() => {
+                // ICCCM leaves it to the client to reset the urgency hint once it got attention.
+                self.set_hints(false).ok();
+                return None;
+            }
+            Event::SelectionRequest(req) => {
+                self.answer_selection_request(req).ok();
+                return None;
+            }
+            Event::PropertyNotify(e) if e.state == Property::DELETE => {
+                self.continue_transfer(e.window, e.atom).ok();
+                return None;
+            }
+            Event::SelectionClear(e) => {
+                if e.selection == self.atoms.CLIPBOARD {
+                    self.clipboard.take();
+                } else if e.selection == AtomEnum::PRIMARY.into() {
+                    self.primary.take();
+                }
+                return None;
+            }
             _ => return None,
         })
     }
 
+    /// Records the time of a key or button press.
+    ///
+    /// Window managers use `_NET_WM_USER_TIME` to decide whether a window may take the focus from
+    /// the window the user interacted with most recently.
+    fn set_user_time(&self, time: Timestamp) {
+        self.last_input_time.set(time);
+        self.conn
+            .change_property32(
+                PropMode::REPLACE,
+                self.window,
+                self.atoms._NET_WM_USER_TIME,
+                AtomEnum::CARDINAL,
+                &[time],
+            )
+            .ok();
+    }
+
+    /// Sends a `_NET_WM_PING` message back to the root window, as EWMH asks for.
+    fn answer_ping(&self, mut msg: ClientMessageEvent) -> Result<(), Error> {
+        let root = self.conn.screen()?.root;
+        msg.window = root;
+        self.conn
+            .send_event(
+                false,
+                root,
+                EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT,
+                msg,
+            )
+            .map_err(err)?;
+        Ok(())
+    }
+
+    /// Takes ownership of `selection` and makes it contain `text`.
+    pub(crate) fn set_selection(&self, selection: Selection, text: String) -> Result<(), Error> {
+        let (atom, contents) = match selection {
+            Selection::Clipboard => (self.atoms.CLIPBOARD, &self.clipboard),
+            Selection::Primary => (AtomEnum::PRIMARY.into(), &self.primary),
+        };
+
+        self.conn
+            .set_selection_owner(self.window, atom, self.last_input_time.get())
+            .map_err(err)?;
+        let owner = self
+            .conn
+            .get_selection_owner(atom)
+            .map_err(err)?
+            .reply()
+            .map_err(err)?
+            .owner;
+        if owner != self.window {
+            return Err(Error::new("failed to acquire X11 selection"));
+        }
+
+        *contents.borrow_mut() = Some(text);
+        Ok(())
+    }
+
+    fn answer_selection_request(&self, req: SelectionRequestEvent) -> Result<(), Error> {
+        let contents = if req.selection == self.atoms.CLIPBOARD {
+            self.clipboard.borrow()
+        } else if req.selection == AtomEnum::PRIMARY.into() {
+            self.primary.borrow()
+        } else {
+            return Ok(());
+        };
+
+        // Obsolete clients may not specify a property; ICCCM says to use the target atom then.
+        let property = if req.property == x11rb::NONE {
+            req.target
+        } else {
+            req.property
+        };
+
+        // The requestor waits for the `SelectionNotify` event, so it is sent even if storing the
+        // contents failed.
+        let stored = match &*contents {
+            Some(text) => self.store_selection(&req, property, text).unwrap_or(false),
+            None => false,
+        };
+
+        self.conn
+            .send_event(
+                false,
+                req.requestor,
+                EventMask::NO_EVENT,
+                SelectionNotifyEvent {
+                    response_type: xproto::SELECTION_NOTIFY_EVENT,
+                    sequence: 0,
+                    time: req.time,
+                    requestor: req.requestor,
+                    selection: req.selection,
+                    target: req.target,
+                    property: if stored { property } else { x11rb::NONE },
+                },
+            )
+            .map_err(err)?;
+        self.conn.flush().map_err(err)?;
+        Ok(())
+    }
+
+    /// Stores `text`, converted to the target of `req`, in `property` of the requestor.
+    ///
+    /// Returns `false` if `text` can't be converted to the target.
+    fn store_selection(
+        &self,
+        req: &SelectionRequestEvent,
+        property: Atom,
+        text: &str,
+    ) -> Result<bool, Error> {
+        let string: Atom = AtomEnum::STRING.into();
+        let data = if req.target == self.atoms.TARGETS {
+            let mut targets = vec![self.atoms.TARGETS, self.atoms.UTF8_STRING];
+            if latin1(text).is_some() {
+                targets.push(string);
+            }
+            self.conn
+                .change_property32(
+                    PropMode::REPLACE,
+                    req.requestor,
+                    property,
+                    AtomEnum::ATOM,
+                    &targets,
+                )
+                .map_err(err)?
+                .check()
+                .map_err(err)?;
+            return Ok(true);
+        } else if req.target == self.atoms.UTF8_STRING {
+            text.as_bytes().to_vec()
+        } else if req.target == string {
+            match latin1(text) {
+                Some(data) => data,
+                None => return Ok(false),
+            }
+        } else {
+            return Ok(false);
+        };
+
+        if data.len() <= self.max_property_chunk() {
+            self.conn
+                .change_property8(
+                    PropMode::REPLACE,
+                    req.requestor,
+                    property,
+                    req.target,
+                    &data,
+                )
+                .map_err(err)?
+                .check()
+                .map_err(err)?;
+            return Ok(true);
+        }
+
+        // The requestor deletes the property to ask for each chunk, which we have to listen for.
+        self.conn
+            .change_window_attributes(
+                req.requestor,
+                &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+            )
+            .map_err(err)?;
+        self.conn
+            .change_property32(
+                PropMode::REPLACE,
+                req.requestor,
+                property,
+                self.atoms.INCR,
+                &[u32::try_from(data.len()).unwrap_or(u32::MAX)],
+            )
+            .map_err(err)?
+            .check()
+            .map_err(err)?;
+        let mut transfers = self.transfers.borrow_mut();
+        transfers.retain(|t| (t.requestor, t.property) != (req.requestor, property));
+        transfers.push(IncrTransfer {
+            requestor: req.requestor,
+            property,
+            target: req.target,
+            data,
+            sent: 0,
+        });
+        Ok(true)
+    }
+
+    /// Sends the next chunk of an `INCR` transfer once the requestor deleted `property`.
+    ///
+    /// The transfer ends with an empty chunk.
+    fn continue_transfer(&self, requestor: xproto::Window, property: Atom) -> Result<(), Error> {
+        let mut transfers = self.transfers.borrow_mut();
+        let Some(index) = transfers
+            .iter()
+            .position(|t| t.requestor == requestor && t.property == property)
+        else {
+            return Ok(());
+        };
+        let transfer = &mut transfers[index];
+        let end = cmp::min(
+            transfer.sent + self.max_property_chunk(),
+            transfer.data.len(),
+        );
+        let chunk = &transfer.data[transfer.sent..end];
+        let done = chunk.is_empty();
+        let result = self.conn.change_property8(
+            PropMode::REPLACE,
+            requestor,
+            property,
+            transfer.target,
+            chunk,
+        );
+        transfer.sent = end;
+        if done || result.is_err() {
+            transfers.swap_remove(index);
+            if !transfers.iter().any(|t| t.requestor == requestor) {
+                self.conn
+                    .change_window_attributes(
+                        requestor,
+                        &ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT),
+                    )
+                    .ok();
+            }
+        }
+        result.map_err(err)?;
+        self.conn.flush().map_err(err)?;
+        Ok(())
+    }
+
+    /// Returns how many bytes of property data fit into a single `ChangeProperty` request.
+    fn max_property_chunk(&self) -> usize {
+        // Size of the `ChangeProperty` request without the data.
+        const HEADER_LEN: usize = 24;
+        // Uses BIG-REQUESTS if the server supports it.
+        self.conn.maximum_request_bytes() - HEADER_LEN
+    }
+
+    /// Asks the clipboard manager to take over the contents of the clipboard, if we own it.
+    ///
+    /// This will block until the clipboard manager has copied the data (or a timeout elapses).
+    pub(crate) fn persist_clipboard(&self) -> Result<(), Error> {
+        if self.clipboard.borrow().is_none() {
+            return Ok(());
+        }
+        let manager = self
+            .conn
+            .get_selection_owner(self.atoms.CLIPBOARD_MANAGER)
+            .map_err(err)?
+            .reply()
+            .map_err(err)?
+            .owner;
+        if manager == x11rb::NONE {
+            return Ok(());
+        }
+
+        self.conn
+            .convert_selection(
+                self.window,
+                self.atoms.CLIPBOARD_MANAGER,
+                self.atoms.SAVE_TARGETS,
+                x11rb::NONE,
+                self.last_input_time.get(),
+            )
+            .map_err(err)?;
+        self.conn.flush().map_err(err)?;
+
+        // Keep answering selection requests until the manager tells us it's done.
+        let deadline = Instant::now() + CLIPBOARD_MANAGER_TIMEOUT;
+        loop {
+            match self.conn.poll_for_event().map_err(err)? {
+                Some(Event::SelectionNotify(e)) if e.selection == self.atoms.CLIPBOARD_MANAGER => {
+                    return Ok(());
+                }
+                Some(ev) => {
+                    self.cvt_event(ev);
+                }
+                None => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Ok(());
+                    }
+                    self.conn.flush().map_err(err)?;
+                    let timeout = Timespec::try_from(remaining).map_err(err)?;
+                    let mut fds = [PollFd::new(self.conn.stream(), PollFlags::IN)];
+                    match poll(&mut fds, Some(&timeout)) {
+                        Ok(_) | Err(Errno::INTR) => {}
+                        Err(e) => return Err(err(e)),
+                    }
+                }
+            }
+        }
+    }
+
     /// Initiates window dragging.
+    ///
+    /// Without a window manager, the window follows the pointer until the mouse button is
+    /// released.
     pub(crate) fn start_drag(&self) -> Result<(), Error> {
         let pointer = self
             .conn
@@ -341,6 +2028,10 @@ impl X11Window {
             .map_err(err)?
             .reply()
             .map_err(err)?;
+        if self.unmanaged {
+            self.drag_offset.set(Some((pointer.win_x, pointer.win_y)));
+            return Ok(());
+        }
 
         let window_pos = self
             .conn
@@ -372,6 +2063,49 @@ impl X11Window {
     }
 }
 
+/// Encodes `text` in ISO Latin-1, which ICCCM prescribes for the `STRING` selection target.
+///
+/// Returns `None` if `text` contains characters that Latin-1 can't represent.
+pub(crate) fn latin1(text: &str) -> Option<Vec<u8>> {
+    text.chars().map(|c| u8::try_from(c).ok()).collect()
+}
+
+/// Returns the time of the user interaction that launched alerta, which launchers include in
+/// startup notification IDs as a `_TIME<timestamp>` suffix.
+pub(crate) fn startup_time(id: &str) -> Option<Timestamp> {
+    id.rsplit_once("_TIME")?.1.parse().ok()
+}
+
+/// Builds the startup notification message that ends the sequence `id`.
+pub(crate) fn startup_remove_message(id: &str) -> String {
+    let mut msg = String::from("remove: ID=\"");
+    for c in id.chars() {
+        if matches!(c, '"' | '\\') {
+            msg.push('\\');
+        }
+        msg.push(c);
+    }
+    msg.push('"');
+    msg
+}
+
+fn key(keysym: u32) -> Option<Key> {
+    Some(match keysym {
+        XK_ESCAPE => Key::Escape,
+        XK_HOME | XK_KP_HOME => Key::Home,
+        XK_END | XK_KP_END => Key::End,
+        XK_UP | XK_KP_UP => Key::Up,
+        XK_DOWN | XK_KP_DOWN => Key::Down,
+        XK_PAGE_UP | XK_KP_PAGE_UP => Key::PageUp,
+        XK_PAGE_DOWN | XK_KP_PAGE_DOWN => Key::PageDown,
+        // Latin-1 keysyms map directly to their code points.
+        0x20..=0x7e | 0xa0..=0xff => Key::Char(char::from_u32(keysym)?),
+        // Unicode keysyms.
+        0x0100_0100..=0x0110_ffff => Key::Char(char::from_u32(keysym - 0x0100_0000)?),
+        _ => return None,
+    })
+}
+
 fn mouse_button(detail: u8) -> Option<MouseButton> {
     Some(match detail {
         1 => MouseButton::Left,
@@ -380,3 +2114,24 @@ fn mouse_button(detail: u8) -> Option<MouseButton> {
         _ => return None,
     })
 }
+
+fn contains(rect: Rectangle, x: i16, y: i16) -> bool {
+    let (x, y) = (i32::from(x), i32::from(y));
+    x >= rect.x.into()
+        && y >= rect.y.into()
+        && x < i32::from(rect.x) + i32::from(rect.width)
+        && y < i32::from(rect.y) + i32::from(rect.height)
+}
+
+fn intersect(a: Rectangle, b: Rectangle) -> Rectangle {
+    let x0 = a.x.max(b.x);
+    let y0 = a.y.max(b.y);
+    let x1 = (i32::from(a.x) + i32::from(a.width)).min(i32::from(b.x) + i32::from(b.width));
+    let y1 = (i32::from(a.y) + i32::from(a.height)).min(i32::from(b.y) + i32::from(b.height));
+    Rectangle {
+        x: x0,
+        y: y0,
+        width: (x1 - i32::from(x0)).max(0) as u16,
+        height: (y1 - i32::from(y0)).max(0) as u16,
+    }
+}
@@ -0,0 +1,152 @@
+//! Conversion of rendered pixels into the format of an X server's images.
+//!
+//! The canvas holds 32-bit ARGB pixels in native byte order. That happens to be what the common
+//! 24-bit TrueColor visual expects from a little-endian client talking to a little-endian server,
+//! but servers may also use other color depths, indexed colors, or big-endian images.
+
+use std::borrow::Cow;
+
+/// Number of levels per channel in the color cube used for indexed colors.
+pub(crate) const CUBE_LEVELS: usize = 6;
+
+/// The layout of pixels in the images sent to the X server.
+pub(crate) struct PixelFormat {
+    bits_per_pixel: u8,
+    /// Rows are padded to a multiple of this many bits.
+    scanline_pad: u8,
+    msb_first: bool,
+    colors: Colors,
+}
+
+enum Colors {
+    /// Every channel has its own bits in the pixel value. The tables map 8-bit alpha, red, green
+    /// and blue values to those bits.
+    Direct(Box<[[u32; 256]; 4]>),
+    /// Pixel values are indices into a colormap, which contains a color cube with `CUBE_LEVELS`
+    /// levels per channel. Holds the pixel values of the cube's colors, in red-major order.
+    Indexed(Vec<u32>),
+}
+
+impl PixelFormat {
+    /// Creates a format in which every channel is given by a bit mask (alpha, red, green, blue).
+    ///
+    /// Returns [`None`] if pixels aren't a whole number of bytes.
+    pub(crate) fn direct(
+        bits_per_pixel: u8,
+        scanline_pad: u8,
+        msb_first: bool,
+        masks: [u32; 4],
+    ) -> Option<Self> {
+        let mut tables = Box::new([[0; 256]; 4]);
+        for (table, mask) in tables.iter_mut().zip(masks) {
+            if mask == 0 {
+                continue;
+            }
+            let shift = mask.trailing_zeros();
+            let max = u64::from(mask >> shift);
+            for (value, entry) in table.iter_mut().enumerate() {
+                // Scale with rounding, so that 0xff maps to all ones.
+                *entry = (((value as u64 * max + 127) / 255) as u32) << shift;
+            }
+        }
+        Self::new(
+            bits_per_pixel,
+            scanline_pad,
+            msb_first,
+            Colors::Direct(tables),
+        )
+    }
+
+    /// Creates a format for indexed colors, given the pixel values of the color cube (see
+    /// [`cube_color`]).
+    pub(crate) fn indexed(
+        bits_per_pixel: u8,
+        scanline_pad: u8,
+        msb_first: bool,
+        cube: Vec<u32>,
+    ) -> Option<Self> {
+        assert_eq!(cube.len(), CUBE_LEVELS.pow(3));
+        Self::new(
+            bits_per_pixel,
+            scanline_pad,
+            msb_first,
+            Colors::Indexed(cube),
+        )
+    }
+
+    fn new(bits_per_pixel: u8, scanline_pad: u8, msb_first: bool, colors: Colors) -> Option<Self> {
+        if !matches!(bits_per_pixel, 8 | 16 | 24 | 32) || !scanline_pad.is_multiple_of(8) {
+            return None;
+        }
+        Some(Self {
+            bits_per_pixel,
+            scanline_pad,
+            msb_first,
+            colors,
+        })
+    }
+
+    /// Returns the number of bytes in a row of `width` pixels, including padding.
+    pub(crate) fn row_len(&self, width: usize) -> usize {
+        let pad = usize::from(self.scanline_pad);
+        (width * usize::from(self.bits_per_pixel)).next_multiple_of(pad) / 8
+    }
+
+    /// Returns whether the canvas pixels can be sent as they are.
+    fn is_native(&self) -> bool {
+        let Colors::Direct(tables) = &self.colors else {
+            return false;
+        };
+        // Alpha doesn't matter: for opaque windows, the server ignores the unused bits.
+        self.bits_per_pixel == 32
+            && self.msb_first == cfg!(target_endian = "big")
+            && tables[1][0xff] == 0xff0000
+            && tables[2][0xff] == 0xff00
+            && tables[3][0xff] == 0xff
+    }
+
+    /// Converts rows of `width` premultiplied ARGB pixels.
+    pub(crate) fn convert<'a>(&self, pixels: &'a [u32], width: usize) -> Cow<'a, [u8]> {
+        if self.is_native() {
+            return Cow::Borrowed(bytemuck::cast_slice(pixels));
+        }
+
+        let bytes = usize::from(self.bits_per_pixel / 8);
+        let row_len = self.row_len(width);
+        let mut out = Vec::with_capacity(pixels.len().div_ceil(width.max(1)) * row_len);
+        for row in pixels.chunks(width.max(1)) {
+            for &argb in row {
+                let [a, r, g, b] = argb.to_be_bytes();
+                let value = match &self.colors {
+                    Colors::Direct(tables) => {
+                        tables[0][usize::from(a)]
+                            | tables[1][usize::from(r)]
+                            | tables[2][usize::from(g)]
+                            | tables[3][usize::from(b)]
+                    }
+                    Colors::Indexed(cube) => {
+                        let level = |c: u8| (usize::from(c) * (CUBE_LEVELS - 1) + 127) / 255;
+                        cube[(level(r) * CUBE_LEVELS + level(g)) * CUBE_LEVELS + level(b)]
+                    }
+                };
+                if self.msb_first {
+                    out.extend_from_slice(&value.to_be_bytes()[4 - bytes..]);
+                } else {
+                    out.extend_from_slice(&value.to_le_bytes()[..bytes]);
+                }
+            }
+            out.resize(out.len() + row_len - row.len() * bytes, 0);
+        }
+        Cow::Owned(out)
+    }
+}
+
+/// Returns the color at `index` in the color cube of indexed formats, as 16-bit RGB values.
+pub(crate) fn cube_color(index: usize) -> [u16; 3] {
+    let level = |l: usize| (l * 0xffff / (CUBE_LEVELS - 1)) as u16;
+    [
+        level(index / (CUBE_LEVELS * CUBE_LEVELS)),
+        level(index / CUBE_LEVELS % CUBE_LEVELS),
+        level(index % CUBE_LEVELS),
+    ]
+}
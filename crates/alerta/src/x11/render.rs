@@ -0,0 +1,554 @@
+//! Drawing with the X Rendering Extension.
+//!
+//! Instead of uploading rendered pixels, the window contents are described by requests that the X
+//! server carries out: shapes are split into triangles, and glyphs and images are uploaded once and
+//! then referenced by ID. Over a network connection, this needs a fraction of the bandwidth.
+
+use std::{
+    cmp,
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use euclid::{
+    default::{Rect, Vector2D},
+    point2, size2, vec2,
+};
+use raqote::{DrawTarget, IntPoint, IntRect, Path, PathOp, Point};
+use x11rb::{
+    NONE,
+    connection::{Connection as _, RequestConnection as _},
+    cookie::VoidCookie,
+    errors::ConnectionError,
+    protocol::{
+        render::{
+            self, ChangePictureAux, ConnectionExt as _, CreatePictureAux, Glyphinfo, Glyphset,
+            PictOp, PictType, Pictformat, Picture, Pointfix, Triangle,
+        },
+        xproto::{self, ConnectionExt as _, ImageFormat, Rectangle},
+    },
+    rust_connection::RustConnection,
+};
+
+use crate::{
+    Error, Rgb,
+    error::err,
+    ui::{GlyphImage, Painter, Text},
+    x11::{X11Window, pixels::PixelFormat},
+};
+
+/// Number of line segments each curve is approximated with.
+const CURVE_SEGMENTS: u16 = 8;
+
+/// Server-side drawing resources that are kept for as long as the window exists.
+pub(crate) struct Renderer {
+    window: xproto::Window,
+    /// The picture that draws into the window.
+    picture: Picture,
+    argb32: Pictformat,
+    /// The format of 32-bit images sent to the server.
+    argb32_pixels: PixelFormat,
+    a8: Pictformat,
+    glyphset: Glyphset,
+    /// IDs of the glyphs uploaded to `glyphset`, by the hash of their coverage mask.
+    glyphs: HashMap<u64, u32>,
+    /// Uploaded images, by the hash of their contents.
+    images: HashMap<u64, Picture>,
+    /// Pictures of a solid color, used as the source when drawing shapes and text.
+    fills: HashMap<Rgb, Picture>,
+}
+
+impl Renderer {
+    /// Prepares drawing into `win`.
+    ///
+    /// Fails if the X server doesn't support version 0.10 of the extension.
+    pub(crate) fn new(win: &X11Window) -> Result<Self, Error> {
+        let conn = &win.conn;
+        let version = conn
+            .render_query_version(0, 10)
+            .map_err(err)?
+            .reply()
+            .map_err(err)?;
+        if (version.major_version, version.minor_version) < (0, 10) {
+            return Err(Error::new(format!(
+                "X server only supports XRender {}.{}",
+                version.major_version, version.minor_version,
+            )));
+        }
+
+        let formats = conn
+            .render_query_pict_formats()
+            .map_err(err)?
+            .reply()
+            .map_err(err)?;
+        let find = |depth: u8, shifts: [u16; 4], color: bool| {
+            formats
+                .formats
+                .iter()
+                .find(|f| {
+                    let d = &f.direct;
+                    let rgb_mask = if color { 0xff } else { 0 };
+                    f.type_ == PictType::DIRECT
+                        && f.depth == depth
+                        && [d.red_mask, d.green_mask, d.blue_mask] == [rgb_mask; 3]
+                        && d.alpha_mask == 0xff
+                        && (!color || [d.red_shift, d.green_shift, d.blue_shift] == shifts[..3])
+                        && d.alpha_shift == shifts[3]
+                })
+                .map(|f| f.id)
+                .ok_or_else(|| Error::new(format!("X server has no {depth}-bit picture format")))
+        };
+        let argb32 = find(32, [16, 8, 0, 24], true)?;
+        let (bits_per_pixel, scanline_pad, msb_first) = conn.image_layout(32)?;
+        let argb32_pixels = PixelFormat::direct(
+            bits_per_pixel,
+            scanline_pad,
+            msb_first,
+            [0xff000000, 0xff0000, 0xff00, 0xff],
+        )
+        .ok_or_else(|| Error::new("X server has no usable 32-bit image format"))?;
+        let a8 = find(8, [0, 0, 0, 0], false)?;
+        let window_format = formats
+            .screens
+            .iter()
+            .flat_map(|screen| &screen.depths)
+            .flat_map(|depth| &depth.visuals)
+            .find(|vis| vis.visual == win.visual.id)
+            .ok_or_else(|| Error::new("X server has no picture format for the window visual"))?
+            .format;
+
+        let picture = conn.generate_id().map_err(err)?;
+        conn.render_create_picture(picture, win.window, window_format, &CreatePictureAux::new())
+            .map_err(err)?
+            .check()
+            .map_err(err)?;
+        let glyphset = conn.generate_id().map_err(err)?;
+        conn.render_create_glyph_set(glyphset, a8)
+            .map_err(err)?
+            .check()
+            .map_err(err)?;
+
+        Ok(Self {
+            window: win.window,
+            picture,
+            argb32,
+            argb32_pixels,
+            a8,
+            glyphset,
+            glyphs: HashMap::new(),
+            images: HashMap::new(),
+            fills: HashMap::new(),
+        })
+    }
+
+    /// Starts drawing a frame into `win`, which must be the window the renderer was created for.
+    pub(crate) fn frame<'a>(&'a mut self, win: &'a X11Window) -> Frame<'a> {
+        Frame {
+            conn: &win.conn,
+            renderer: self,
+            clip: Vec::new(),
+            cookies: Vec::new(),
+            error: None,
+        }
+    }
+}
+
+/// Draws a frame with a [`Renderer`].
+///
+/// Requests are sent without waiting for the server to process them, so errors are only reported
+/// by [`Frame::finish`].
+pub(crate) struct Frame<'a> {
+    conn: &'a RustConnection,
+    renderer: &'a mut Renderer,
+    /// Stack of clip rectangles, innermost last.
+    clip: Vec<IntRect>,
+    cookies: Vec<VoidCookie<'a, RustConnection>>,
+    /// The first error that occurred while sending a request.
+    error: Option<Error>,
+}
+
+impl<'a> Frame<'a> {
+    /// Waits for the X server to process the frame, and returns the first error that occurred.
+    pub(crate) fn finish(self) -> Result<(), Error> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        for cookie in self.cookies {
+            cookie.check().map_err(err)?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, request: Result<VoidCookie<'a, RustConnection>, ConnectionError>) {
+        match request {
+            Ok(cookie) => self.cookies.push(cookie),
+            Err(e) => {
+                self.error.get_or_insert(err(e));
+            }
+        }
+    }
+
+    fn generate_id(&mut self) -> Option<u32> {
+        match self.conn.generate_id() {
+            Ok(id) => Some(id),
+            Err(e) => {
+                self.error.get_or_insert(err(e));
+                None
+            }
+        }
+    }
+
+    /// Returns a picture filled with `color`.
+    fn fill(&mut self, color: Rgb) -> Option<Picture> {
+        if let Some(&picture) = self.renderer.fills.get(&color) {
+            return Some(picture);
+        }
+        let picture = self.generate_id()?;
+        let conn = self.conn;
+        self.send(conn.render_create_solid_fill(picture, render_color(color)));
+        self.renderer.fills.insert(color, picture);
+        Some(picture)
+    }
+
+    /// Returns a picture containing `image`, uploading it if it wasn't used before.
+    fn image(&mut self, image: &DrawTarget) -> Option<Picture> {
+        let key = hash((image.width(), image.height(), image.get_data()));
+        if let Some(&picture) = self.renderer.images.get(&key) {
+            return Some(picture);
+        }
+
+        let conn = self.conn;
+        let (width, height) = (image.width() as u16, image.height() as u16);
+        let (pixmap, gc, picture) = (
+            self.generate_id()?,
+            self.generate_id()?,
+            self.generate_id()?,
+        );
+        self.send(conn.create_pixmap(32, pixmap, self.renderer.window, width, height));
+        self.send(conn.create_gc(gc, pixmap, &Default::default()));
+
+        // Send the image in strips that fit within the maximum request length.
+        let row_len = self.renderer.argb32_pixels.row_len(width.into());
+        let max_rows = cmp::max((conn.maximum_request_bytes() - 24) / row_len, 1);
+        let strips = image.get_data().chunks(max_rows * usize::from(width));
+        for (i, strip) in strips.enumerate() {
+            let data = self.renderer.argb32_pixels.convert(strip, width.into());
+            self.send(conn.put_image(
+                ImageFormat::Z_PIXMAP,
+                pixmap,
+                gc,
+                width,
+                (strip.len() / usize::from(width)) as u16,
+                0,
+                (i * max_rows) as i16,
+                0,
+                32,
+                &data,
+            ));
+        }
+
+        // The picture keeps the pixmap alive.
+        self.send(conn.render_create_picture(
+            picture,
+            pixmap,
+            self.renderer.argb32,
+            &CreatePictureAux::new(),
+        ));
+        self.send(conn.free_gc(gc));
+        self.send(conn.free_pixmap(pixmap));
+        self.renderer.images.insert(key, picture);
+        Some(picture)
+    }
+
+    /// Returns the ID of `glyph` in the glyph set, uploading it if it wasn't used before.
+    fn glyph(&mut self, glyph: &GlyphImage) -> u32 {
+        let key = hash((glyph.width, glyph.height, &glyph.coverage));
+        if let Some(&id) = self.renderer.glyphs.get(&key) {
+            return id;
+        }
+
+        // Rows of 8-bit images are padded to 32 bits.
+        let width = usize::from(glyph.width);
+        let stride = width.next_multiple_of(4);
+        let mut data = vec![0; stride * usize::from(glyph.height)];
+        for (row, src) in data.chunks_mut(stride).zip(glyph.coverage.chunks(width)) {
+            row[..width].copy_from_slice(src);
+        }
+
+        // The glyph is drawn with its top left corner at the current position, which then stays
+        // unchanged (the position of every glyph is given explicitly).
+        let info = Glyphinfo {
+            width: glyph.width,
+            height: glyph.height,
+            x: 0,
+            y: 0,
+            x_off: 0,
+            y_off: 0,
+        };
+        let id = self.renderer.glyphs.len() as u32 + 1;
+        let conn = self.conn;
+        self.send(conn.render_add_glyphs(self.renderer.glyphset, &[id], &[info], &data));
+        self.renderer.glyphs.insert(key, id);
+        id
+    }
+
+    fn draw_triangles(&mut self, triangles: &[Triangle], color: Rgb) {
+        if triangles.is_empty() {
+            return;
+        }
+        let Some(src) = self.fill(color) else {
+            return;
+        };
+        // With a mask format, the triangles are combined into a single mask before compositing,
+        // so the antialiased edges between adjacent triangles don't show.
+        let conn = self.conn;
+        self.send(conn.render_triangles(
+            PictOp::OVER,
+            src,
+            self.renderer.picture,
+            self.renderer.a8,
+            0,
+            0,
+            triangles,
+        ));
+    }
+
+    fn set_clip(&mut self) {
+        let conn = self.conn;
+        let picture = self.renderer.picture;
+        match self.clip.last() {
+            Some(rect) => {
+                let rect = rectangle(*rect);
+                self.send(conn.render_set_picture_clip_rectangles(picture, 0, 0, &[rect]));
+            }
+            None => {
+                self.send(
+                    conn.render_change_picture(picture, &ChangePictureAux::new().clipmask(NONE)),
+                );
+            }
+        }
+    }
+}
+
+impl Painter for Frame<'_> {
+    fn fill_rect(&mut self, rect: Rect<f32>, color: Rgb) {
+        let rect = rect.round().to_i32().to_box2d();
+        if rect.is_empty() {
+            return;
+        }
+        let conn = self.conn;
+        self.send(conn.render_fill_rectangles(
+            PictOp::OVER,
+            self.renderer.picture,
+            render_color(color),
+            &[rectangle(rect)],
+        ));
+    }
+
+    fn fill_path(&mut self, path: &Path, color: Rgb) {
+        self.draw_triangles(&fill_triangles(path), color);
+    }
+
+    fn stroke_path(&mut self, path: &Path, color: Rgb, width: f32) {
+        self.draw_triangles(&stroke_triangles(path, width), color);
+    }
+
+    fn draw_image(&mut self, image: &DrawTarget, src: IntRect, pos: IntPoint) {
+        if src.is_empty() {
+            return;
+        }
+        let Some(picture) = self.image(image) else {
+            return;
+        };
+        let conn = self.conn;
+        self.send(conn.render_composite(
+            PictOp::OVER,
+            picture,
+            NONE,
+            self.renderer.picture,
+            src.min.x as i16,
+            src.min.y as i16,
+            0,
+            0,
+            pos.x as i16,
+            pos.y as i16,
+            src.width() as u16,
+            src.height() as u16,
+        ));
+    }
+
+    fn draw_text(&mut self, text: &Text, pos: IntPoint) {
+        let Some(src) = self.fill(text.color) else {
+            return;
+        };
+
+        // Every glyph gets its own element, which moves the current position to the glyph. Glyphs
+        // outside of the clip rectangle are left out, which also keeps the offsets small.
+        let visible = self.clip.last().copied();
+        let mut cmds = Vec::new();
+        let mut current = point2(0, 0);
+        for glyph in &text.glyphs {
+            let rect = IntRect::from_origin_and_size(
+                pos + glyph.pos.to_vector(),
+                size2(i32::from(glyph.width), i32::from(glyph.height)),
+            );
+            if rect.is_empty() || visible.is_some_and(|clip| !clip.intersects(&rect)) {
+                continue;
+            }
+            let id = self.glyph(glyph);
+            let delta = rect.min - current;
+            current = rect.min;
+            cmds.extend([1, 0, 0, 0]);
+            cmds.extend((delta.x as i16).to_ne_bytes());
+            cmds.extend((delta.y as i16).to_ne_bytes());
+            cmds.extend(id.to_ne_bytes());
+        }
+        if cmds.is_empty() {
+            return;
+        }
+
+        let conn = self.conn;
+        self.send(conn.render_composite_glyphs32(
+            PictOp::OVER,
+            src,
+            self.renderer.picture,
+            self.renderer.a8,
+            self.renderer.glyphset,
+            0,
+            0,
+            &cmds,
+        ));
+    }
+
+    fn push_clip(&mut self, rect: IntRect) {
+        let rect = match self.clip.last() {
+            Some(outer) => outer.intersection_unchecked(&rect),
+            None => rect,
+        };
+        self.clip.push(rect);
+        self.set_clip();
+    }
+
+    fn pop_clip(&mut self) {
+        self.clip.pop();
+        self.set_clip();
+    }
+}
+
+/// Splits the area enclosed by `path` into triangles.
+///
+/// Every subpath has to be convex.
+pub(crate) fn fill_triangles(path: &Path) -> Vec<Triangle> {
+    let mut triangles = Vec::new();
+    for polygon in flatten(path) {
+        // Fan out from the first point.
+        let Some((&first, rest)) = polygon.split_first() else {
+            continue;
+        };
+        for pair in rest.windows(2) {
+            triangles.push(triangle(first, pair[0], pair[1]));
+        }
+    }
+    triangles
+}
+
+/// Covers the outline of `path` with triangles, two for each line segment.
+///
+/// The joins between segments are left out, which is only noticeable with sharp corners.
+pub(crate) fn stroke_triangles(path: &Path, width: f32) -> Vec<Triangle> {
+    let mut triangles = Vec::new();
+    for polyline in flatten(path) {
+        for pair in polyline.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let dir = b - a;
+            let len = dir.length();
+            if len == 0.0 {
+                continue;
+            }
+            let normal = vec2(-dir.y, dir.x) * (width / 2.0 / len);
+            triangles.push(triangle(a + normal, b + normal, b - normal));
+            triangles.push(triangle(a + normal, b - normal, a - normal));
+        }
+    }
+    triangles
+}
+
+/// Approximates the subpaths of `path` with polylines.
+///
+/// Closed subpaths end with their first point.
+fn flatten(path: &Path) -> Vec<Vec<Point>> {
+    let mut polylines: Vec<Vec<Point>> = Vec::new();
+    let mut current = Point::zero();
+    for op in &path.ops {
+        if polylines.is_empty() || matches!(op, PathOp::MoveTo(_)) {
+            polylines.push(vec![current]);
+        }
+        let Some(line) = polylines.last_mut() else {
+            continue;
+        };
+        let start = current;
+        match *op {
+            PathOp::MoveTo(pt) => line[0] = pt,
+            PathOp::LineTo(pt) => line.push(pt),
+            PathOp::QuadTo(c, end) => line.extend(curve(|t, u| {
+                start.to_vector() * (u * u)
+                    + c.to_vector() * (2.0 * u * t)
+                    + end.to_vector() * (t * t)
+            })),
+            PathOp::CubicTo(c1, c2, end) => line.extend(curve(|t, u| {
+                start.to_vector() * (u * u * u)
+                    + c1.to_vector() * (3.0 * u * u * t)
+                    + c2.to_vector() * (3.0 * u * t * t)
+                    + end.to_vector() * (t * t * t)
+            })),
+            PathOp::Close => line.push(line[0]),
+        }
+        current = line[line.len() - 1];
+    }
+    polylines
+}
+
+/// Returns `CURVE_SEGMENTS` points along a curve, given as a function of `t` and `1 - t`.
+fn curve(f: impl Fn(f32, f32) -> Vector2D<f32>) -> impl Iterator<Item = Point> {
+    (1..=CURVE_SEGMENTS).map(move |i| {
+        let t = f32::from(i) / f32::from(CURVE_SEGMENTS);
+        f(t, 1.0 - t).to_point()
+    })
+}
+
+fn triangle(p1: Point, p2: Point, p3: Point) -> Triangle {
+    let cvt = |pt: Point| Pointfix {
+        x: (pt.x * 65536.0).round() as render::Fixed,
+        y: (pt.y * 65536.0).round() as render::Fixed,
+    };
+    Triangle {
+        p1: cvt(p1),
+        p2: cvt(p2),
+        p3: cvt(p3),
+    }
+}
+
+fn rectangle(rect: IntRect) -> Rectangle {
+    Rectangle {
+        x: rect.min.x as i16,
+        y: rect.min.y as i16,
+        width: rect.width() as u16,
+        height: rect.height() as u16,
+    }
+}
+
+fn render_color(Rgb(r, g, b): Rgb) -> render::Color {
+    // Scale 8-bit channels to 16 bits, so that 0xff becomes 0xffff.
+    render::Color {
+        red: u16::from(r) * 0x101,
+        green: u16::from(g) * 0x101,
+        blue: u16::from(b) * 0x101,
+        alpha: 0xffff,
+    }
+}
+
+fn hash(value: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}